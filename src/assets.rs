@@ -0,0 +1,334 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+use ggez::{audio, graphics, Context};
+use toml;
+use zip;
+
+use super::error::{Error, Result};
+
+pub const FONT: &str = "/DejaVuSerif.ttf";
+pub const ENERGY_CHARGE: &str = "/energy_charge.ogg";
+pub const BOOM: &str = "/boom.ogg";
+pub const SHOT: &str = "/pew.ogg";
+pub const LOSS: &str = "/loss.ogg";
+
+const MANIFEST_NAME: &str = "pack.toml";
+const FONT_DPI: f32 = 96.0;
+
+static EMBEDDED: &[(&str, &[u8])] = &[
+    (FONT, include_bytes!("../resources/DejaVuSerif.ttf")),
+    (
+        ENERGY_CHARGE,
+        include_bytes!("../resources/energy_charge.ogg"),
+    ),
+    (BOOM, include_bytes!("../resources/boom.ogg")),
+    (SHOT, include_bytes!("../resources/pew.ogg")),
+    (LOSS, include_bytes!("../resources/loss.ogg")),
+];
+
+pub fn embedded(name: &str) -> Option<&'static [u8]> {
+    EMBEDDED
+        .iter()
+        .find(|&&(n, _)| n == name)
+        .map(|&(_, data)| data)
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Manifest {
+    pub name: String,
+    // asset name (e.g. "/boom.ogg") -> file inside the pack
+    pub assets: HashMap<String, String>,
+}
+
+impl Manifest {
+    pub fn from_toml(s: &str) -> Result<Manifest> {
+        let manifest: Manifest =
+            toml::from_str(s).map_err(|e| Error::Config(format!("{}: {}", MANIFEST_NAME, e)))?;
+        if let Some(key) = manifest.assets.keys().find(|k| !k.starts_with('/')) {
+            return Err(Error::Config(format!(
+                "{}: asset name {:?} must start with '/', like \"{}\"",
+                MANIFEST_NAME, key, BOOM
+            )));
+        }
+        if let Some(file) = manifest.assets.values().find(|f| !inside_pack(f)) {
+            return Err(Error::Config(format!(
+                "{}: file {:?} must be a relative path inside the pack",
+                MANIFEST_NAME, file
+            )));
+        }
+        Ok(manifest)
+    }
+}
+
+// no "..", root or drive, so a pack can't point outside itself
+fn inside_pack(file: &str) -> bool {
+    let path = Path::new(file);
+    path.components().next().is_some()
+        && path.components().all(|c| match c {
+            Component::Normal(_) | Component::CurDir => true,
+            _ => false,
+        })
+}
+
+enum PackSource {
+    Dir(PathBuf),
+    Zip(zip::ZipArchive<fs::File>),
+}
+
+pub struct ResourcePack {
+    path: PathBuf,
+    manifest: Manifest,
+    source: PackSource,
+}
+
+impl ResourcePack {
+    pub fn open(path: &Path) -> Result<ResourcePack> {
+        let pack_err =
+            |msg: String| Error::Config(format!("resource pack {}: {}", path.display(), msg));
+        let mut source = if path.is_dir() {
+            PackSource::Dir(path.to_owned())
+        } else {
+            let file = fs::File::open(path).map_err(|e| pack_err(e.to_string()))?;
+            let archive = zip::ZipArchive::new(file)
+                .map_err(|e| pack_err(format!("not a zip archive ({})", e)))?;
+            PackSource::Zip(archive)
+        };
+        let manifest = match read_entry(&mut source, MANIFEST_NAME) {
+            Ok(data) => String::from_utf8(data)
+                .map_err(|_| pack_err(format!("{} is not valid UTF-8", MANIFEST_NAME)))?,
+            Err(_) => return Err(pack_err(format!("missing {}", MANIFEST_NAME))),
+        };
+        let manifest = Manifest::from_toml(&manifest).map_err(|e| pack_err(e.to_string()))?;
+        let mut missing = vec![];
+        for (name, file) in manifest.assets.iter() {
+            if read_entry(&mut source, file).is_err() {
+                missing.push(format!("{} -> {}", name, file));
+            }
+        }
+        if !missing.is_empty() {
+            missing.sort();
+            return Err(pack_err(format!("missing files: {}", missing.join(", "))));
+        }
+        Ok(ResourcePack {
+            path: path.to_owned(),
+            manifest: manifest,
+            source: source,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.manifest.name
+    }
+
+    pub fn read(&mut self, name: &str) -> Option<Result<Vec<u8>>> {
+        let file = match self.manifest.assets.get(name) {
+            Some(f) => f.clone(),
+            None => return None,
+        };
+        let path = self.path.clone();
+        Some(
+            read_entry(&mut self.source, &file)
+                .map_err(|e| Error::Asset(format!("{} from pack {}: {}", name, path.display(), e))),
+        )
+    }
+}
+
+fn read_entry(source: &mut PackSource, file: &str) -> ::std::result::Result<Vec<u8>, String> {
+    let mut data = vec![];
+    match *source {
+        PackSource::Dir(ref dir) => {
+            let mut f = fs::File::open(dir.join(file)).map_err(|e| e.to_string())?;
+            f.read_to_end(&mut data).map_err(|e| e.to_string())?;
+        }
+        PackSource::Zip(ref mut archive) => {
+            let mut f = archive.by_name(file).map_err(|e| e.to_string())?;
+            f.read_to_end(&mut data).map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(data)
+}
+
+// A pack given by name is looked up under "packs/" in the user config dir.
+pub fn find_pack(ctx: &Context, name: &str) -> Result<PathBuf> {
+    let mut tried = vec![PathBuf::from(name)];
+    let packs_dir = ctx.filesystem.get_user_config_dir().join("packs");
+    tried.push(packs_dir.join(name));
+    tried.push(packs_dir.join(format!("{}.zip", name)));
+    for p in tried.iter() {
+        if p.exists() {
+            return Ok(p.clone());
+        }
+    }
+    let tried: Vec<String> = tried.iter().map(|p| p.display().to_string()).collect();
+    Err(Error::Config(format!(
+        "resource pack {:?} not found, tried {}",
+        name,
+        tried.join(", ")
+    )))
+}
+
+// Looks an asset up in the resource pack, then the mounted resource
+// directories, then the copy built into the binary.
+pub struct Assets {
+    pack: Option<ResourcePack>,
+}
+
+impl Assets {
+    pub fn new(ctx: &Context, pack: Option<&str>) -> Result<Assets> {
+        let pack = match pack {
+            Some(name) => Some(ResourcePack::open(&find_pack(ctx, name)?)?),
+            None => None,
+        };
+        Ok(Assets { pack: pack })
+    }
+
+    pub fn pack_name(&self) -> Option<&str> {
+        self.pack.as_ref().map(|p| p.name())
+    }
+
+    pub fn exists(&self, ctx: &Context, name: &str) -> bool {
+        self.pack
+            .as_ref()
+            .map_or(false, |p| p.manifest.assets.contains_key(name))
+            || ctx.filesystem.is_file(name)
+            || embedded(name).is_some()
+    }
+
+    pub fn read(&mut self, ctx: &mut Context, name: &str) -> Result<Vec<u8>> {
+        if let Some(ref mut pack) = self.pack {
+            if let Some(data) = pack.read(name) {
+                return data;
+            }
+        }
+        if ctx.filesystem.is_file(name) {
+            let mut data = vec![];
+            let mut f = ctx
+                .filesystem
+                .open(name)
+                .map_err(|e| Error::asset(name, e))?;
+            f.read_to_end(&mut data)?;
+            return Ok(data);
+        }
+        embedded(name)
+            .map(|d| d.to_vec())
+            .ok_or_else(|| Error::Asset(format!("{}: not found in resources", name)))
+    }
+
+    pub fn read_string(&mut self, ctx: &mut Context, name: &str) -> Result<String> {
+        let data = self.read(ctx, name)?;
+        String::from_utf8(data).map_err(|_| Error::Asset(format!("{}: not valid UTF-8", name)))
+    }
+
+    pub fn font(&mut self, ctx: &mut Context, points: u32) -> Result<graphics::Font> {
+        self.font_scaled(ctx, points, 1.0)
+    }
+
+    // the font rasterised `scale` times denser than usual, for drawing text
+    // scaled back down on HiDPI or enlarged windows
+    pub fn font_scaled(
+        &mut self,
+        ctx: &mut Context,
+        points: u32,
+        scale: f32,
+    ) -> Result<graphics::Font> {
+        let data = self.read(ctx, FONT)?;
+        let dpi = FONT_DPI * scale.max(0.1);
+        graphics::Font::from_bytes(FONT, &data, points, (dpi, dpi))
+            .map_err(|e| Error::asset(FONT, e))
+    }
+
+    pub fn sound(&mut self, ctx: &mut Context, name: &str) -> Result<audio::Source> {
+        let data = audio::SoundData::from_bytes(&self.read(ctx, name)?);
+        audio::Source::from_data(ctx, data).map_err(|e| Error::asset(name, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::io::Write;
+    use std::process;
+
+    fn pack_dir(name: &str, manifest: &str, files: &[&str]) -> PathBuf {
+        let dir = env::temp_dir().join(format!("fallingball-pack-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::File::create(dir.join(MANIFEST_NAME))
+            .unwrap()
+            .write_all(manifest.as_bytes())
+            .unwrap();
+        for f in files {
+            fs::File::create(dir.join(f))
+                .unwrap()
+                .write_all(b"data")
+                .unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn embeds_default_assets() {
+        for name in &[FONT, ENERGY_CHARGE, BOOM, SHOT, LOSS] {
+            assert!(
+                embedded(name).map_or(false, |d| !d.is_empty()),
+                "{} is not embedded",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn pack_overrides_listed_assets() {
+        let dir = pack_dir(
+            "ok",
+            "name = \"retro\"\n[assets]\n\"/boom.ogg\" = \"boom2.ogg\"\n",
+            &["boom2.ogg"],
+        );
+        let mut pack = ResourcePack::open(&dir).unwrap();
+        assert_eq!(pack.name(), "retro");
+        assert_eq!(pack.read(BOOM).unwrap().unwrap(), b"data".to_vec());
+        assert!(pack.read(LOSS).is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn pack_reports_missing_files() {
+        let dir = pack_dir("missing", "[assets]\n\"/boom.ogg\" = \"nope.ogg\"\n", &[]);
+        let err = ResourcePack::open(&dir).err().unwrap().to_string();
+        assert!(
+            err.contains("missing files: /boom.ogg -> nope.ogg"),
+            "{}",
+            err
+        );
+        fs::remove_dir_all(&dir).unwrap();
+
+        let dir = pack_dir(
+            "badkey",
+            "[assets]\n\"boom.ogg\" = \"boom.ogg\"\n",
+            &["boom.ogg"],
+        );
+        let err = ResourcePack::open(&dir).err().unwrap().to_string();
+        assert!(err.contains("must start with '/'"), "{}", err);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn pack_stays_inside_itself() {
+        for file in &[
+            "../secret.ogg",
+            "sounds/../../secret.ogg",
+            "/etc/passwd",
+            "",
+        ] {
+            let manifest = format!("[assets]\n\"/boom.ogg\" = {:?}\n", file);
+            let err = Manifest::from_toml(&manifest).err().unwrap().to_string();
+            assert!(err.contains("relative path inside the pack"), "{}", err);
+        }
+        assert!(Manifest::from_toml("[assets]\n\"/boom.ogg\" = \"./sounds/boom.ogg\"\n").is_ok());
+    }
+}
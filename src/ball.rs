@@ -1,96 +1,146 @@
+use std::collections::VecDeque;
+
 use ggez::graphics::{self, DrawMode, Point2};
 use ggez::{Context, GameResult};
 
 const GRAVITY: f32 = -1.5f32;
-const BALL_HIGHT_MAX : f32 = 1f32;
+const BALL_HIGHT_MAX: f32 = 1f32;
 const BALL_HIGHT_INIT: f32 = 0.5f32;
-pub const BALL_VELOCITY_MAX : f32 = 1f32;
+pub const BALL_VELOCITY_MAX: f32 = 1f32;
+const TRAIL_LEN: usize = 10;
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Ball {
     radius: f32,
-    pos: (Point2,f32),
-    velocity:(f32,f32,f32),
-    alive:bool,
+    pos: (Point2, f32),
+    velocity: (f32, f32, f32),
+    alive: bool,
+    color: graphics::Color,
+    // recent (position, height), oldest first
+    trail: VecDeque<(Point2, f32)>,
 }
 
 impl Ball {
-    pub fn get_vel(flying_time: f32)->f32{
-        0.5*flying_time + 0.5/GRAVITY/flying_time
+    pub fn get_vel(flying_time: f32) -> f32 {
+        0.5 * flying_time + 0.5 / GRAVITY / flying_time
+    }
+
+    pub fn get_vel_alpha(persent: f32, t_max: f32) -> f32 {
+        let k = persent * t_max;
+        (-GRAVITY * k * k / (k + k + 1f32)).sqrt()
     }
 
-    pub fn get_vel_alpha(persent:f32,t_max:f32)->f32{
-        let k = persent*t_max;
-        (-GRAVITY*k*k/(k+k+1f32)).sqrt()
+    // where a ball launched from `pos` with `velocity` comes down
+    pub fn landing_point(pos: (f32, f32), velocity: (f32, f32, f32)) -> (f32, f32) {
+        let vz = velocity.2;
+        let t = (vz + (vz * vz - 2.0 * GRAVITY * BALL_HIGHT_INIT).sqrt()) / -GRAVITY;
+        (pos.0 + velocity.0 * t, pos.1 + velocity.1 * t)
+    }
+
+    // the power bar charge that sends a ball `range` along the ground, None
+    // when even a full charge falls short
+    pub fn charge_for_range(range: f32, max_vel: f32, t_max: f32) -> Option<f32> {
+        let reach = |charge: f32| {
+            let p = Ball::get_vel_alpha(charge, t_max);
+            Ball::landing_point((0.0, 0.0), (max_vel * p, 0.0, p)).0
+        };
+        if range > reach(1.0) {
+            return None;
+        }
+        let (mut lo, mut hi) = (0f32, 1f32);
+        for _ in 0..32 {
+            let mid = (lo + hi) / 2.0;
+            if reach(mid) < range {
+                lo = mid
+            } else {
+                hi = mid
+            }
+        }
+        Some((lo + hi) / 2.0)
     }
 
-    pub fn new(radius:f32,pos:(f32,f32),velocity:(f32,f32,f32)) ->Ball{
-        Ball{
-            radius:radius,
-            pos:(Point2::new(pos.0,pos.1),BALL_HIGHT_INIT),
-            velocity:velocity,
-            alive:true,
+    pub fn new(radius: f32, pos: (f32, f32), velocity: (f32, f32, f32)) -> Ball {
+        Ball {
+            radius: radius,
+            pos: (Point2::new(pos.0, pos.1), BALL_HIGHT_INIT),
+            velocity: velocity,
+            alive: true,
+            color: graphics::Color::new(0f32, 0f32, 0f32, 1f32),
+            trail: VecDeque::with_capacity(TRAIL_LEN),
         }
     }
-    pub fn set_radius(&mut self,radius:f32){
+
+    pub fn set_color(&mut self, color: graphics::Color) {
+        self.color = color;
+    }
+    pub fn set_radius(&mut self, radius: f32) {
         self.radius = radius;
     }
-    pub fn restore(&mut self,radius:f32,pos:(f32,f32),velocity:(f32,f32,f32)) {
+    pub fn restore(&mut self, radius: f32, pos: (f32, f32), velocity: (f32, f32, f32)) {
         self.alive = true;
         self.radius = radius;
         (self.pos.0).x = pos.0;
         (self.pos.0).y = pos.1;
         self.pos.1 = BALL_HIGHT_INIT;
         self.velocity = velocity;
+        self.trail.clear();
     }
 
-    pub fn get_pos(&self)->Point2{
+    pub fn get_pos(&self) -> Point2 {
         self.pos.0
     }
-    pub fn get_radius(&self) ->f32{
+    pub fn get_radius(&self) -> f32 {
         self.radius
     }
 
-    pub fn is_avtive(&self)->bool{
+    pub fn is_avtive(&self) -> bool {
         self.alive
     }
 
-    pub fn is_on_ground(&self) ->bool{
+    pub fn is_on_ground(&self) -> bool {
         self.pos.1 <= 0f32
     }
 
-    pub fn get_view_radius(hight:f32,radius:f32)->f32{
-        (1.0+1.5*hight/BALL_HIGHT_MAX)*radius
+    pub fn get_view_radius(hight: f32, radius: f32) -> f32 {
+        (1.0 + 1.5 * hight / BALL_HIGHT_MAX) * radius
     }
 
     fn get_draw_radius(&self) -> f32 {
-        (1.0+1.5*self.pos.1/BALL_HIGHT_MAX)*self.radius
+        (1.0 + 1.5 * self.pos.1 / BALL_HIGHT_MAX) * self.radius
     }
 
-    fn get_draw_color(&self) -> graphics::Color{
-        graphics::Color::new(0f32,0f32,0f32,1f32 - 0.1*self.pos.1/BALL_HIGHT_MAX)
+    fn get_draw_color(&self) -> graphics::Color {
+        graphics::Color::new(
+            self.color.r,
+            self.color.g,
+            self.color.b,
+            self.color.a * (1f32 - 0.1 * self.pos.1 / BALL_HIGHT_MAX),
+        )
     }
 
     pub fn disable(&mut self) {
         self.alive = false;
     }
 
-    pub fn set_direction(&mut self,direction: f32){
+    pub fn set_direction(&mut self, direction: f32) {
         let vel = ((self.velocity.0).powi(2) + (self.velocity.1).powi(2)).sqrt();
-        self.velocity.0 = vel*direction.sin();
-        self.velocity.1 = vel*direction.cos();
+        self.velocity.0 = vel * direction.sin();
+        self.velocity.1 = vel * direction.cos();
     }
 
-    pub fn set_direction_vec(&mut self,vectory:(f32,f32)){
+    pub fn set_direction_vec(&mut self, vectory: (f32, f32)) {
         let vel = ((self.velocity.0).powi(2) + (self.velocity.1).powi(2)).sqrt();
         let vec_len = ((vectory.0).powi(2) + (vectory.1).powi(2)).sqrt();
         self.velocity.0 = vel * vectory.0 / vec_len;
-        self.velocity.1 = vel* vectory.1 / vec_len;
+        self.velocity.1 = vel * vectory.1 / vec_len;
     }
 
-
-    pub fn update(&mut self,time_delta_persent:f32){
-        if self.alive && self.pos.1 > 0f32{
+    pub fn update(&mut self, time_delta_persent: f32) {
+        if self.alive && self.pos.1 > 0f32 {
+            if self.trail.len() == TRAIL_LEN {
+                self.trail.pop_front();
+            }
+            self.trail.push_back(self.pos);
             (self.pos.0).x += self.velocity.0 * time_delta_persent;
             (self.pos.0).y += self.velocity.1 * time_delta_persent;
             self.velocity.2 += GRAVITY * time_delta_persent;
@@ -98,16 +148,73 @@ impl Ball {
         }
     }
 
-    pub fn draw(&self,ctx:&mut Context) ->GameResult<()>{
-        graphics::set_color(ctx, self.get_draw_color())?;
+    // where the ball will come down, smaller and fainter the higher it is
+    pub fn draw_shadow(&self, ctx: &mut Context, color: graphics::Color) -> GameResult<()> {
+        let h = (self.pos.1 / BALL_HIGHT_MAX).max(0.0).min(1.0);
+        let color = graphics::Color::new(color.r, color.g, color.b, color.a * (1.0 - 0.6 * h));
+        graphics::set_color(ctx, color)?;
+        graphics::circle(
+            ctx,
+            DrawMode::Fill,
+            self.pos.0,
+            self.radius * (1.0 - 0.5 * h),
+            0.2,
+        )?;
+        Ok(())
+    }
+
+    pub fn draw_trail(&self, ctx: &mut Context) -> GameResult<()> {
+        let n = self.trail.len() as f32;
+        for (i, &(pos, h)) in self.trail.iter().enumerate() {
+            let k = (i + 1) as f32 / (n + 1.0);
+            let c = self.color;
+            graphics::set_color(ctx, graphics::Color::new(c.r, c.g, c.b, c.a * 0.4 * k))?;
             graphics::circle(
                 ctx,
                 DrawMode::Fill,
-                self.pos.0,
-                self.get_draw_radius(),
+                pos,
+                Ball::get_view_radius(h, self.radius) * (0.4 + 0.6 * k),
                 0.2,
             )?;
+        }
+        Ok(())
+    }
+
+    pub fn draw(&self, ctx: &mut Context) -> GameResult<()> {
+        graphics::set_color(ctx, self.get_draw_color())?;
+        graphics::circle(ctx, DrawMode::Fill, self.pos.0, self.get_draw_radius(), 0.2)?;
         Ok(())
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-}
\ No newline at end of file
+    #[test]
+    fn landing_point_matches_flight() {
+        let vel = (30.0, -120.0, Ball::get_vel_alpha(0.6, 1.5));
+        let mut b = Ball::new(5.0, (200.0, 600.0), vel);
+        while !b.is_on_ground() {
+            b.update(1.0 / 600.0);
+        }
+        let (x, y) = Ball::landing_point((200.0, 600.0), vel);
+        let p = b.get_pos();
+        assert!(
+            (p.x - x).abs() < 0.5 && (p.y - y).abs() < 1.0,
+            "{:?} vs {:?}",
+            p,
+            (x, y)
+        );
+    }
+
+    #[test]
+    fn charge_for_range_inverts_the_shot() {
+        let max_vel = 300.0;
+        let p = Ball::get_vel_alpha(0.4, 1.5);
+        let (range, _) = Ball::landing_point((0.0, 0.0), (max_vel * p, 0.0, p));
+        let charge = Ball::charge_for_range(range, max_vel, 1.5).unwrap();
+        assert!((charge - 0.4).abs() < 1e-4);
+        assert_eq!(Ball::charge_for_range(1e6, max_vel, 1.5), None);
+    }
+}
@@ -1,7 +1,12 @@
-use ggez::graphics::{self, DrawMode};
+use std::f32::consts::PI;
+
+use ggez::graphics::{self, DrawMode, Point2, Rect};
 use ggez::{Context, GameResult};
 
+use super::particle::ColorRamp;
+use super::text::TextCache;
 use super::timer::Timer;
+use super::tween::{Easing, Tween};
 
 #[repr(u8)]
 #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
@@ -10,175 +15,579 @@ pub enum BarDirection {
     Horizontal = 1,
 }
 
+// Fill grows from the start (left or bottom) for values in 0..1; Centered
+// grows out of the middle either way for values in -1..1.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum BarMode {
+    Fill,
+    Centered,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BarLabel {
+    None,
+    Text(String),
+    // the value as a percentage
+    Percent,
+}
+
+// gap between segments as a fraction of one segment
+const SEGMENT_GAP: f32 = 0.15;
+
 #[derive(Debug)]
 pub struct Bar {
-    rim_rect:graphics::Rect,
-    value_rect:graphics::Rect,
-    value:f32,
+    rim_rect: graphics::Rect,
+    value: f32,
+    // what is drawn, trailing `value` when smoothing is on
+    shown: Tween,
     color: graphics::Color,
-    direction : BarDirection,
+    rim_color: graphics::Color,
+    rim_width: f32,
+    direction: BarDirection,
+    mode: BarMode,
+    // 0 or 1 draws one solid fill
+    segments: usize,
+    // colour by value instead of `color`; sampled with |value|
+    gradient: Option<ColorRamp>,
+    thresholds: Vec<f32>,
+    tick_color: graphics::Color,
+    label: BarLabel,
+    label_color: graphics::Color,
 }
 
 impl Bar {
     pub fn new(x: f32, y: f32, length: f32, width: f32) -> Bar {
-        Bar{
-            rim_rect:graphics::Rect::new(x,y,length,width),
-            value_rect:graphics::Rect::new(x+1f32,y+1f32,length-2f32,width-2f32),
-            value:1.0,
-            color:graphics::Color::new(0.0,1.0,0.0,1.0),
+        Bar {
+            rim_rect: graphics::Rect::new(x, y, length, width),
+            value: 1.0,
+            shown: Tween::fixed(1.0),
+            color: graphics::Color::new(0.0, 1.0, 0.0, 1.0),
+            rim_color: graphics::Color::new(0.74218, 0.74218, 0.74218, 1.0),
+            rim_width: 1.0,
             direction: BarDirection::Horizontal,
+            mode: BarMode::Fill,
+            segments: 0,
+            gradient: None,
+            thresholds: vec![],
+            tick_color: graphics::Color::new(0.0, 0.0, 0.0, 0.6),
+            label: BarLabel::None,
+            label_color: graphics::Color::new(0.0, 0.0, 0.0, 1.0),
         }
     }
 
-    fn update_graphic(&mut self){
-        self.value_rect.x = self.rim_rect.x + 1.0;
+    // the green, yellow, red scale used for "how much is left"
+    pub fn traffic_gradient() -> ColorRamp {
+        ColorRamp::new(vec![
+            (0.0, graphics::Color::from_rgb(255, 59, 48)),
+            (0.5, graphics::Color::from_rgb(255, 204, 0)),
+            (1.0, graphics::Color::from_rgb(52, 199, 89)),
+        ])
+    }
+
+    fn inner(&self) -> Rect {
+        // thick rims on thin bars would swallow the value entirely
+        let rim = self
+            .rim_width
+            .min(self.rim_rect.w.min(self.rim_rect.h) / 4.0);
+        let r = self.rim_rect;
+        Rect::new(r.x + rim, r.y + rim, r.w - 2.0 * rim, r.h - 2.0 * rim)
+    }
+
+    // the part of the inside between `from` and `to`, both 0..1 along the
+    // bar starting from the left or the bottom
+    fn span(&self, from: f32, to: f32) -> Rect {
+        let r = self.inner();
+        let (a, b) = (from.min(to).max(0.0), from.max(to).min(1.0));
         match self.direction {
-            BarDirection::Horizontal => {
-                self.value_rect.y = self.rim_rect.y + 1.0;
-                self.value_rect.w = (self.rim_rect.w-1.0)*self.value;
-                self.value_rect.h = self.rim_rect.h - 2.0;
-                },
-            BarDirection::Vertical => {
-                self.value_rect.y = self.rim_rect.y+1.0+(self.rim_rect.h-2.0)*(1.0 - self.value);
-                self.value_rect.w = self.rim_rect.w - 2.0;
-                self.value_rect.h = (self.rim_rect.h-2.0)*self.value;
-            },
+            BarDirection::Horizontal => Rect::new(r.x + r.w * a, r.y, r.w * (b - a), r.h),
+            BarDirection::Vertical => Rect::new(r.x, r.y + r.h * (1.0 - b), r.w, r.h * (b - a)),
+        }
+    }
+
+    // where a value sits along the bar, 0..1
+    fn position_of(&self, value: f32) -> f32 {
+        match self.mode {
+            BarMode::Fill => value,
+            BarMode::Centered => 0.5 + value / 2.0,
+        }
+    }
+
+    fn range(&self) -> (f32, f32) {
+        match self.mode {
+            BarMode::Fill => (0.0, 1.0),
+            BarMode::Centered => (-1.0, 1.0),
         }
     }
 
-    pub fn set_size(&mut self,length: f32, width: f32){
-        if self.direction == BarDirection::Horizontal{
+    // start and end of the filled part along the bar
+    pub fn fill_span(&self) -> (f32, f32) {
+        let (min, max) = self.range();
+        let value = self.shown.value().max(min).min(max);
+        let (a, b) = (self.position_of(0.0), self.position_of(value));
+        (a.min(b), a.max(b))
+    }
+
+    pub fn fill_color(&self) -> graphics::Color {
+        match self.gradient {
+            Some(ref ramp) => ramp.sample(self.shown.value().abs().min(1.0)),
+            None => self.color,
+        }
+    }
+
+    // rectangles to fill in the bar colour
+    pub fn fill_rects(&self) -> Vec<Rect> {
+        let (a, b) = self.fill_span();
+        if b <= a {
+            return vec![];
+        }
+        if self.segments <= 1 {
+            return vec![self.span(a, b)];
+        }
+        let n = self.segments as f32;
+        let gap = SEGMENT_GAP / n / 2.0;
+        (0..self.segments)
+            .map(|i| (i as f32 / n, (i + 1) as f32 / n))
+            .filter(|&(s0, s1)| s0 >= a - 1e-4 && s1 <= b + 1e-4)
+            .map(|(s0, s1)| self.span(s0 + gap, s1 - gap))
+            .collect()
+    }
+
+    pub fn label_text(&self) -> Option<String> {
+        match self.label {
+            BarLabel::None => None,
+            BarLabel::Text(ref s) => Some(s.clone()),
+            BarLabel::Percent => Some(format!("{:.0}%", self.value * 100.0)),
+        }
+    }
+
+    pub fn set_size(&mut self, length: f32, width: f32) {
+        if self.direction == BarDirection::Horizontal {
             self.rim_rect.w = length;
             self.rim_rect.h = width;
-        }else{
+        } else {
             self.rim_rect.w = width;
             self.rim_rect.h = length;
         }
-        self.update_graphic();
     }
 
-    pub fn restore(&mut self,x: f32, y: f32, length: f32, width: f32){
+    pub fn set_position(&mut self, x: f32, y: f32) {
+        self.rim_rect.x = x;
+        self.rim_rect.y = y;
+    }
+
+    pub fn get_rect(&self) -> Rect {
+        self.rim_rect
+    }
+
+    pub fn restore(&mut self, x: f32, y: f32, length: f32, width: f32) {
         self.value = 1.0;
+        self.shown.snap(1.0);
         self.rim_rect.x = x;
         self.rim_rect.y = y;
-        self.set_size(length,width);
+        self.set_size(length, width);
     }
 
-    pub fn set_direction(&mut self,direction:BarDirection){
-        if self.direction != direction{
-            let (w,h) = (self.rim_rect.w,self.rim_rect.h);
+    pub fn set_direction(&mut self, direction: BarDirection) {
+        if self.direction != direction {
+            let (w, h) = (self.rim_rect.w, self.rim_rect.h);
             self.rim_rect.h = w;
             self.rim_rect.w = h;
             self.direction = direction;
-            self.update_graphic();
         }
     }
 
-    pub fn set_color(&mut self,color: graphics::Color){
+    // switching mode clamps the value into the new range
+    pub fn set_mode(&mut self, mode: BarMode) {
+        self.mode = mode;
+        let (min, max) = self.range();
+        self.value = self.value.max(min).min(max);
+        self.shown.snap(self.value);
+    }
+
+    pub fn set_segments(&mut self, segments: usize) {
+        self.segments = segments;
+    }
+
+    pub fn set_gradient(&mut self, gradient: Option<ColorRamp>) {
+        self.gradient = gradient;
+    }
+
+    // tick marks at these values, in the same range as the bar's values
+    pub fn set_thresholds(&mut self, thresholds: Vec<f32>) {
+        self.thresholds = thresholds;
+    }
+
+    pub fn set_tick_color(&mut self, color: graphics::Color) {
+        self.tick_color = color;
+    }
+
+    pub fn set_label(&mut self, label: BarLabel) {
+        self.label = label;
+    }
+
+    pub fn set_label_color(&mut self, color: graphics::Color) {
+        self.label_color = color;
+    }
+
+    pub fn set_color(&mut self, color: graphics::Color) {
         self.color = color;
     }
 
-    pub fn set_value(&mut self,value:f32){
-        if value >= 0f32 && value <=1f32{
+    pub fn set_rim_color(&mut self, color: graphics::Color) {
+        self.rim_color = color;
+    }
+
+    pub fn set_rim_width(&mut self, width: f32) {
+        self.rim_width = width.max(0.0);
+    }
+
+    pub fn set_value(&mut self, value: f32) {
+        let (min, max) = self.range();
+        if value >= min && value <= max {
             self.value = value;
-            self.update_graphic();
+            self.shown.retarget(value);
         }
     }
 
-    pub fn get_value(&self) -> f32{
+    // eases the drawn value towards new values over `duration` seconds
+    // instead of jumping; 0 turns it off
+    pub fn set_smoothing(&mut self, duration: f32) {
+        self.shown = Tween::new(self.value, self.value, duration, Easing::EaseOut);
+    }
+
+    pub fn animate(&mut self, dt: f32) {
+        self.shown.update(dt);
+    }
+
+    pub fn get_value(&self) -> f32 {
         self.value
     }
 
-    pub fn draw(&self,ctx:&mut Context) ->GameResult<()>{
-        graphics::set_color(ctx, graphics::Color::new(0.74218,0.74218,0.74218,1.0))?;
-        graphics::rectangle(ctx,DrawMode::Fill,self.rim_rect)?;
-        if self.value > 0f32{
-            graphics::set_color(ctx, self.color)?;
-            graphics::rectangle(ctx,DrawMode::Fill,self.value_rect)?;
+    pub fn draw(&self, ctx: &mut Context) -> GameResult<()> {
+        graphics::set_color(ctx, self.rim_color)?;
+        graphics::rectangle(ctx, DrawMode::Fill, self.rim_rect)?;
+        let rects = self.fill_rects();
+        if !rects.is_empty() {
+            graphics::set_color(ctx, self.fill_color())?;
+            for r in rects.iter() {
+                graphics::rectangle(ctx, DrawMode::Fill, *r)?;
+            }
+        }
+        if !self.thresholds.is_empty() || self.mode == BarMode::Centered {
+            graphics::set_color(ctx, self.tick_color)?;
+            let width = self.rim_width.max(1.0);
+            let mut ticks = self.thresholds.clone();
+            if self.mode == BarMode::Centered {
+                ticks.push(0.0);
+            }
+            for t in ticks.iter() {
+                let p = self.position_of(*t);
+                let r = self.rim_rect;
+                let points = match self.direction {
+                    BarDirection::Horizontal => {
+                        let s = self.span(0.0, p);
+                        let x = s.x + s.w;
+                        [Point2::new(x, r.y), Point2::new(x, r.y + r.h)]
+                    }
+                    BarDirection::Vertical => {
+                        let y = self.span(0.0, p).y;
+                        [Point2::new(r.x, y), Point2::new(r.x + r.w, y)]
+                    }
+                };
+                graphics::line(ctx, &points, width)?;
+            }
         }
         Ok(())
     }
 
+    // draws the bar with its label centred over it
+    pub fn draw_with_label(&self, ctx: &mut Context, text: &mut TextCache) -> GameResult<()> {
+        self.draw(ctx)?;
+        if let Some(s) = self.label_text() {
+            let r = self.rim_rect;
+            graphics::set_color(ctx, self.label_color)?;
+            text.draw_centered(ctx, &s, r.x + r.w / 2.0, r.y + (r.h - text.height()) / 2.0)?;
+        }
+        Ok(())
+    }
+}
+
+// A ring swept clockwise from `start_angle` (radians, 0 pointing right,
+// y down) in proportion to its value.
+#[derive(Debug)]
+pub struct Ring {
+    center: Point2,
+    radius: f32,
+    thickness: f32,
+    start_angle: f32,
+    value: f32,
+    color: graphics::Color,
+    rim_color: graphics::Color,
+}
+
+impl Ring {
+    pub fn new(x: f32, y: f32, radius: f32, thickness: f32) -> Ring {
+        Ring {
+            center: Point2::new(x, y),
+            radius: radius,
+            thickness: thickness,
+            start_angle: -PI / 2.0,
+            value: 1.0,
+            color: graphics::Color::new(0.0, 1.0, 0.0, 1.0),
+            rim_color: graphics::Color::new(0.74218, 0.74218, 0.74218, 1.0),
+        }
+    }
+
+    pub fn set_position(&mut self, x: f32, y: f32) {
+        self.center = Point2::new(x, y);
+    }
+
+    pub fn set_radius(&mut self, radius: f32) {
+        self.radius = radius.max(0.0);
+    }
+
+    pub fn set_thickness(&mut self, thickness: f32) {
+        self.thickness = thickness.max(0.0);
+    }
+
+    pub fn set_start_angle(&mut self, angle: f32) {
+        self.start_angle = angle;
+    }
+
+    pub fn set_color(&mut self, color: graphics::Color) {
+        self.color = color;
+    }
+
+    pub fn set_rim_color(&mut self, color: graphics::Color) {
+        self.rim_color = color;
+    }
+
+    pub fn set_value(&mut self, value: f32) {
+        if value >= 0f32 && value <= 1f32 {
+            self.value = value;
+        }
+    }
+
+    pub fn get_value(&self) -> f32 {
+        self.value
+    }
+
+    // points along the filled part of the ring, about one every 6 degrees
+    pub fn arc_points(&self) -> Vec<Point2> {
+        let sweep = 2.0 * PI * self.value;
+        let steps = ((self.value * 60.0).ceil() as usize).max(1);
+        (0..steps + 1)
+            .map(|i| {
+                let a = self.start_angle + sweep * i as f32 / steps as f32;
+                Point2::new(
+                    self.center.x + self.radius * a.cos(),
+                    self.center.y + self.radius * a.sin(),
+                )
+            })
+            .collect()
+    }
+
+    pub fn draw(&self, ctx: &mut Context) -> GameResult<()> {
+        graphics::set_color(ctx, self.rim_color)?;
+        graphics::circle(
+            ctx,
+            DrawMode::Line(self.thickness),
+            self.center,
+            self.radius,
+            0.2,
+        )?;
+        if self.value > 0f32 {
+            graphics::set_color(ctx, self.color)?;
+            graphics::line(ctx, &self.arc_points(), self.thickness)?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
 pub struct TimerBar {
-    bar : Bar,
+    bar: Bar,
     time_tick: Timer,
     is_increase: bool,
 }
 
-impl TimerBar{
-    pub fn new(duration:f64,x: f32, y: f32, l: f32, w: f32)-> TimerBar {
-        TimerBar{
-            bar: Bar::new(x,y,l,w),
-            time_tick:Timer::new(duration),
+impl TimerBar {
+    pub fn new(duration: f64, x: f32, y: f32, l: f32, w: f32) -> TimerBar {
+        TimerBar {
+            bar: Bar::new(x, y, l, w),
+            time_tick: Timer::new(duration),
             is_increase: false,
         }
     }
 
-    fn update_value(&mut self){
+    fn update_value(&mut self) {
         let value = self.get_value();
-        if self.is_increase{
+        if self.is_increase {
             self.bar.set_value(value);
-        }else {
-            self.bar.set_value(1.0-value);
+        } else {
+            self.bar.set_value(1.0 - value);
         }
     }
 
-    pub fn set_increase(&mut self,is_increase:bool){
+    pub fn set_increase(&mut self, is_increase: bool) {
         self.update_value();
         self.is_increase = is_increase;
     }
 
-    pub fn set_direction(&mut self,direction: BarDirection){
+    pub fn set_direction(&mut self, direction: BarDirection) {
         self.bar.set_direction(direction);
     }
 
-    pub fn set_color(&mut self,color:graphics::Color){
+    pub fn set_color(&mut self, color: graphics::Color) {
         self.bar.set_color(color);
     }
 
-    pub fn get_value(&self)->f32{
+    pub fn set_rim_color(&mut self, color: graphics::Color) {
+        self.bar.set_rim_color(color);
+    }
+
+    pub fn set_rim_width(&mut self, width: f32) {
+        self.bar.set_rim_width(width);
+    }
+
+    pub fn set_smoothing(&mut self, duration: f32) {
+        self.bar.set_smoothing(duration);
+    }
+
+    pub fn set_gradient(&mut self, gradient: Option<ColorRamp>) {
+        self.bar.set_gradient(gradient);
+    }
+
+    pub fn get_rect(&self) -> Rect {
+        self.bar.get_rect()
+    }
+
+    pub fn animate(&mut self, dt: f32) {
+        self.bar.animate(dt);
+    }
+
+    pub fn set_position(&mut self, x: f32, y: f32) {
+        self.bar.set_position(x, y);
+    }
+
+    pub fn set_size(&mut self, length: f32, width: f32) {
+        self.bar.set_size(length, width);
+    }
+
+    pub fn get_value(&self) -> f32 {
         self.time_tick.get_value()
     }
 
-    pub fn get_event(&mut self) ->bool{
+    pub fn get_event(&mut self) -> bool {
         self.time_tick.get_event()
     }
 
-    pub fn pause(&mut self){
+    pub fn pause(&mut self) {
         self.time_tick.pause();
     }
 
-    pub fn is_paused(&self) ->bool{
+    pub fn is_paused(&self) -> bool {
         self.time_tick.is_paused()
     }
 
-
-    pub fn is_stopped(&self) ->bool{
+    pub fn is_stopped(&self) -> bool {
         self.time_tick.is_stopped()
     }
 
-    pub fn start(&mut self,ctx:&Context){
+    pub fn delay(&mut self, seconds: f64) {
+        self.time_tick.delay(seconds);
+    }
+
+    pub fn start(&mut self, ctx: &Context) {
         self.time_tick.start(ctx);
         self.update_value();
     }
 
-    pub fn restore(&mut self,duration:f64,x: f32, y: f32, w: f32, h: f32){
-        self.bar.restore(x,y,w,h);
+    pub fn set_value(&mut self, value: f32) {
+        self.time_tick.set_value(value);
+        self.update_value();
+    }
+
+    pub fn restore(&mut self, duration: f64, x: f32, y: f32, w: f32, h: f32) {
+        self.bar.restore(x, y, w, h);
         self.time_tick.restore(duration);
     }
 
-    pub fn update(&mut self,ctx: &Context) {
-        if self.time_tick.on_start(){
+    pub fn update(&mut self, ctx: &Context) {
+        if self.time_tick.on_start() {
             self.time_tick.update(ctx);
             self.update_value();
         }
     }
 
-    pub fn draw(&self,ctx:&mut Context) ->GameResult<()> {
+    pub fn draw(&self, ctx: &mut Context) -> GameResult<()> {
         self.bar.draw(ctx)?;
         Ok(())
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn segments_light_up_in_steps() {
+        let mut bar = Bar::new(0.0, 0.0, 102.0, 10.0);
+        bar.set_segments(5);
+        bar.set_value(0.5);
+        assert_eq!(bar.fill_rects().len(), 2);
+        bar.set_value(1.0);
+        assert_eq!(bar.fill_rects().len(), 5);
+        bar.set_value(0.1);
+        assert!(bar.fill_rects().is_empty());
+    }
+
+    #[test]
+    fn vertical_fills_from_the_bottom() {
+        let mut bar = Bar::new(0.0, 0.0, 102.0, 10.0);
+        bar.set_direction(BarDirection::Vertical);
+        bar.set_value(0.25);
+        let r = bar.fill_rects()[0];
+        assert_eq!((r.y, r.h), (76.0, 25.0));
+    }
+
+    #[test]
+    fn centered_mode_grows_both_ways() {
+        let mut bar = Bar::new(0.0, 0.0, 102.0, 10.0);
+        bar.set_mode(BarMode::Centered);
+        bar.set_value(-0.5);
+        assert_eq!(bar.fill_span(), (0.25, 0.5));
+        let r = bar.fill_rects()[0];
+        assert_eq!((r.x, r.w), (26.0, 25.0));
+        bar.set_value(1.0);
+        assert_eq!(bar.fill_span(), (0.5, 1.0));
+        bar.set_value(2.0);
+        assert_eq!(bar.get_value(), 1.0);
+    }
+
+    #[test]
+    fn gradient_and_label() {
+        let mut bar = Bar::new(0.0, 0.0, 100.0, 10.0);
+        bar.set_gradient(Some(Bar::traffic_gradient()));
+        bar.set_value(0.0);
+        assert_eq!(bar.fill_color(), graphics::Color::from_rgb(255, 59, 48));
+        bar.set_value(0.5);
+        assert_eq!(bar.fill_color(), graphics::Color::from_rgb(255, 204, 0));
+        assert_eq!(bar.label_text(), None);
+        bar.set_label(BarLabel::Percent);
+        assert_eq!(bar.label_text(), Some("50%".to_owned()));
+    }
+
+    #[test]
+    fn ring_arc_follows_value() {
+        let mut ring = Ring::new(0.0, 0.0, 10.0, 2.0);
+        ring.set_value(0.25);
+        let points = ring.arc_points();
+        let (first, last) = (points[0], points[points.len() - 1]);
+        assert!((first.x - 0.0).abs() < 1e-4 && (first.y + 10.0).abs() < 1e-4);
+        assert!((last.x - 10.0).abs() < 1e-4 && last.y.abs() < 1e-4);
+        ring.set_start_angle(0.0);
+        ring.set_value(0.5);
+        let last = ring.arc_points().pop().unwrap();
+        assert!((last.x + 10.0).abs() < 1e-4 && last.y.abs() < 1e-3);
+    }
+}
@@ -1,85 +1,258 @@
-use ggez::graphics::{self, DrawMode,Point2};
+use ggez::graphics::{self, DrawMode, Point2};
 use ggez::{Context, GameResult};
 
 use super::bar;
+use super::particle::ColorRamp;
+use super::rules;
 use super::timer::Timer;
 
+const URGENT: f32 = 0.3;
+const WARNING: f32 = 0.5;
+
+// how a block shows the time it has left
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimerStyle {
+    Bar,
+    Ring,
+}
+
+impl Default for TimerStyle {
+    fn default() -> TimerStyle {
+        TimerStyle::Bar
+    }
+}
+
+impl TimerStyle {
+    pub fn next(self) -> TimerStyle {
+        match self {
+            TimerStyle::Bar => TimerStyle::Ring,
+            TimerStyle::Ring => TimerStyle::Bar,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct BlockStyle {
+    pub color: graphics::Color,
+    pub timer_color: graphics::Color,
+    pub urgent_color: graphics::Color,
+    pub rim_color: graphics::Color,
+    pub pattern_color: graphics::Color,
+    pub rim_width: f32,
+    pub timer: TimerStyle,
+    // stripes over the block as its time runs out, for players who can't
+    // rely on the bar colour
+    pub urgency_cues: bool,
+}
+
+impl Default for BlockStyle {
+    fn default() -> BlockStyle {
+        BlockStyle {
+            color: graphics::Color::from_rgb(54, 100, 139),
+            timer_color: graphics::Color::new(0.0, 1.0, 0.0, 1.0),
+            urgent_color: graphics::Color::new(0.0, 1.0, 0.0, 1.0),
+            rim_color: graphics::Color::new(0.74218, 0.74218, 0.74218, 1.0),
+            pattern_color: graphics::Color::new(1.0, 1.0, 1.0, 1.0),
+            rim_width: 1.0,
+            timer: TimerStyle::Bar,
+            urgency_cues: false,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Block {
-    pos: (f32,f32),
-    width:f32,
+    pos: (f32, f32),
+    width: f32,
     rect: graphics::Rect,
     time_bar: bar::Bar,
-    timer_tick : Timer,
+    time_ring: bar::Ring,
+    timer_tick: Timer,
+    style: BlockStyle,
+    // drawn size relative to the real one, for spawn animations
+    scale: f32,
 }
 
 impl Block {
-    pub fn new(alive_time:f64,pos:(f32,f32),width:f32)-> Block{
-        let block_delta = width*0.1;
-        Block{
-            pos:pos,
-            width:width,
-            rect:graphics::Rect::new(pos.0+block_delta,pos.1+2.0*block_delta,8.0*block_delta,8.0*block_delta),
-            time_bar: bar::Bar::new(pos.0,pos.1,width,block_delta),
+    pub fn new(alive_time: f64, pos: (f32, f32), width: f32) -> Block {
+        let block_delta = width * 0.1;
+        Block {
+            pos: pos,
+            width: width,
+            rect: graphics::Rect::new(
+                pos.0 + block_delta,
+                pos.1 + 2.0 * block_delta,
+                8.0 * block_delta,
+                8.0 * block_delta,
+            ),
+            time_bar: bar::Bar::new(pos.0, pos.1, width, block_delta),
+            time_ring: bar::Ring::new(
+                pos.0 + 5.0 * block_delta,
+                pos.1 + 6.0 * block_delta,
+                3.5 * block_delta,
+                0.6 * block_delta,
+            ),
             timer_tick: Timer::new(alive_time),
+            style: BlockStyle::default(),
+            scale: 1.0,
         }
     }
 
-    pub fn restore(&mut self,alive_time:f64,pos:(f32,f32),width:f32){
-        let block_delta = width*0.1;
+    pub fn restore(&mut self, alive_time: f64, pos: (f32, f32), width: f32) {
+        self.timer_tick.restore(alive_time);
+        self.set_geometry(pos, width);
+        self.time_bar.set_value(1.0);
+        self.update_graphic();
+    }
+
+    // moves and resizes the block without touching its timer
+    pub fn set_geometry(&mut self, pos: (f32, f32), width: f32) {
+        let block_delta = width * 0.1;
         self.pos = pos;
-        self.width =width;
+        self.width = width;
         self.rect.x = pos.0 + block_delta;
-        self.rect.y = pos.1 + 2.0*block_delta;
-        self.rect.h = 8.0*block_delta;
-        self.rect.w = 8.0*block_delta;
-        self.timer_tick.restore(alive_time);
-        self.time_bar.restore(pos.0,pos.1,width,block_delta);
+        self.rect.y = pos.1 + 2.0 * block_delta;
+        self.rect.h = 8.0 * block_delta;
+        self.rect.w = 8.0 * block_delta;
+        self.time_bar.set_position(pos.0, pos.1);
+        self.time_bar.set_size(width, block_delta);
+        self.place_ring();
+    }
+
+    // centred on the body and inside its edge, so it never reaches the next
+    // cell down
+    fn place_ring(&mut self) {
+        let block_delta = self.width * 0.1;
+        let thickness = (0.6 * block_delta).max(self.style.rim_width);
+        self.time_ring.set_position(
+            self.rect.x + self.rect.w / 2.0,
+            self.rect.y + self.rect.h / 2.0,
+        );
+        self.time_ring
+            .set_radius(3.8 * block_delta - thickness / 2.0);
+        self.time_ring.set_thickness(thickness);
+    }
+
+    pub fn set_style(&mut self, style: BlockStyle) {
+        self.style = style;
+        self.time_bar.set_rim_color(style.rim_color);
+        self.time_bar.set_rim_width(style.rim_width);
+        self.time_ring.set_rim_color(style.rim_color);
+        // holds the urgent colour below URGENT and blends up to the normal one
+        self.time_bar.set_gradient(Some(ColorRamp::new(vec![
+            (0.0, style.urgent_color),
+            (URGENT, style.urgent_color),
+            (1.0, style.timer_color),
+        ])));
+        self.place_ring();
         self.update_graphic();
     }
 
-    pub fn is_hit_cricle(&self,point: Point2,radius:f32) -> bool {
-        let pos = (self.pos.0 + self.width/2.0,self.pos.1+self.width * 0.6);
-        (point.x-pos.0)*(point.x-pos.0) + (point.y - pos.1)*(point.y-pos.1) <= (0.48*self.width+radius)*(0.48*self.width+radius)
+    pub fn set_scale(&mut self, scale: f32) {
+        self.scale = scale.max(0.0);
+    }
+
+    fn draw_rect(&self) -> graphics::Rect {
+        let r = self.rect;
+        let (w, h) = (r.w * self.scale, r.h * self.scale);
+        graphics::Rect::new(r.x + (r.w - w) / 2.0, r.y + (r.h - h) / 2.0, w, h)
+    }
+
+    fn get_left(&self) -> f32 {
+        1.0 - self.timer_tick.get_value()
+    }
+
+    pub fn is_hit_cricle(&self, point: Point2, radius: f32) -> bool {
+        rules::is_hit(self.pos, self.width, (point.x, point.y), radius)
+    }
+
+    pub fn get_rect(&self) -> graphics::Rect {
+        self.rect
     }
 
-    pub fn is_contains(&self,point: Point2)->bool{
+    pub fn is_contains(&self, point: Point2) -> bool {
         self.rect.contains(point)
     }
 
-    pub fn is_stopped(&self)->bool{
+    pub fn is_stopped(&self) -> bool {
         self.timer_tick.is_stopped()
     }
 
-    pub fn get_event(&mut self)->bool{
+    pub fn get_event(&mut self) -> bool {
         self.timer_tick.get_event()
     }
 
     fn update_graphic(&mut self) {
-        let value = self.timer_tick.get_value();
-        self.time_bar.set_value(1.0 - value);
+        let left = self.get_left();
+        self.time_bar.set_value(left);
+        self.time_ring.set_value(left);
+        self.time_ring.set_color(self.time_bar.fill_color());
     }
 
-    pub fn update(&mut self,ctx:&Context){
-        if self.timer_tick.on_start(){
+    fn draw_urgency(&self, ctx: &mut Context) -> GameResult<()> {
+        let left = self.get_left();
+        if left >= WARNING {
+            return Ok(());
+        }
+        let r = self.draw_rect();
+        let width = r.w * 0.08;
+        graphics::set_color(ctx, self.style.pattern_color)?;
+        graphics::line(
+            ctx,
+            &[Point2::new(r.x, r.y + r.h), Point2::new(r.x + r.w, r.y)],
+            width,
+        )?;
+        if left < URGENT {
+            graphics::line(
+                ctx,
+                &[Point2::new(r.x, r.y), Point2::new(r.x + r.w, r.y + r.h)],
+                width,
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn update(&mut self, ctx: &Context) {
+        if self.timer_tick.on_start() {
             self.timer_tick.update(ctx);
             self.update_graphic();
         }
     }
 
-    pub fn stop(&mut self){
+    // `left` is the share of its time the block has left, for a block
+    // timed by the simulation rather than its own clock
+    pub fn set_left(&mut self, left: f32) {
+        self.timer_tick.set_value(1.0 - left);
+        self.update_graphic();
+    }
+
+    pub fn stop(&mut self) {
         self.timer_tick.stop();
     }
-    pub fn start(&mut self,ctx:&Context){
+
+    pub fn delay(&mut self, seconds: f64) {
+        self.timer_tick.delay(seconds);
+    }
+    pub fn start(&mut self, ctx: &Context) {
         self.timer_tick.start(ctx);
     }
 
-    pub fn draw(&self,ctx:&mut Context) ->GameResult<()>{
-        if !self.timer_tick.is_stopped(){
-            self.time_bar.draw(ctx)?;
-            graphics::set_color(ctx,graphics::Color::from_rgb(54,100,139))?;
-            graphics::rectangle(ctx,DrawMode::Fill,self.rect)?;
+    pub fn draw(&self, ctx: &mut Context) -> GameResult<()> {
+        if !self.timer_tick.is_stopped() {
+            if self.style.timer == TimerStyle::Bar {
+                self.time_bar.draw(ctx)?;
+            }
+            graphics::set_color(ctx, self.style.color)?;
+            graphics::rectangle(ctx, DrawMode::Fill, self.draw_rect())?;
+            // the ring sits inside the body, so it goes on top
+            if self.style.timer == TimerStyle::Ring {
+                self.time_ring.draw(ctx)?;
+            }
+            if self.style.urgency_cues {
+                self.draw_urgency(ctx)?;
+            }
         }
         Ok(())
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,155 @@
+use ggez::graphics::{self, Color, DrawMode, Rect};
+use ggez::{Context, GameResult};
+
+use super::view::View;
+
+const SHAKE_DECAY: f32 = 2.5;
+const SHAKE_MAX: f32 = 1.0;
+const FLASH_TIME: f32 = 0.3;
+const HIT_STOP_MAX: f32 = 0.15;
+
+// Screen feedback layered over the view: shake moves the whole world,
+// hit-stop holds game time for a moment and flash tints the screen.
+// Everything is scaled by `intensity`, so 0 turns it all off.
+#[derive(Copy, Clone, Debug)]
+pub struct Camera {
+    intensity: f32,
+    // amplitude in world units at full trauma
+    amplitude: f32,
+    trauma: f32,
+    time: f32,
+    hit_stop: f32,
+    flash: Option<(Color, f32)>,
+}
+
+impl Camera {
+    pub fn new(amplitude: f32) -> Camera {
+        Camera {
+            intensity: 1.0,
+            amplitude: amplitude,
+            trauma: 0.0,
+            time: 0.0,
+            hit_stop: 0.0,
+            flash: None,
+        }
+    }
+
+    pub fn set_intensity(&mut self, intensity: f32) {
+        self.intensity = intensity.max(0.0);
+        if self.intensity == 0.0 {
+            self.reset();
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.trauma = 0.0;
+        self.hit_stop = 0.0;
+        self.flash = None;
+    }
+
+    pub fn shake(&mut self, amount: f32) {
+        if self.intensity > 0.0 {
+            self.trauma = (self.trauma + amount).min(SHAKE_MAX);
+        }
+    }
+
+    pub fn hit_stop(&mut self, seconds: f32) {
+        if self.intensity > 0.0 {
+            self.hit_stop = self.hit_stop.max(seconds.min(HIT_STOP_MAX));
+        }
+    }
+
+    pub fn flash(&mut self, color: Color) {
+        if self.intensity > 0.0 {
+            self.flash = Some((color, FLASH_TIME));
+        }
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.hit_stop > 0.0
+    }
+
+    // advances the effects by a real time step and returns how much game
+    // time should pass, which is none during hit-stop
+    pub fn update(&mut self, dt: f32) -> f32 {
+        self.time += dt;
+        self.trauma = (self.trauma - SHAKE_DECAY * dt).max(0.0);
+        self.flash = match self.flash {
+            Some((c, t)) if t > dt => Some((c, t - dt)),
+            _ => None,
+        };
+        if self.hit_stop > 0.0 {
+            let stopped = self.hit_stop.min(dt);
+            self.hit_stop -= stopped;
+            dt - stopped
+        } else {
+            dt
+        }
+    }
+
+    // world-space offset of the camera this frame; squared trauma keeps
+    // small knocks subtle
+    pub fn offset(&self) -> (f32, f32) {
+        let k = self.trauma * self.trauma * self.amplitude * self.intensity;
+        if k == 0.0 {
+            return (0.0, 0.0);
+        }
+        let t = self.time * 40.0;
+        (
+            k * (t.sin() + (t * 2.3).sin() * 0.5) / 1.5,
+            k * ((t * 1.7).cos() + (t * 3.1).sin() * 0.5) / 1.5,
+        )
+    }
+
+    pub fn apply(&self, ctx: &mut Context, view: &View) -> GameResult<()> {
+        let (dx, dy) = self.offset();
+        let v = view.visible();
+        graphics::set_screen_coordinates(ctx, Rect::new(v.x + dx, v.y + dy, v.w, v.h))
+    }
+
+    pub fn draw_flash(&self, ctx: &mut Context, view: &View) -> GameResult<()> {
+        if let Some((c, t)) = self.flash {
+            let a = c.a * 0.5 * (t / FLASH_TIME) * self.intensity.min(1.0);
+            graphics::set_color(ctx, Color::new(c.r, c.g, c.b, a))?;
+            graphics::rectangle(ctx, DrawMode::Fill, view.visible())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shake_decays_to_rest() {
+        let mut cam = Camera::new(10.0);
+        cam.shake(0.8);
+        cam.update(0.05);
+        assert_ne!(cam.offset(), (0.0, 0.0));
+        cam.update(1.0);
+        assert_eq!(cam.offset(), (0.0, 0.0));
+    }
+
+    #[test]
+    fn hit_stop_eats_game_time() {
+        let mut cam = Camera::new(10.0);
+        cam.hit_stop(0.05);
+        assert_eq!(cam.update(0.03), 0.0);
+        assert!(cam.is_frozen());
+        assert!((cam.update(0.03) - 0.01).abs() < 1e-6);
+        assert!(!cam.is_frozen());
+    }
+
+    #[test]
+    fn zero_intensity_disables_everything() {
+        let mut cam = Camera::new(10.0);
+        cam.set_intensity(0.0);
+        cam.shake(1.0);
+        cam.hit_stop(0.1);
+        cam.flash(Color::new(1.0, 0.0, 0.0, 1.0));
+        assert_eq!(cam.update(0.016), 0.016);
+        assert_eq!(cam.offset(), (0.0, 0.0));
+        assert!(cam.flash.is_none());
+    }
+}
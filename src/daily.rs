@@ -0,0 +1,173 @@
+use chrono::{Datelike, Local};
+
+pub use chrono::NaiveDate;
+
+use super::error::{Error, Result};
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+pub fn today() -> NaiveDate {
+    Local::now().naive_local().date()
+}
+
+// "2024-03-01", as given to --date
+pub fn parse_date(s: &str) -> Result<NaiveDate> {
+    NaiveDate::parse_from_str(s, DATE_FORMAT)
+        .map_err(|e| Error::Config(format!("bad date {:?}, expected YYYY-MM-DD: {}", s, e)))
+}
+
+pub fn format_date(date: NaiveDate) -> String {
+    date.format(DATE_FORMAT).to_string()
+}
+
+fn splitmix(x: &mut u64) -> u64 {
+    *x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *x;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// RNG seed shared by everyone playing on `date`
+pub fn seed(date: NaiveDate) -> [u32; 4] {
+    let mut x = date.num_days_from_ce() as u64;
+    let (a, b) = (splitmix(&mut x), splitmix(&mut x));
+    // an all-zero seed would stall the generator
+    [(a >> 32) as u32 | 1, a as u32, (b >> 32) as u32, b as u32]
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DailyResult {
+    pub date: String,
+    pub score: usize,
+}
+
+// Every day's scored attempt, oldest first.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DailyHistory {
+    pub results: Vec<DailyResult>,
+}
+
+impl DailyHistory {
+    pub fn result(&self, date: NaiveDate) -> Option<&DailyResult> {
+        let key = format_date(date);
+        self.results.iter().find(|r| r.date == key)
+    }
+
+    // takes the day's one scored attempt; false if it is already used
+    pub fn begin(&mut self, date: NaiveDate) -> bool {
+        if self.result(date).is_some() {
+            return false;
+        }
+        self.results.push(DailyResult {
+            date: format_date(date),
+            score: 0,
+        });
+        true
+    }
+
+    pub fn finish(&mut self, date: NaiveDate, score: usize) {
+        let key = format_date(date);
+        if let Some(r) = self.results.iter_mut().find(|r| r.date == key) {
+            r.score = score;
+        }
+    }
+
+    fn dates(&self) -> Vec<NaiveDate> {
+        let mut dates: Vec<NaiveDate> = self
+            .results
+            .iter()
+            .filter_map(|r| parse_date(&r.date).ok())
+            .collect();
+        dates.sort();
+        dates.dedup();
+        dates
+    }
+
+    // days in a row played up to `today`; a streak survives until the end of
+    // the day after its last game
+    pub fn streak(&self, today: NaiveDate) -> usize {
+        let dates = self.dates();
+        let mut expect = today;
+        let mut n = 0;
+        for &d in dates.iter().rev() {
+            let gap = expect.signed_duration_since(d).num_days();
+            if gap < 0 {
+                continue;
+            }
+            if gap > 1 || (gap == 1 && n > 0) {
+                break;
+            }
+            n += 1;
+            expect = d.pred_opt().unwrap_or(d);
+        }
+        n
+    }
+
+    pub fn best_streak(&self) -> usize {
+        let dates = self.dates();
+        let (mut best, mut run) = (0, 0);
+        for (i, d) in dates.iter().enumerate() {
+            run = if i > 0 && d.signed_duration_since(dates[i - 1]).num_days() == 1 {
+                run + 1
+            } else {
+                1
+            };
+            best = best.max(run);
+        }
+        best
+    }
+
+    // the last `n` results, newest first
+    pub fn recent(&self, n: usize) -> Vec<&DailyResult> {
+        self.results.iter().rev().take(n).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(s: &str) -> NaiveDate {
+        parse_date(s).unwrap()
+    }
+
+    #[test]
+    fn seeds_follow_the_date() {
+        assert_eq!(seed(day("2024-03-01")), seed(day("2024-03-01")));
+        assert_ne!(seed(day("2024-03-01")), seed(day("2024-03-02")));
+        assert!(parse_date("2024-13-01").is_err());
+        assert_eq!(format_date(day("2024-03-01")), "2024-03-01");
+    }
+
+    #[test]
+    fn one_scored_attempt_per_day() {
+        let mut h = DailyHistory::default();
+        assert!(h.begin(day("2024-03-01")));
+        h.finish(day("2024-03-01"), 17);
+        assert!(!h.begin(day("2024-03-01")));
+        assert_eq!(h.result(day("2024-03-01")).unwrap().score, 17);
+    }
+
+    #[test]
+    fn counts_streaks() {
+        let mut h = DailyHistory::default();
+        for d in [
+            "2024-02-27",
+            "2024-02-28",
+            "2024-03-01",
+            "2024-03-02",
+            "2024-03-03",
+        ]
+        .iter()
+        {
+            h.begin(day(d));
+        }
+        assert_eq!(h.best_streak(), 3);
+        assert_eq!(h.streak(day("2024-03-03")), 3);
+        // not played yet today, yesterday's streak still stands
+        assert_eq!(h.streak(day("2024-03-04")), 3);
+        assert_eq!(h.streak(day("2024-03-05")), 0);
+    }
+}
@@ -0,0 +1,95 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::result;
+
+use ggez::GameError;
+
+#[derive(Debug)]
+pub enum Error {
+    Asset(String),
+    Audio(String),
+    Config(String),
+    SaveData(String),
+    Replay(String),
+    Net(String),
+    Game(GameError),
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+fn game_error_message(e: &GameError) -> String {
+    match *e {
+        GameError::FilesystemError(ref s)
+        | GameError::ConfigError(ref s)
+        | GameError::SdlError(ref s)
+        | GameError::ResourceLoadError(ref s)
+        | GameError::RenderError(ref s)
+        | GameError::AudioError(ref s)
+        | GameError::FontError(ref s)
+        | GameError::VideoError(ref s)
+        | GameError::UnknownError(ref s) => s.clone(),
+        GameError::ResourceNotFound(ref s, _) => format!("{} not found", s),
+        GameError::IOError(ref e) => e.to_string(),
+        ref e => format!("{:?}", e),
+    }
+}
+
+impl Error {
+    pub fn asset(path: &str, e: GameError) -> Error {
+        Error::Asset(format!("{}: {}", path, game_error_message(&e)))
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Asset(ref s) => write!(f, "could not load asset {}", s),
+            Error::Audio(ref s) => write!(f, "audio error: {}", s),
+            Error::Config(ref s) => write!(f, "bad config: {}", s),
+            Error::SaveData(ref s) => write!(f, "save data error: {}", s),
+            Error::Replay(ref s) => write!(f, "bad replay: {}", s),
+            Error::Net(ref s) => write!(f, "network error: {}", s),
+            Error::Game(ref e) => write!(f, "{}", game_error_message(e)),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Game(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<GameError> for Error {
+    fn from(e: GameError) -> Error {
+        match e {
+            GameError::ResourceLoadError(s) | GameError::FontError(s) => Error::Asset(s),
+            GameError::ResourceNotFound(s, _) => Error::Asset(format!("{} not found", s)),
+            GameError::AudioError(s) => Error::Audio(s),
+            GameError::ConfigError(s) => Error::Config(s),
+            e => Error::Game(e),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Game(GameError::IOError(e))
+    }
+}
+
+impl From<Error> for GameError {
+    fn from(e: Error) -> GameError {
+        match e {
+            Error::Asset(s) => GameError::ResourceLoadError(s),
+            Error::Audio(s) => GameError::AudioError(s),
+            Error::Config(s) => GameError::ConfigError(s),
+            Error::Game(e) => e,
+            e => GameError::UnknownError(e.to_string()),
+        }
+    }
+}
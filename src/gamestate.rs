@@ -1,363 +1,2181 @@
-use ggez::{event,graphics,Context, GameResult,timer,audio};
-use rand::{self,thread_rng, Rng};
+use std::path::PathBuf;
 
-use super::{block::Block,timer::Timer,ball::{Ball},bar};
+use chrono::NaiveDate;
+use ggez::{audio, event, graphics, timer, Context, GameResult};
+use rand::{thread_rng, Rng, XorShiftRng};
+use rodio;
 
-const BALL_PERIOD:f64 = 1f64;
-const BALL_MAX_TIME: f32 = 1.5f32;
+use super::assets::{self, Assets};
+use super::camera::Camera;
+use super::daily;
+use super::error::{Error, Result};
+use super::menu::{self, MenuAction};
+use super::mode::{Board, Mode};
+use super::music::{MusicPlayer, Playlist, Scene, SourceSink};
+use super::net::Lockstep;
+use super::particle::{Emitter, ParticleSystem};
+use super::player::{self, Control, Player};
+use super::puzzle::{self, Puzzle};
+use super::replay::{self, Race, Replay};
+use super::rules::{self, BALL_MAX_TIME, BLOCK_ALIVE};
+use super::save::SaveData;
+use super::settings::{self, Action, Difficulty, KeyBindings, Settings};
+use super::sfx::{self, Effect};
+use super::sim::{self, Input};
+use super::spawn;
+use super::text::TextCache;
+use super::theme::{Theme, Themes};
+use super::tween::{Easing, Tween};
+use super::ui::{Ui, UiKey, UiStyle};
+use super::view::{self, View};
+use super::{
+    ball::Ball,
+    bar,
+    block::{Block, BlockStyle},
+    timer::Timer,
+};
 
-const  BLOCK_COUNT: usize = 8;
-const BLOCK_NUM: usize = BLOCK_COUNT*BLOCK_COUNT;
-const BLOCK_ALIVE:f64 = 10f64;
-const BLOCK_GENERATE: f64 = 5f64;
+const BLOCK_COUNT: usize = rules::BOARD;
+const BLOCK_NUM: usize = rules::CELLS;
 
-const RELOADING_TIME:f64 = 1f64;
+const PARTICLE_LIMIT: usize = 512;
+
+const BLOCK_APPEAR: f32 = 0.5;
+const POPUP_TIME: f32 = 0.8;
+// size of the ghost's board next to the player's
+const GHOST_SCALE: f32 = 0.25;
 
 #[derive(Debug)]
 struct StateBlock {
     block: Block,
-    index:usize,
+    index: usize,
+    appear: Tween,
+}
+
+// "+N" floating up from where a ball scored
+#[derive(Debug)]
+struct Popup {
+    text: String,
+    pos: (f32, f32),
+    rise: Tween,
 }
 
 #[derive(Debug)]
 struct StateBall {
     ball: Ball,
-    extra_live_timer:Timer,
+    extra_live_timer: Timer,
+    // power bar value it was thrown with
+    charge: f32,
+    // index into `players` of whoever threw it
+    owner: usize,
 }
 
+// practice feedback about the last ball that landed
+#[derive(Debug)]
+struct ShotReport {
+    // from the landing point to the nearest block centre
+    error: f32,
+    charge: f32,
+    // charge that would have reached that block, if any could
+    needed: Option<f32>,
+}
 
-impl StateBall{
+// what the last landed ball changed, so practice can take it back
+#[derive(Debug)]
+struct Undo {
+    // slots in block_list
+    blocks: Vec<usize>,
+    score: usize,
+    // the combo before the ball came down
+    combo: usize,
+}
+
+// centre of the live block nearest to `pos`
+fn nearest_block(blocks: &[StateBlock], pos: (f32, f32)) -> Option<(f32, f32)> {
+    blocks
+        .iter()
+        .filter(|b| !b.block.is_stopped())
+        .map(|b| {
+            let r = b.block.get_rect();
+            (r.x + r.w / 2.0, r.y + r.h / 2.0)
+        })
+        .fold(None, |best: Option<(f32, f32)>, c| {
+            let d = (c.0 - pos.0).powi(2) + (c.1 - pos.1).powi(2);
+            match best {
+                Some(b) if (b.0 - pos.0).powi(2) + (b.1 - pos.1).powi(2) <= d => Some(b),
+                _ => Some(c),
+            }
+        })
+}
 
-    fn update(&mut self,ctx:&Context,time_delta:f32){
+// The ghost's game in miniature beside the player's: its blocks and balls,
+// drawn the way the board draws them, copied from its simulation each tick.
+#[derive(Debug, Default)]
+struct GhostBoard {
+    blocks: Vec<(usize, Block)>,
+    balls: Vec<Ball>,
+}
+
+impl GhostBoard {
+    fn show(
+        &mut self,
+        ctx: &Context,
+        sim: &sim::Sim,
+        style: BlockStyle,
+        ball_color: graphics::Color,
+    ) {
+        self.blocks
+            .retain(|&(cell, _)| sim.blocks.iter().any(|b| b.cell == cell));
+        for b in sim.blocks.iter() {
+            if !self.blocks.iter().any(|&(cell, _)| cell == b.cell) {
+                let mut block = Block::new(1.0, sim.cell_pos(b.cell), sim.delta_length());
+                block.set_style(style);
+                block.start(ctx);
+                self.blocks.push((b.cell, block));
+            }
+            if let Some(block) = self.blocks.iter_mut().find(|x| x.0 == b.cell) {
+                block.1.set_left(sim.time_left(b));
+            }
+        }
+        self.balls = sim
+            .balls
+            .iter()
+            .map(|b| {
+                let mut ball = b.ball.clone();
+                ball.set_color(ball_color);
+                ball
+            })
+            .collect();
+    }
+
+    fn restyle(&mut self, style: BlockStyle, ball_color: graphics::Color) {
+        for block in self.blocks.iter_mut() {
+            block.1.set_style(style);
+        }
+        for b in self.balls.iter_mut() {
+            b.set_color(ball_color);
+        }
+    }
+}
+
+impl StateBall {
+    fn update(&mut self, ctx: &Context, time_delta: f32) {
         self.ball.update(time_delta);
         self.extra_live_timer.update(ctx);
         self.extra_live_timer.get_event();
     }
 }
-#[derive(Debug)]
 struct SouldEffects {
-    energy_up:audio::Source,
-    boom : audio::Source,
-    shot : audio::Source,
-    loss : audio::Source,
+    // None once a sound has failed, the effects stay off for the session
+    voices: Option<sfx::Voices>,
+    shot: audio::Source,
+    // landings are panned and pitched, so they play through `voices`
+    boom: audio::SoundData,
+    loss: audio::SoundData,
+    energy: audio::SoundData,
+    // the charge sound while a power bar fills; dropping it stops the sound
+    charging: Option<rodio::Sink>,
+    charge_pitch: sfx::Pitch,
+    volume: f32,
 }
 
-impl SouldEffects{
-    fn new(ctx :&mut Context)->SouldEffects{
-        SouldEffects{
-            energy_up: audio::Source::new(ctx, "/energy_charge.ogg").unwrap(),
-            boom: audio::Source::new(ctx, "/boom.ogg").unwrap(),
-            shot: audio::Source::new(ctx, "/pew.ogg").unwrap(),
-            loss: audio::Source::new(ctx, "/loss.ogg").unwrap(),
+fn sound_data(ctx: &mut Context, assets: &mut Assets, name: &str) -> Result<audio::SoundData> {
+    Ok(audio::SoundData::from_bytes(&assets.read(ctx, name)?))
+}
+
+impl SouldEffects {
+    fn new(ctx: &mut Context, assets: &mut Assets) -> Result<SouldEffects> {
+        let voices = sfx::Voices::new()
+            .map_err(|e| eprintln!("falling ball: sound effects off: {}", e))
+            .ok();
+        Ok(SouldEffects {
+            voices: voices,
+            shot: assets.sound(ctx, assets::SHOT)?,
+            boom: sound_data(ctx, assets, assets::BOOM)?,
+            loss: sound_data(ctx, assets, assets::LOSS)?,
+            energy: sound_data(ctx, assets, assets::ENERGY_CHARGE)?,
+            charging: None,
+            charge_pitch: sfx::Pitch::new(1.0),
+            volume: 1.0,
+        })
+    }
+
+    fn set_volume(&mut self, volume: f32) {
+        self.volume = volume;
+        self.shot.set_volume(volume);
+        if let Some(ref mut sink) = self.charging {
+            sink.set_volume(volume);
         }
     }
 
-    fn energy_charge_reload(&mut self,ctx:&mut Context){
-        self.energy_up = audio::Source::new(ctx, "/energy_charge.ogg").unwrap();
+    // a sound that won't play is reported once and turns the effects off;
+    // it never ends the run
+    fn report(&mut self, result: Result<()>) {
+        if let Err(e) = result {
+            eprintln!("falling ball: sound effects off: {}", e);
+            self.voices = None;
+            self.charging = None;
+        }
+    }
+
+    fn shot(&mut self) {
+        if self.voices.is_some() {
+            let result = self.shot.play().map_err(|e| Error::Audio(e.to_string()));
+            self.report(result);
+        }
+        self.stop_charge();
+    }
+
+    fn landing(&mut self, effect: Effect, params: sfx::EffectParams) {
+        let result = match self.voices {
+            Some(ref voices) => voices.play(
+                if effect == Effect::Boom {
+                    &self.boom
+                } else {
+                    &self.loss
+                },
+                params,
+                self.volume,
+            ),
+            None => return,
+        };
+        self.report(result);
     }
-}
 
+    fn start_charge(&mut self) {
+        self.set_charge(0.0);
+        let result = match self.voices {
+            Some(ref voices) => voices.start(
+                &self.energy,
+                sfx::EffectParams::default(),
+                self.volume,
+                &self.charge_pitch,
+            ),
+            None => return,
+        };
+        match result {
+            Ok(sink) => self.charging = Some(sink),
+            Err(e) => self.report(Err(e)),
+        }
+    }
 
+    // the charge sound rises with the power bar
+    fn set_charge(&self, charge: f32) {
+        self.charge_pitch.set(sfx::charge_params(charge).pitch);
+    }
+
+    fn stop_charge(&mut self) {
+        self.charging = None;
+    }
+}
 
 #[repr(u8)]
 #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
 enum GameStatus {
-    Ready = 0,
+    Title = 0,
     Running = 1,
     GameOver = 2,
+    Leaderboard = 3,
+    Settings = 4,
+    KeyBindings = 5,
+    TimeSelect = 6,
+    PuzzleSelect = 7,
+    DailySelect = 8,
+    RaceSelect = 9,
 }
 
 pub struct GameState {
-    font:graphics::Font,
-    ball_ready_timer: Timer,
+    text: TextCache,
     block_generate_time_ticker: Timer,
-    power_record_bar: bar::TimerBar,
-    rng : rand::ThreadRng,
+    // time left in a timed round
+    run_timer: bar::TimerBar,
+    // reseeded every run, from the date for the daily challenge
+    rng: XorShiftRng,
     block_list: Vec<StateBlock>,
-    block_index: [bool;BLOCK_NUM],
+    block_index: [bool; BLOCK_NUM],
     ball_list: Vec<StateBall>,
+    particles: ParticleSystem,
+    popups: Vec<Popup>,
+    last_shot: Option<ShotReport>,
+    undo: Option<Undo>,
 
-    delta_length:f32,
-    window_size: (f32,f32),
+    delta_length: f32,
+    world_size: (f32, f32),
+    view: View,
+    camera: Camera,
+    hud: graphics::Rect,
 
     status: GameStatus,
-    score: usize,
-    left:usize,
+    mode: Mode,
+    // seconds in the current time attack round
+    time_limit: u32,
+    // which leaderboard the leaderboard screen shows
+    board: Board,
+    puzzles: Vec<Puzzle>,
+    // index into `puzzles` of the one being played
+    puzzle: usize,
+    shots_left: usize,
+    // stars for the last clear, None if the shots ran out
+    puzzle_stars: Option<u8>,
+    // the day of the daily challenge on show or being played
+    daily_date: NaiveDate,
+    // --date, played for fun and never scored
+    daily_override: Option<NaiveDate>,
+    // whether this daily run is the day's scored attempt
+    daily_scored: bool,
+    // a seeded run's spawns, the same whatever the player hits
+    spawns: Option<spawn::Sequence>,
+    // random part of the wait after the last seeded spawn
+    spawn_roll: Option<f64>,
+    ui: Ui,
+    save: SaveData,
+    save_path: Option<PathBuf>,
+    // action waiting for a key on the key bindings screen
+    capturing: Option<Action>,
+    // one per player in the current mode
+    players: Vec<Player>,
+    // whose round it is in hotseat
+    turn: usize,
+    // both hotseat rounds play the same blocks
+    round_seed: [u32; 4],
+    left: usize,
+    // a networked versus game, which replaces the local one while it lasts
+    lan: Option<Lockstep>,
+    // what this side sends the other player each frame, or feeds a race
+    sim_input: Input,
+    // the mouse button as the LAN or race ticks see it
+    sim_button: sim::Button,
+    // why a LAN game stopped early
+    lan_error: Option<String>,
+    // a solo run against a saved one, played on the simulation like a LAN game
+    race: Option<Race>,
+    // saved runs for the ghost race screen, best first
+    replays: Vec<Replay>,
+    unreadable_replays: usize,
+    // the ghost's score rolling up beside the player's
+    ghost_shown: Tween,
+    ghost_board: GhostBoard,
+    // whether the finished race was kept as the best on its seed
+    race_saved: bool,
 
     sould_effects: SouldEffects,
+    music: MusicPlayer<SourceSink>,
+    settings: Settings,
+    themes: Themes,
+    theme: Theme,
+    assets: Assets,
+    // raised inside event callbacks, reported by the next update
+    pending_error: Option<Error>,
 }
 
 impl GameState {
-    pub fn new(_ctx: &mut Context) -> GameResult<GameState> {
-        let window_size = graphics::get_size(_ctx);
-        let delta_length = window_size.1 as f32/(BLOCK_COUNT+8) as f32;
-        let font = graphics::Font::new(_ctx, "/DejaVuSerif.ttf", 12)?;
-        let mut s = GameState{
-            ball_ready_timer: Timer::new(BALL_PERIOD),
+    // `prefs` are the saved preferences if there were any, otherwise the
+    // settings file from the resources is used
+    pub fn new(_ctx: &mut Context, prefs: Option<Settings>) -> Result<GameState> {
+        let mut view = View::new((view::WORLD_WIDTH, view::WORLD_HEIGHT), (1.0, 1.0));
+        view.sync(_ctx);
+        let world_size = view.world_size();
+        let delta_length = rules::delta_length(world_size.1);
+        let mut settings = match prefs {
+            Some(s) => s,
+            None => Settings::load(_ctx)?,
+        };
+        let save_path = SaveData::path();
+        let save = match save_path {
+            Some(ref p) => SaveData::load(p)?,
+            None => SaveData::default(),
+        };
+        let mut assets = Assets::new(_ctx, settings.resource_pack.as_ref().map(|p| p.as_str()))?;
+        let font = assets.font_scaled(
+            _ctx,
+            settings.accessibility.font_size,
+            settings.accessibility.ui_scale * view.pixels_per_unit(),
+        )?;
+        let playlist = Playlist::load(_ctx, &mut assets)?;
+        let music_sink = SourceSink::new(_ctx, &mut assets, &playlist)?;
+        let sould_effects = SouldEffects::new(_ctx, &mut assets)?;
+        let mut themes = Themes::load(_ctx, &mut assets)?;
+        let puzzles = puzzle::load(_ctx, &mut assets, BLOCK_COUNT)?;
+        // a theme file that has gone away shouldn't keep the game from starting
+        let theme_missing = !themes.select(&settings.theme);
+        if theme_missing {
+            let fallback = Theme::default().name;
+            eprintln!(
+                "falling ball: unknown theme {:?}, using {:?} (available: {})",
+                settings.theme,
+                fallback,
+                themes.names().join(", ")
+            );
+            themes.select(&fallback);
+            settings.theme = fallback;
+        }
+        let rng: XorShiftRng = thread_rng().gen();
+        let particles = ParticleSystem::new(thread_rng().gen(), PARTICLE_LIMIT);
+        let mut s = GameState {
             block_generate_time_ticker: Timer::new(BLOCK_ALIVE),
-            power_record_bar: bar::TimerBar::new(
-                RELOADING_TIME,
-                window_size.0 as f32 - delta_length*0.5,
-                window_size.1 as f32 - delta_length*3.2,
-                delta_length*3.0,
-                delta_length*0.4
-                 ),
-            rng:thread_rng(),
+            run_timer: bar::TimerBar::new(
+                save.time_attack_length as f64,
+                0.0,
+                delta_length * 1.2,
+                delta_length * BLOCK_COUNT as f32,
+                delta_length * 0.4,
+            ),
+            rng: rng,
             block_list: vec![],
-            block_index:[false;BLOCK_NUM],
+            block_index: [false; BLOCK_NUM],
             ball_list: vec![],
+            particles: particles,
+            popups: vec![],
+            last_shot: None,
+            undo: None,
 
-            font: font,
-            delta_length: window_size.1 as f32/(BLOCK_COUNT+8) as f32,
-            window_size: (window_size.0 as f32,window_size.1 as f32),
+            text: TextCache::new(font, view.pixels_per_unit()),
+            delta_length: delta_length,
+            world_size: world_size,
+            hud: view.visible(),
+            camera: Camera::new(delta_length * 0.3),
+            view: view,
 
-            left:5,
-            score:0,
-            status: GameStatus::Ready,
+            left: 5,
+            status: GameStatus::Title,
+            mode: save.last_mode,
+            time_limit: save.time_attack_length,
+            board: Board::Endless,
+            puzzles: puzzles,
+            puzzle: 0,
+            shots_left: 0,
+            puzzle_stars: None,
+            daily_date: daily::today(),
+            daily_override: None,
+            daily_scored: false,
+            spawns: None,
+            spawn_roll: None,
+            ui: Ui::new(UiStyle::default()),
+            save: save,
+            save_path: save_path,
+            capturing: None,
+            players: vec![Player::new(Control::Mouse)],
+            turn: 0,
+            round_seed: [1, 0, 0, 0],
+            lan: None,
+            sim_input: Input::default(),
+            sim_button: sim::Button::default(),
+            lan_error: None,
+            race: None,
+            replays: vec![],
+            unreadable_replays: 0,
+            ghost_shown: Tween::new(0.0, 0.0, player::SCORE_ROLL, Easing::EaseOut),
+            ghost_board: GhostBoard::default(),
+            race_saved: false,
 
-            sould_effects: SouldEffects::new(_ctx),
+            sould_effects: sould_effects,
+            music: MusicPlayer::new(music_sink, playlist),
+            settings: settings,
+            themes: themes,
+            theme: Theme::default(),
+            assets: assets,
+            pending_error: None,
         };
-        s.power_record_bar.set_direction(bar::BarDirection::Vertical);
-        s.power_record_bar.set_increase(true);
+        s.run_timer.set_gradient(Some(bar::Bar::traffic_gradient()));
+        s.layout();
         s.restore_timer();
+        s.apply_audio_settings();
+        s.apply_theme();
+        s.camera.set_intensity(s.settings.camera_intensity());
+        s.show_title();
+        if theme_missing {
+            s.save_settings();
+        }
         Ok(s)
     }
 
-    fn restore_timer(&mut self){
-        let count = self.block_index.iter().filter(|&&x| x).count();
-        if count == 0{
-            self.block_generate_time_ticker.restore(0.1);
-        }else{
-            let count = BLOCK_GENERATE*count.min(10) as f64;
-            let duration = self.rng.gen_range(count,3.0 + count);
-            self.block_generate_time_ticker.restore(duration);
+    // places everything that depends on the window shape; blocks keep their
+    // timers so this is safe in the middle of a game
+    fn layout(&mut self) {
+        self.hud = self.view.visible();
+        self.delta_length = rules::delta_length(self.world_size.1);
+        let right = self.hud.x + self.hud.w;
+        let left = self.get_left_point();
+        self.run_timer.set_position(left, self.delta_length * 1.2);
+        self.run_timer.set_size(
+            self.delta_length * BLOCK_COUNT as f32,
+            self.delta_length * 0.4,
+        );
+        let (dl, world, side_by_side) = (self.delta_length, self.world_size, self.side_by_side());
+        let hud_x = self.hud.x;
+        for (i, p) in self.players.iter_mut().enumerate() {
+            // side by side launchers have their power bars at their own edge
+            // of the window
+            let bar_x = if side_by_side && i == 0 {
+                hud_x + dl * 0.1
+            } else {
+                right - dl * 0.5
+            };
+            p.place(rules::launcher_pos(world, dl, side_by_side, i), bar_x, dl);
+        }
+        let width = self.delta_length;
+        for i in 0..self.block_list.len() {
+            let pos = self.get_block_pos(self.block_list[i].index);
+            self.block_list[i].block.set_geometry(pos, width);
         }
     }
 
-    fn get_left_point(&self)->f32{
-        (self.window_size.0 as f32- BLOCK_COUNT as f32 * self.delta_length) as f32 /2f32
+    fn set_fullscreen(&mut self, ctx: &mut Context, on: bool) -> Result<()> {
+        graphics::set_fullscreen(ctx, on)?;
+        self.settings.fullscreen = on;
+        self.window_changed(ctx)
     }
 
-    fn get_ball_max_range(&self)->f32{
-        let w = self.delta_length*BLOCK_COUNT as f32/2.0;
-        let h = self.delta_length*(6+BLOCK_COUNT) as f32;
-        (w*w+h*h).sqrt()
+    fn window_changed(&mut self, ctx: &mut Context) -> Result<()> {
+        let ppu = self.view.pixels_per_unit();
+        self.view.sync(ctx);
+        self.layout();
+        if self.view.pixels_per_unit() != ppu {
+            self.apply_scale(ctx)?;
+        }
+        Ok(())
+    }
+
+    // world-space width of a one pixel line at the current UI scale, never
+    // thinner than one real pixel
+    fn line_width(&self) -> f32 {
+        self.settings
+            .accessibility
+            .ui_scale
+            .max(1.0 / self.view.pixels_per_unit())
+    }
+
+    fn circle_tolerance(&self) -> f32 {
+        (0.5 / self.view.pixels_per_unit()).min(1.0)
+    }
+
+    // re-rasterises text and resizes rims after the pixel density or the
+    // UI scale changed
+    fn apply_scale(&mut self, ctx: &mut Context) -> Result<()> {
+        let ppu = self.view.pixels_per_unit();
+        let a11y = &self.settings.accessibility;
+        let font = self
+            .assets
+            .font_scaled(ctx, a11y.font_size, a11y.ui_scale * ppu)?;
+        self.text.set_font(font, ppu);
+        self.apply_theme();
+        Ok(())
+    }
+
+    fn set_ui_scale(&mut self, ctx: &mut Context, scale: f32) -> Result<()> {
+        self.settings.accessibility.ui_scale = scale
+            .max(settings::UI_SCALE_MIN)
+            .min(settings::UI_SCALE_MAX);
+        self.apply_scale(ctx)
+    }
+
+    fn get_block_pos(&self, index: usize) -> (f32, f32) {
+        rules::cell_pos(self.world_size.0, self.delta_length, index)
+    }
+
+    fn apply_theme(&mut self) {
+        let theme = if self.settings.accessibility.high_contrast {
+            Theme::high_contrast()
+        } else {
+            self.themes.current().clone()
+        };
+        self.theme = theme.with_palette(self.settings.accessibility.palette);
+        let line_width = self.line_width();
+        for p in self.players.iter_mut() {
+            p.apply_theme(&self.theme, line_width);
+        }
+        self.run_timer.set_rim_color(self.theme.bar_rim);
+        self.run_timer.set_rim_width(self.line_width());
+        self.ui
+            .set_style(UiStyle::from_theme(&self.theme, self.line_width()));
+        let style = self.block_style();
+        for b in self.block_list.iter_mut() {
+            b.block.set_style(style);
+        }
+        for b in self.ball_list.iter_mut() {
+            b.ball.set_color(self.theme.ball_color(b.owner));
+        }
+        self.ghost_board.restyle(style, self.theme.ball_color(0));
+    }
+
+    fn block_style(&self) -> BlockStyle {
+        BlockStyle {
+            color: self.theme.block,
+            timer_color: self.theme.block_timer,
+            urgent_color: self.theme.block_urgent,
+            rim_color: self.theme.bar_rim,
+            pattern_color: self.theme.background,
+            rim_width: self.line_width(),
+            timer: self.settings.block_timer,
+            urgency_cues: self.settings.accessibility.urgency_cues,
+        }
+    }
+
+    fn set_font_size(&mut self, ctx: &mut Context, size: u32) -> Result<()> {
+        let size = size
+            .max(settings::FONT_SIZE_MIN)
+            .min(settings::FONT_SIZE_MAX);
+        self.settings.accessibility.font_size = size;
+        self.apply_scale(ctx)
+    }
+
+    fn apply_audio_settings(&mut self) {
+        self.sould_effects
+            .set_volume(self.settings.audio.effect_volume());
+        self.music.set_gain(self.settings.audio.music_gain());
+    }
+
+    // failing to save never stops the game, it is only reported
+    fn save_settings(&self) {
+        if let Some(path) = Settings::path() {
+            if let Err(e) = self.settings.save(&path) {
+                eprintln!("falling ball: {}", e);
+            }
+        }
+    }
+
+    // makes edits from the settings screen take effect
+    fn apply_settings(&mut self, ctx: &mut Context, old: &Settings) -> Result<()> {
+        if self.settings == *old {
+            return Ok(());
+        }
+        self.apply_audio_settings();
+        if self.settings.theme != old.theme {
+            self.themes.select(&self.settings.theme);
+        }
+        self.camera.set_intensity(self.settings.camera_intensity());
+        if self.settings.accessibility.ui_scale != old.accessibility.ui_scale {
+            self.apply_scale(ctx)?;
+        } else {
+            self.apply_theme();
+        }
+        if self.settings.fullscreen != graphics::is_fullscreen(ctx) {
+            let on = self.settings.fullscreen;
+            self.set_fullscreen(ctx, on)?;
+        }
+        Ok(())
+    }
+
+    fn run_action(&mut self, ctx: &mut Context, action: Action) -> Result<()> {
+        match action {
+            Action::Mute => {
+                self.settings.audio.muted = !self.settings.audio.muted;
+                self.apply_audio_settings();
+            }
+            Action::Fullscreen => {
+                let on = !graphics::is_fullscreen(ctx);
+                self.set_fullscreen(ctx, on)?;
+            }
+            Action::Theme => {
+                self.settings.theme = self.themes.next().name.clone();
+                self.apply_theme();
+            }
+            Action::Palette => {
+                self.settings.accessibility.palette = self.settings.accessibility.palette.next();
+                self.apply_theme();
+            }
+            Action::HighContrast => {
+                self.settings.accessibility.high_contrast =
+                    !self.settings.accessibility.high_contrast;
+                self.apply_theme();
+            }
+            Action::UrgencyCues => {
+                self.settings.accessibility.urgency_cues =
+                    !self.settings.accessibility.urgency_cues;
+                self.apply_theme();
+            }
+            Action::BlockTimer => {
+                self.settings.block_timer = self.settings.block_timer.next();
+                self.apply_theme();
+            }
+            Action::ReduceMotion => {
+                self.settings.accessibility.reduce_motion =
+                    !self.settings.accessibility.reduce_motion;
+                self.camera.set_intensity(self.settings.camera_intensity());
+            }
+            Action::UiSmaller | Action::UiLarger => {
+                let step = if action == Action::UiSmaller {
+                    -0.25
+                } else {
+                    0.25
+                };
+                let scale = self.settings.accessibility.ui_scale + step;
+                self.set_ui_scale(ctx, scale)?;
+            }
+            Action::Undo => {
+                self.undo_shot(ctx);
+                return Ok(());
+            }
+            // held keys go through control_key
+            Action::AimLeft | Action::AimRight | Action::Charge => return Ok(()),
+            Action::FontSmaller | Action::FontLarger => {
+                let size = self.settings.accessibility.font_size;
+                let size = if action == Action::FontSmaller {
+                    size.saturating_sub(2)
+                } else {
+                    size + 2
+                };
+                self.set_font_size(ctx, size)?;
+            }
+        }
+        self.save_settings();
+        Ok(())
+    }
+
+    fn update_music(&mut self, time_delta: f32) -> Result<()> {
+        let scene = match self.status {
+            GameStatus::Running => Scene::Gameplay,
+            GameStatus::GameOver => Scene::GameOver,
+            _ => Scene::Title,
+        };
+        self.music.set_scene(scene)?;
+        self.music.update(time_delta)
+    }
+
+    fn restore_timer(&mut self) {
+        // seeded runs wait by their sequence, not by what is on the board
+        let (count, roll) = match self.spawn_roll.take() {
+            Some(roll) => (spawn::SEEDED_LOAD, roll),
+            None => (
+                self.block_index.iter().filter(|&&x| x).count(),
+                self.rng.gen::<f64>(),
+            ),
+        };
+        let duration = rules::spawn_wait(
+            self.mode,
+            self.difficulty(),
+            self.run_timer.get_value(),
+            count,
+            roll,
+        );
+        self.block_generate_time_ticker.restore(duration);
+    }
+
+    // the daily challenge is the same for everyone whatever their settings
+    fn difficulty(&self) -> Difficulty {
+        if self.mode == Mode::Daily {
+            Difficulty::Normal
+        } else {
+            self.settings.difficulty
+        }
     }
 
-    fn get_ball_max_vel(&self) ->f32{
-        self.get_ball_max_range()/BALL_MAX_TIME
+    // plays a networked versus game, already connected, instead of opening
+    // on the title screen
+    pub fn start_lan(&mut self, game: Lockstep) {
+        self.start_sim(game.sim().mode());
+        self.lan = Some(game);
+        self.lan_error = None;
     }
 
-    fn game_over(&mut self){
+    // an empty board for a game played on the simulation; the players only
+    // show what it does, so neither is on the keyboard
+    fn start_sim(&mut self, mode: Mode) {
+        self.mode = mode;
+        self.set_players();
+        for p in self.players.iter_mut() {
+            p.control = Control::Mouse;
+        }
+        self.turn = 0;
+        self.popups.clear();
+        self.last_shot = None;
+        self.undo = None;
+        self.camera.reset();
+        self.left = rules::LIVES;
+        self.particles.clear();
+        self.clear_board();
+        self.sim_input = Input::default();
+        self.sim_button = sim::Button::default();
+        self.status = GameStatus::Running;
+    }
+
+    // puts what the last tick of `sim` did on the board, with the effects
+    // and sounds a local game makes for the same things; `inputs` are the
+    // ones the tick was played with
+    fn show_tick(&mut self, ctx: &Context, sim: &sim::Sim, inputs: &[Input]) {
+        // balls fly here as they do in the simulation, from a copy taken as
+        // they leave the launcher
+        let mut landed = vec![];
+        for i in 0..self.ball_list.len() {
+            let b = &mut self.ball_list[i];
+            if b.ball.is_avtive() {
+                b.ball.update(sim::TICK);
+                if b.ball.is_on_ground() {
+                    b.ball.disable();
+                    landed.push(i);
+                }
+            }
+        }
+        let mut landed = landed.into_iter();
+        let mut landing = None;
+        let mut destroyed = 0;
+        let mut thrown = vec![];
+        for e in sim.events.iter() {
+            match *e {
+                sim::Event::Spawned(cell) => self.spawn_block(ctx, cell),
+                sim::Event::Thrown(player) => {
+                    self.sould_effects.shot();
+                    thrown.push(player);
+                }
+                sim::Event::Landed {
+                    owner,
+                    pos,
+                    ref cells,
+                } => {
+                    let broken: Vec<graphics::Rect> =
+                        cells.iter().filter_map(|&c| self.take_block(c)).collect();
+                    self.ball_landed(owner, pos, &broken);
+                    // the sim lands its balls in the order they were thrown
+                    let ball = landed.next();
+                    if let (true, Some(i)) = (cells.is_empty(), ball) {
+                        self.ball_list[i].extra_live_timer.start(ctx);
+                    }
+                    if !cells.is_empty() || landing.is_none() {
+                        landing = Some((!cells.is_empty(), pos.0, owner));
+                    }
+                    destroyed += cells.len();
+                }
+                sim::Event::Expired(cell) => {
+                    if let Some(r) = self.take_block(cell) {
+                        self.block_lost(r);
+                    }
+                }
+            }
+        }
+        // the balls thrown this tick are the last ones in
+        self.ball_list
+            .retain(|b| b.ball.is_avtive() || !b.extra_live_timer.is_stopped());
+        let first = sim.balls.len() - thrown.len();
+        for (b, owner) in sim.balls[first..].iter().zip(thrown) {
+            let mut ball = b.ball.clone();
+            ball.set_color(self.theme.ball_color(owner));
+            self.ball_list.push(StateBall {
+                ball: ball,
+                extra_live_timer: Timer::new(0.5),
+                charge: self.players[owner].power_bar.get_value(),
+                owner: owner,
+            });
+        }
+        for (i, p) in sim.players.iter().enumerate() {
+            let was_charging = self.players[i].is_charging();
+            self.players[i].mirror(ctx, p, inputs[i].aim);
+            if p.is_charging() && !was_charging {
+                self.sould_effects.start_charge();
+            } else if p.is_charging() {
+                self.sould_effects.set_charge(p.charge());
+            }
+        }
+        for b in sim.blocks.iter() {
+            if let Some(sb) = self
+                .block_list
+                .iter_mut()
+                .find(|sb| sb.index == b.cell && !sb.block.is_stopped())
+            {
+                sb.block.set_left(sim.time_left(b));
+            }
+        }
+        self.left = sim.left;
+        // no hit-stop: the simulation can't stand still for it
+        if destroyed > 0 {
+            self.camera.shake(0.3 * destroyed as f32);
+        }
+        if let Some((hit, x, owner)) = landing {
+            self.landing_sound(hit, x, owner);
+        }
+    }
+
+    // takes the block in `cell` off the board, returning where it was
+    fn take_block(&mut self, cell: usize) -> Option<graphics::Rect> {
+        self.block_index[cell] = false;
+        self.block_list
+            .iter_mut()
+            .find(|b| b.index == cell && !b.block.is_stopped())
+            .map(|b| {
+                b.block.stop();
+                b.block.get_rect()
+            })
+    }
+
+    // the parts of a simulated game that move with the frame rate, not the
+    // ticks
+    fn update_sim_board(&mut self, ctx: &mut Context) {
+        let real_time = ((timer::get_delta(ctx)).subsec_millis() as f32) / 1.0e3;
+        let delta_time = self.camera.update(real_time);
+        for p in self.players.iter_mut() {
+            p.animate(delta_time);
+        }
+        for b in self.ball_list.iter_mut().filter(|b| !b.ball.is_avtive()) {
+            b.update(ctx, delta_time);
+        }
+        self.animate_board(delta_time);
+    }
+
+    // one tick of our clock: sends our input when another is due and plays
+    // a frame if the other player's is in, never waiting for it; losing the
+    // other player ends the game, not the program
+    fn update_lan(&mut self, ctx: &Context) {
+        if self.status != GameStatus::Running {
+            return;
+        }
+        let mut game = match self.lan.take() {
+            Some(game) => game,
+            None => return,
+        };
+        if game.wants_input() {
+            self.sim_input.charging = self.sim_button.tick();
+            game.send_input(self.sim_input);
+        }
+        match game.advance() {
+            Ok(false) => {}
+            Ok(true) => {
+                self.show_tick(ctx, game.sim(), game.inputs());
+                if game.sim().is_over() {
+                    self.status = GameStatus::GameOver;
+                    game.close("game over");
+                }
+            }
+            Err(e) => {
+                self.lan_error = Some(e.to_string());
+                self.status = GameStatus::GameOver;
+            }
+        }
+        self.lan = Some(game);
+    }
+
+    // a race on `seed`, against `ghost` if there is a saved run on it
+    fn start_race(&mut self, seed: [u32; 4], ghost: Option<Replay>) {
+        let race = Race::new(seed, ghost, &daily::format_date(daily::today()));
+        self.start_sim(race.sim.mode());
+        self.race = Some(race);
+        self.ghost_board = GhostBoard::default();
+        self.ghost_shown.snap(0.0);
+        self.race_saved = false;
+    }
+
+    // again on the same seed, against its best run, which may be the one
+    // just played; either way it has been played out already
+    fn restart_race(&mut self) {
+        if let Some(seed) = self.race.as_ref().map(|r| r.seed()) {
+            let ghost = self.replays.iter().find(|r| r.seed == seed).cloned();
+            self.start_race(seed, ghost);
+        }
+    }
+
+    // one tick of the race, on the player's board and the ghost's
+    fn update_race(&mut self, ctx: &Context) {
+        if self.status != GameStatus::Running {
+            return;
+        }
+        self.sim_input.charging = self.sim_button.tick();
+        let input = self.sim_input;
+        let mut race = match self.race.take() {
+            Some(race) => race,
+            None => return,
+        };
+        race.step(input);
+        self.show_tick(ctx, &race.sim, &[input]);
+        if let Some(ref ghost) = race.ghost {
+            let style = self.block_style();
+            self.ghost_board
+                .show(ctx, &ghost.sim, style, self.theme.ball_color(0));
+            self.ghost_shown.retarget(ghost.sim.players[0].score as f32);
+        }
+        let over = race.sim.is_over();
+        self.race = Some(race);
+        if over {
+            self.status = GameStatus::GameOver;
+            self.finish_race();
+        }
+    }
+
+    // keeps the run if it is a new best on its seed; like the save file,
+    // failing to write it is only reported
+    fn finish_race(&mut self) {
+        let run = match self.race {
+            Some(ref race) => race.result(),
+            None => None,
+        };
+        if let (Some(run), Some(dir)) = (run, replay::dir()) {
+            match run.save(&dir) {
+                Ok(()) => self.race_saved = true,
+                Err(e) => eprintln!("falling ball: {}", e),
+            }
+            self.load_replays();
+        }
+    }
+
+    fn load_replays(&mut self) {
+        let (replays, errors) = match replay::dir() {
+            Some(dir) => replay::load_all(&dir),
+            None => (vec![], vec![]),
+        };
+        for e in errors.iter() {
+            eprintln!("falling ball: {}", e);
+        }
+        self.replays = replays;
+        self.unreadable_replays = errors.len();
+    }
+
+    // plays the daily challenge of `date` instead of today's, unscored
+    pub fn set_daily_date(&mut self, date: NaiveDate) {
+        self.daily_override = Some(date);
+        self.daily_date = date;
+    }
+
+    // today's date is looked up again each time, so a session left open
+    // past midnight moves on to the new day
+    fn refresh_daily_date(&mut self) {
+        self.daily_date = self.daily_override.unwrap_or_else(daily::today);
+    }
+
+    fn get_left_point(&self) -> f32 {
+        rules::left_point(self.world_size.0, self.delta_length)
+    }
+
+    // two launchers at the bottom at once
+    fn side_by_side(&self) -> bool {
+        self.players.len() > 1 && !self.mode.takes_turns()
+    }
+
+    // in hotseat only the player whose turn it is is on the board
+    fn is_active(&self, i: usize) -> bool {
+        !self.mode.takes_turns() || i == self.turn
+    }
+
+    fn team_score(&self) -> usize {
+        self.players.iter().map(|p| p.score).sum()
+    }
+
+    fn mouse_player(&self) -> Option<usize> {
+        (0..self.players.len())
+            .find(|&i| self.is_active(i) && self.players[i].control == Control::Mouse)
+    }
+
+    fn keyboard_player(&self) -> Option<usize> {
+        (0..self.players.len())
+            .find(|&i| self.is_active(i) && self.players[i].control == Control::Keyboard)
+    }
+
+    fn get_ball_max_range(&self) -> f32 {
+        rules::max_range(self.delta_length, self.side_by_side())
+    }
+
+    fn get_ball_max_vel(&self) -> f32 {
+        rules::max_vel(self.delta_length, self.side_by_side())
+    }
+
+    fn game_over(&mut self) {
         self.block_generate_time_ticker.stop();
-        self.ball_ready_timer.stop();
+        for p in self.players.iter_mut() {
+            p.ready_timer.stop();
+        }
+        self.run_timer.pause();
     }
 
-    fn game_restart(&mut self,ctx:&Context){
-            self.status = GameStatus::Running;
-            self.score = 0;
-            self.left = 5;
-            self.restore_timer();
-            self.block_generate_time_ticker.start(ctx);
+    // runs once when a run ends
+    fn finish_run(&mut self) {
+        let score = self.players[0].score;
+        match self.mode {
+            Mode::TimeAttack => {
+                self.save.record_time_attack(self.time_limit, score);
+            }
+            Mode::Levels => {
+                let cleared = !self.block_index.iter().any(|&x| x);
+                self.puzzle_stars = if cleared {
+                    Some(self.puzzles[self.puzzle].stars(self.shots_left))
+                } else {
+                    None
+                };
+                if let Some(stars) = self.puzzle_stars {
+                    self.save
+                        .record_puzzle(&self.puzzles[self.puzzle].id, stars);
+                }
+            }
+            Mode::Daily => {
+                if self.daily_scored {
+                    self.save.daily.finish(self.daily_date, score);
+                }
+            }
+            // two-player games are compared on the spot, not kept
+            Mode::Hotseat | Mode::Versus | Mode::Coop => {}
+            // practice is never scored, and races keep their own replays
+            Mode::Practice | Mode::Race => {}
+            Mode::Endless => {
+                self.save.record_score(score);
+            }
+        }
+        self.write_save();
+    }
+
+    // failing to save never stops the game, it is only reported
+    fn write_save(&mut self) {
+        if let Some(ref path) = self.save_path {
+            if let Err(e) = self.save.save(path) {
+                eprintln!("falling ball: {}", e);
+            }
+        }
+    }
+
+    fn clear_board(&mut self) {
+        for b in self.block_list.iter_mut() {
+            b.block.stop();
+        }
+        self.block_index = [false; BLOCK_NUM];
+        for b in self.ball_list.iter_mut() {
+            b.ball.disable();
+        }
+    }
+
+    // leaves whatever run is going on and clears the board
+    fn show_title(&mut self) {
+        if let Some(mut game) = self.lan.take() {
+            game.close("left the game");
+        }
+        self.race = None;
+        self.game_over();
+        self.clear_board();
+        self.particles.clear();
+        self.popups.clear();
+        self.last_shot = None;
+        self.undo = None;
+        self.status = GameStatus::Title;
+        self.ui.set_focus(Some(menu::title_focus(self.mode)));
+    }
+
+    fn start_mode(&mut self, ctx: &Context, mode: Mode) {
+        self.mode = mode;
+        self.save.last_mode = mode;
+        self.write_save();
+        self.set_players();
+        self.turn = 0;
+        self.round_seed = sim::random_seed();
+        self.game_restart(ctx);
+    }
+
+    // the second player of a versus game is on the keyboard
+    fn set_players(&mut self) {
+        let mode = self.mode;
+        self.players = (0..mode.players())
+            .map(|i| {
+                Player::new(if i > 0 && !mode.takes_turns() {
+                    Control::Keyboard
+                } else {
+                    Control::Mouse
+                })
+            })
+            .collect();
+        self.layout();
+        self.apply_theme();
+    }
+
+    // restarts after a game over; hotseat hands over to the next player and
+    // draws new blocks once everyone has had a go
+    fn next_round(&mut self, ctx: &Context) {
+        if self.mode.takes_turns() {
+            self.turn = (self.turn + 1) % self.players.len();
+            if self.turn == 0 {
+                self.round_seed = sim::random_seed();
+            }
+        }
+        self.game_restart(ctx);
+    }
+
+    // a LAN game or a race, both run on the simulation rather than the board
+    fn on_sim(&self) -> bool {
+        self.lan.is_some() || self.race.is_some()
+    }
+
+    fn in_menu(&self) -> bool {
+        match self.status {
+            GameStatus::Running | GameStatus::GameOver => false,
+            _ => true,
+        }
     }
 
-    fn random_block(&mut self,ctx:&Context){
-        let count = self.block_index.iter().filter(|&&x| !x).count();
-        if count > 0{
-            let mut index = self.rng.gen_range(0usize,count);
-            let mut count = 0;
-            for (i,v) in self.block_index.iter().enumerate(){
-                if *v{
-                    if count == index{
-                        index = i;
-                        break;
+    fn update_menu(&mut self, ctx: &mut Context) -> Result<()> {
+        let area = self.hud;
+        let row = self.delta_length * 0.8;
+        let action = match self.status {
+            GameStatus::Title => menu::title(&mut self.ui, area, row, &self.save),
+            GameStatus::Leaderboard => {
+                menu::leaderboard(&mut self.ui, area, row, &self.save, self.board)
+            }
+            GameStatus::TimeSelect => menu::time_attack(&mut self.ui, area, row),
+            GameStatus::PuzzleSelect => {
+                menu::puzzles(&mut self.ui, area, row, &self.puzzles, &self.save)
+            }
+            GameStatus::DailySelect => {
+                menu::daily(&mut self.ui, area, row, &self.save.daily, self.daily_date)
+            }
+            GameStatus::RaceSelect => menu::race(
+                &mut self.ui,
+                area,
+                row,
+                &self.replays,
+                self.unreadable_replays,
+            ),
+            GameStatus::Settings => {
+                let old = self.settings.clone();
+                let action = {
+                    let names = self.themes.names();
+                    menu::settings(&mut self.ui, area, row, &mut self.settings, &names)
+                };
+                self.apply_settings(ctx, &old)?;
+                action
+            }
+            GameStatus::KeyBindings => {
+                menu::key_bindings(&mut self.ui, area, row, &self.settings.keys, self.capturing)
+            }
+            _ => return Ok(()),
+        };
+        match action {
+            Some(MenuAction::Start(Mode::TimeAttack)) => {
+                self.status = GameStatus::TimeSelect;
+                self.ui
+                    .set_focus(Some(menu::time_attack_focus(self.save.time_attack_length)));
+            }
+            Some(MenuAction::Start(Mode::Levels)) => {
+                self.status = GameStatus::PuzzleSelect;
+                self.ui.set_focus(None);
+            }
+            Some(MenuAction::Start(Mode::Daily)) => {
+                self.refresh_daily_date();
+                self.status = GameStatus::DailySelect;
+                self.ui.set_focus(None);
+            }
+            Some(MenuAction::Start(Mode::Race)) => {
+                self.load_replays();
+                self.status = GameStatus::RaceSelect;
+                self.ui.set_focus(None);
+            }
+            Some(MenuAction::Start(mode)) => self.start_mode(ctx, mode),
+            Some(MenuAction::PlayDaily) => self.start_mode(ctx, Mode::Daily),
+            Some(MenuAction::Race(ghost)) => {
+                self.mode = Mode::Race;
+                self.save.last_mode = Mode::Race;
+                self.write_save();
+                let ghost = ghost.and_then(|i| self.replays.get(i)).cloned();
+                // a run is only played out when it is picked, not every time
+                // the list is read; one that doesn't add up stays off it
+                match ghost.as_ref().map(|g| g.verify()) {
+                    Some(Err(e)) => {
+                        eprintln!("falling ball: {}", e);
+                        self.replays.retain(|r| Some(r) != ghost.as_ref());
+                        self.unreadable_replays += 1;
+                    }
+                    _ => {
+                        let seed = ghost.as_ref().map_or_else(sim::random_seed, |g| g.seed);
+                        self.start_race(seed, ghost);
                     }
-                    count +=1;
                 }
             }
-            self.block_index[index] = true;
-            let pos = (
-                    (index%BLOCK_COUNT) as f32*self.delta_length+ self.get_left_point(),
-                    (index/BLOCK_COUNT +2 ) as f32* self.delta_length,
-                );
-            if let Some(block_item) = self.block_list.iter_mut().find(|b| b.block.is_stopped()){
-                block_item.block.restore(BLOCK_ALIVE,pos,self.delta_length);
-                block_item.block.start(ctx);
-                block_item.index = index;
-                return;
+            Some(MenuAction::Puzzle(i)) => {
+                self.puzzle = i;
+                self.start_mode(ctx, Mode::Levels);
             }
-            let mut block_item = StateBlock{
-                index: index,
-                block:Block::new(BLOCK_ALIVE,pos,self.delta_length),
-            };
+            Some(MenuAction::TimeAttack(seconds)) => {
+                self.time_limit = seconds;
+                self.save.time_attack_length = seconds;
+                self.start_mode(ctx, Mode::TimeAttack);
+            }
+            Some(MenuAction::Leaderboard) => {
+                self.board = match self.mode {
+                    Mode::TimeAttack => Board::TimeAttack(self.save.time_attack_length),
+                    _ => Board::Endless,
+                };
+                self.status = GameStatus::Leaderboard;
+                self.ui.set_focus(None);
+            }
+            Some(MenuAction::NextBoard) => self.board = self.board.next(),
+            Some(MenuAction::Settings) => {
+                self.status = GameStatus::Settings;
+                self.ui.set_focus(None);
+            }
+            Some(MenuAction::KeyBindings) => {
+                self.status = GameStatus::KeyBindings;
+                self.ui.set_focus(None);
+            }
+            Some(MenuAction::Capture(a)) => self.capturing = Some(a),
+            Some(MenuAction::ResetKeys) => {
+                self.settings.keys = KeyBindings::default();
+                self.save_settings();
+            }
+            Some(MenuAction::Back) if self.status == GameStatus::KeyBindings => {
+                self.capturing = None;
+                self.status = GameStatus::Settings;
+                self.ui.set_focus(None);
+            }
+            Some(MenuAction::Back) => {
+                if self.status == GameStatus::Settings {
+                    self.save_settings();
+                }
+                self.show_title();
+            }
+            Some(MenuAction::Quit) => ctx.quit()?,
+            None => {}
+        }
+        Ok(())
+    }
+
+    fn game_restart(&mut self, ctx: &Context) {
+        self.status = GameStatus::Running;
+        for i in 0..self.players.len() {
+            if self.is_active(i) {
+                self.players[i].reset();
+            }
+        }
+        self.popups.clear();
+        self.last_shot = None;
+        self.undo = None;
+        self.camera.reset();
+        self.left = rules::LIVES;
+        self.particles.clear();
+        self.clear_board();
+        if self.mode == Mode::Daily {
+            self.refresh_daily_date();
+        }
+        // the board was cleared above, so each hotseat round starts from
+        // nothing and gets the same spawns
+        self.rng = thread_rng().gen();
+        self.spawns = match self.mode {
+            Mode::Daily => Some(spawn::Sequence::new(
+                daily::seed(self.daily_date),
+                BLOCK_NUM,
+            )),
+            Mode::Hotseat => Some(spawn::Sequence::new(self.round_seed, BLOCK_NUM)),
+            _ => None,
+        };
+        self.spawn_roll = None;
+        if self.mode == Mode::Daily {
+            // the first start of the day is the scored one, even if abandoned
+            self.daily_scored =
+                self.daily_override.is_none() && self.save.daily.begin(self.daily_date);
+            if self.daily_scored {
+                self.write_save();
+            }
+        }
+        if self.mode == Mode::TimeAttack {
+            let r = self.run_timer.get_rect();
+            self.run_timer
+                .restore(self.time_limit as f64, r.x, r.y, r.w, r.h);
+            self.run_timer.start(ctx);
+        }
+        if self.mode == Mode::Levels {
+            self.shots_left = self.puzzles[self.puzzle].shots;
+            for index in self.puzzles[self.puzzle].cells(BLOCK_COUNT) {
+                self.spawn_block(ctx, index);
+            }
+        }
+        if self.mode.spawns_blocks() {
+            self.restore_timer();
+            self.block_generate_time_ticker.start(ctx);
+        }
+    }
+
+    // the pick only counts empty cells, so a block never lands on another
+    fn random_block(&mut self, ctx: &Context) {
+        if let Some(next) = self.spawns.as_mut().and_then(|q| q.next()) {
+            self.spawn_roll = Some(next.roll);
+            if let Some(index) = next.place(&self.block_index) {
+                self.spawn_block(ctx, index);
+            }
+            return;
+        }
+        if let Some(index) = rules::spawn_cell(&mut self.rng, &self.block_index) {
+            self.spawn_block(ctx, index);
+        }
+    }
+
+    fn spawn_block(&mut self, ctx: &Context, index: usize) {
+        let alive = rules::block_time(self.difficulty());
+        self.block_index[index] = true;
+        let pos = self.get_block_pos(index);
+        if let Some(block_item) = self.block_list.iter_mut().find(|b| b.block.is_stopped()) {
+            block_item.block.restore(alive, pos, self.delta_length);
+            block_item.block.set_scale(0.0);
             block_item.block.start(ctx);
-            self.block_list.push(block_item);
+            block_item.index = index;
+            block_item.appear.restart(0.0, 1.0);
+            return;
         }
+        let mut block_item = StateBlock {
+            index: index,
+            block: Block::new(alive, pos, self.delta_length),
+            appear: Tween::new(0.0, 1.0, BLOCK_APPEAR, Easing::Elastic),
+        };
+        block_item.block.set_style(self.block_style());
+        block_item.block.set_scale(0.0);
+        block_item.block.start(ctx);
+        self.block_list.push(block_item);
     }
 
-    fn throw_ball(&mut self,(x,y):(f32,f32),ctx:&Context){
-        let point = (x - self.window_size.0/2.0,y -self.window_size.1);
-        let point_len = (point.0*point.0+point.1*point.1).sqrt();
-        let max_vel = self.get_ball_max_vel();
-        let power = self.power_record_bar.get_value();
-        let power = Ball::get_vel_alpha(power,BALL_MAX_TIME);
-        let radius = self.delta_length*0.2;
-        let b_pos = (self.window_size.0/2.0,self.window_size.1);
-        let vel = (point.0/point_len*max_vel*power,point.1/point_len*max_vel*power,power);
-        if let Some(b) = self.ball_list.iter_mut().find(|b| !b.ball.is_avtive()){
-            b.ball.restore(radius,b_pos,vel);
-            self.ball_ready_timer.start(ctx);
+    // launch velocity of a ball thrown from `from` at (x,y) with the power
+    // bar at `charge`
+    fn shot_velocity(&self, from: (f32, f32), aim: (f32, f32), charge: f32) -> (f32, f32, f32) {
+        rules::shot_velocity(from, aim, charge, self.get_ball_max_vel())
+    }
+
+    // puts back the blocks the last landed ball destroyed
+    fn undo_shot(&mut self, ctx: &Context) {
+        if self.mode != Mode::Practice || self.status != GameStatus::Running {
             return;
         }
-        let b = StateBall{
-            ball:Ball::new(radius,b_pos,vel),
+        let undo = match self.undo.take() {
+            Some(u) => u,
+            None => return,
+        };
+        let width = self.delta_length;
+        for slot in undo.blocks {
+            let index = self.block_list[slot].index;
+            // the slot went to a new block in the meantime
+            if !self.block_list[slot].block.is_stopped() || self.block_index[index] {
+                continue;
+            }
+            let pos = self.get_block_pos(index);
+            let b = &mut self.block_list[slot];
+            b.block.restore(BLOCK_ALIVE, pos, width);
+            b.block.set_scale(1.0);
+            b.block.start(ctx);
+            self.block_index[index] = true;
+        }
+        self.players[0].score = self.players[0].score.saturating_sub(undo.score);
+        self.players[0].combo = undo.combo;
+        self.last_shot = None;
+    }
+
+    fn throw_ball(&mut self, player: usize, (x, y): (f32, f32), ctx: &Context) {
+        let radius = rules::ball_radius(self.delta_length);
+        let b_pos = self.players[player].launcher;
+        let charge = self.players[player].power_bar.get_value();
+        let vel = self.shot_velocity(b_pos, (x, y), charge);
+        if self.mode == Mode::Levels {
+            self.shots_left = self.shots_left.saturating_sub(1);
+        }
+        if let Some(b) = self.ball_list.iter_mut().find(|b| !b.ball.is_avtive()) {
+            b.charge = charge;
+            b.owner = player;
+            b.ball.set_color(self.theme.ball_color(player));
+            b.ball.restore(radius, b_pos, vel);
+            self.players[player].ready_timer.start(ctx);
+            return;
+        }
+        let mut b = StateBall {
+            ball: Ball::new(radius, b_pos, vel),
             extra_live_timer: Timer::new(0.5),
-            };
+            charge: charge,
+            owner: player,
+        };
+        b.ball.set_color(self.theme.ball_color(player));
         self.ball_list.push(b);
-        self.ball_ready_timer.start(ctx);
+        self.players[player].ready_timer.start(ctx);
+    }
+
+    fn start_charge(&mut self, ctx: &Context, player: usize) {
+        self.players[player].power_bar.start(ctx);
+        self.sould_effects.start_charge();
+    }
+
+    // stops the power bar and throws at the player's aim if a ball is ready
+    fn release_charge(&mut self, ctx: &mut Context, player: usize) {
+        self.players[player].power_bar.update(ctx);
+        self.players[player].power_bar.pause();
+        let has_shots = self.mode != Mode::Levels || self.shots_left > 0;
+        if self.status == GameStatus::Running && self.players[player].is_ready() && has_shots {
+            self.sould_effects.shot();
+            let aim = self.players[player].aim;
+            self.throw_ball(player, aim, ctx);
+        }
+    }
+
+    // held keys of the keyboard player; `down` is false on release
+    fn control_key(&mut self, ctx: &mut Context, action: Action, down: bool) {
+        let player = match self.keyboard_player() {
+            Some(i) => i,
+            None => return,
+        };
+        match action {
+            Action::AimLeft | Action::AimRight => {
+                let direction = if !down {
+                    0.0
+                } else if action == Action::AimLeft {
+                    -1.0
+                } else {
+                    1.0
+                };
+                self.players[player].set_turning(direction);
+            }
+            Action::Charge if down => {
+                if self.status == GameStatus::Running {
+                    self.start_charge(ctx, player);
+                }
+            }
+            Action::Charge => self.release_charge(ctx, player),
+            _ => {}
+        }
     }
 
-    fn update_running(&mut self,ctx:&mut Context) -> GameResult<()>{
+    // the clock-driven timers skip time that hit-stop held still
+    fn hold_timers(&mut self, seconds: f32) {
+        let seconds = f64::from(seconds);
+        self.block_generate_time_ticker.delay(seconds);
+        self.run_timer.delay(seconds);
+        for b in self.block_list.iter_mut() {
+            b.block.delay(seconds);
+        }
+        for p in self.players.iter_mut() {
+            p.delay(seconds);
+        }
+    }
+
+    fn update_running(&mut self, ctx: &mut Context) -> Result<()> {
+        let real_time = ((timer::get_delta(ctx)).subsec_millis() as f32) / 1.0e3;
+        // game time stands still during hit-stop
+        let delta_time = self.camera.update(real_time);
+        if delta_time < real_time {
+            self.hold_timers(real_time - delta_time);
+        }
         self.block_generate_time_ticker.update(ctx);
-        self.ball_ready_timer.update(ctx);
-        if self.ball_ready_timer.get_event(){
-            //ready sould
+        let reach = self.get_ball_max_range() * 0.5;
+        for p in self.players.iter_mut() {
+            p.turn(delta_time, reach);
+            p.update(ctx, delta_time);
         }
-        self.power_record_bar.update(ctx);
-        if self.power_record_bar.get_event(){
-            self.sould_effects.energy_up.stop();
-            self.sould_effects.energy_charge_reload(ctx);
+        for i in 0..self.players.len() {
+            if self.players[i].power_bar.get_event() {
+                self.sould_effects.stop_charge();
+            } else if self.players[i].is_charging() {
+                self.sould_effects
+                    .set_charge(self.players[i].power_bar.get_value());
+            }
         }
 
-        if self.block_generate_time_ticker.get_event(){
+        if self.block_generate_time_ticker.get_event() {
             self.random_block(ctx);
             self.restore_timer();
             self.block_generate_time_ticker.start(ctx);
         }
 
-        let delta_time =  ((timer::get_delta(ctx)).subsec_millis() as f32)/1.0e3; 
-        self.ball_list.iter_mut().for_each(|b| b.update(ctx,delta_time));
-        self.block_list.iter_mut().for_each(|b| b.block.update(ctx));
+        self.ball_list
+            .iter_mut()
+            .for_each(|b| b.update(ctx, delta_time));
+        if self.mode.blocks_expire() {
+            self.block_list.iter_mut().for_each(|b| b.block.update(ctx));
+        }
         //update block status
-        for b in self.block_list.iter_mut(){
-            if b.block.get_event(){
-                self.block_index[b.index] = false;
-                if self.left == 0{
+        let was_running = self.status == GameStatus::Running;
+        self.run_timer.update(ctx);
+        if self.run_timer.get_event() && was_running {
+            self.status = GameStatus::GameOver;
+        }
+        let has_lives = self.mode.has_lives();
+        for i in 0..self.block_list.len() {
+            if self.block_list[i].block.get_event() {
+                let index = self.block_list[i].index;
+                self.block_index[index] = false;
+                let r = self.block_list[i].block.get_rect();
+                self.block_lost(r);
+                if !has_lives {
+                    continue;
+                }
+                if self.left == 0 {
                     self.status = GameStatus::GameOver;
                     break;
                 }
-                self.left -=1;
+                self.left -= 1;
             }
         }
-        if self.status == GameStatus::GameOver{
+        if self.status == GameStatus::GameOver {
             self.game_over();
+            if was_running {
+                self.finish_run();
+            }
         }
 
         //if ball fall fown on ground
-        let mut sould_hit :u8= 0;
-        for b in self.ball_list.iter_mut().filter(|b| b.ball.is_avtive() && b.ball.is_on_ground()){
-            b.ball.disable();
-            sould_hit = sould_hit.max(1);
-            let mut is_hit = false;
-            for bk in self.block_list.iter_mut().filter(|bk| {
-                !bk.block.is_stopped() && bk.block.is_hit_cricle(b.ball.get_pos(),b.ball.get_radius())
-            }){
-                is_hit = true;
-                sould_hit = 2;
+        let mut landing = None;
+        let mut destroyed = 0;
+        let practice = self.mode == Mode::Practice;
+        let launcher = self.players[0].launcher;
+        let max_vel = self.get_ball_max_vel();
+        let landed: Vec<usize> = (0..self.ball_list.len())
+            .filter(|&i| {
+                self.ball_list[i].ball.is_avtive() && self.ball_list[i].ball.is_on_ground()
+            })
+            .collect();
+        for i in landed {
+            self.ball_list[i].ball.disable();
+            let (pos, radius) = (
+                self.ball_list[i].ball.get_pos(),
+                self.ball_list[i].ball.get_radius(),
+            );
+            let owner = self.ball_list[i].owner;
+            if practice {
+                if let Some(c) = nearest_block(&self.block_list, (pos.x, pos.y)) {
+                    let range = ((c.0 - launcher.0).powi(2) + (c.1 - launcher.1).powi(2)).sqrt();
+                    self.last_shot = Some(ShotReport {
+                        error: ((c.0 - pos.x).powi(2) + (c.1 - pos.y).powi(2)).sqrt(),
+                        charge: self.ball_list[i].charge,
+                        needed: Ball::charge_for_range(range, max_vel, BALL_MAX_TIME),
+                    });
+                }
+            }
+            let combo = self.players[owner].combo;
+            let mut slots = vec![];
+            let mut broken = vec![];
+            for (slot, bk) in self
+                .block_list
+                .iter_mut()
+                .enumerate()
+                .filter(|x| !x.1.block.is_stopped() && x.1.block.is_hit_cricle(pos, radius))
+            {
+                slots.push(slot);
                 bk.block.stop();
+                broken.push(bk.block.get_rect());
                 self.block_index[bk.index] = false;
-                self.score += 1;
             }
-            if !is_hit{
-                b.extra_live_timer.start(ctx);
+            let hits = slots.len();
+            self.ball_landed(owner, (pos.x, pos.y), &broken);
+            if hits == 0 {
+                self.ball_list[i].extra_live_timer.start(ctx);
+            }
+            if hits > 0 || landing.is_none() {
+                landing = Some((hits > 0, pos.x, owner));
+            }
+            destroyed += hits;
+            // a miss on no combo leaves nothing to take back
+            if practice && (hits > 0 || self.players[owner].combo != combo) {
+                self.undo = Some(Undo {
+                    blocks: slots,
+                    score: hits,
+                    combo: combo,
+                });
+            }
+        }
+
+        // a puzzle ends once it is cleared or the last ball has come down
+        if self.mode == Mode::Levels && self.status == GameStatus::Running {
+            let cleared = !self.block_index.iter().any(|&x| x);
+            let flying = self.ball_list.iter().any(|b| b.ball.is_avtive());
+            if cleared || (self.shots_left == 0 && !flying) {
+                self.status = GameStatus::GameOver;
+                self.game_over();
+                self.finish_run();
             }
         }
 
-        match sould_hit{
-            1 => {self.sould_effects.loss.play()?;},
-            2 => {self.sould_effects.boom.play()?;},
-            _=>{},
+        if destroyed > 0 {
+            self.camera.shake(0.3 * destroyed as f32);
+            self.camera.hit_stop(0.03 + 0.02 * destroyed as f32);
+        }
+        self.animate_board(delta_time);
+        if let Some((hit, x, owner)) = landing {
+            self.landing_sound(hit, x, owner);
         }
         Ok(())
     }
 
-    fn draw_game_ready(&mut self,ctx:&mut Context) -> GameResult<()>{
-        let s = "click to start";
-        let dest_point = graphics::Point2::new(
-            self.window_size.0 /2.0 - self.font.get_width(&s) as f32 /2.0,
-            self.window_size.1/2.0 -self.delta_length);
-        let text = graphics::Text::new(ctx, &s, &self.font)?;
-        graphics::set_color(ctx,graphics::Color::from_rgb(0,0,0))?;
-        graphics::draw(ctx, &text, dest_point, 0.0)?;
+    // a block that ran out of time, at `r`
+    fn block_lost(&mut self, r: graphics::Rect) {
+        self.particles.emit(
+            &Emitter::flash(self.theme.danger, self.delta_length),
+            r.x + r.w / 2.0,
+            r.y + r.h / 2.0,
+        );
+        self.camera.flash(self.theme.danger);
+    }
+
+    // scores a ball of `owner`'s that came down at `pos` and breaks the
+    // blocks at `broken`, which are already off the board
+    fn ball_landed(&mut self, owner: usize, pos: (f32, f32), broken: &[graphics::Rect]) {
+        if self.settings.landing_splash() {
+            self.particles.emit(
+                &Emitter::splash(self.theme.ball_color(owner), self.delta_length),
+                pos.0,
+                pos.1,
+            );
+        }
+        for r in broken.iter() {
+            self.particles.emit(
+                &Emitter::shards(self.theme.block, self.delta_length),
+                r.x + r.w / 2.0,
+                r.y + r.h / 2.0,
+            );
+        }
+        let hits = broken.len();
+        self.players[owner].score += hits;
+        self.players[owner].combo += hits;
+        if hits > 0 {
+            self.popups.push(Popup {
+                text: format!("+{}", hits),
+                pos: pos,
+                rise: Tween::new(0.0, 1.0, POPUP_TIME, Easing::EaseOut),
+            });
+        } else {
+            self.particles.emit(
+                &Emitter::dust(self.theme.launcher, self.delta_length),
+                pos.0,
+                pos.1,
+            );
+            self.players[owner].combo = 0;
+        }
+    }
+
+    // one landing sound a frame, a hit over a miss
+    fn landing_sound(&mut self, hit: bool, x: f32, owner: usize) {
+        let effect = if hit { Effect::Boom } else { Effect::Loss };
+        let combo = self.players[owner].combo;
+        self.sould_effects.landing(
+            effect,
+            sfx::landing_params(effect, x, self.world_size.0, combo),
+        );
+        self.music.duck();
+    }
+
+    // what moves on the board whatever is playing it
+    fn animate_board(&mut self, dt: f32) {
+        for b in self
+            .block_list
+            .iter_mut()
+            .filter(|b| !b.appear.is_finished())
+        {
+            b.appear.update(dt);
+            b.block.set_scale(b.appear.value());
+        }
+        self.particles.update(dt);
+        for p in self.popups.iter_mut() {
+            p.rise.update(dt);
+        }
+        self.popups.retain(|p| !p.rise.is_finished());
+    }
+
+    fn draw_menu(&mut self, ctx: &mut Context) -> GameResult<()> {
+        self.ui.draw(ctx, &mut self.text)
+    }
+
+    fn draw_game_over(&mut self, ctx: &mut Context) -> GameResult<()> {
+        // player 1's hotseat round is over, player 2 still to go
+        let handover = self.mode.takes_turns() && self.turn + 1 < self.players.len();
+        let score = self.players[0].score;
+        let s = match (self.mode, self.puzzle_stars) {
+            (Mode::Levels, Some(stars)) => format!("CLEARED {}", menu::stars_text(stars)),
+            (Mode::Levels, None) => "OUT OF SHOTS".to_owned(),
+            _ if handover => format!(
+                "PLAYER {} GOT:{}",
+                self.turn + 1,
+                self.players[self.turn].score
+            ),
+            (Mode::Hotseat, _) | (Mode::Versus, _) => {
+                format!("P1 {} : {} P2", score, self.players[1].score)
+            }
+            (Mode::Coop, _) => format!("TEAM GOT:{}", self.team_score()),
+            _ => format!("YOU GOT:{}", score),
+        };
+        let center_x = self.world_size.0 / 2.0;
+        graphics::set_color(ctx, self.theme.highlight)?;
+        self.text.draw_centered(
+            ctx,
+            &s,
+            center_x,
+            self.world_size.1 / 2.0 - self.delta_length,
+        )?;
+        if self.mode == Mode::Daily {
+            let s = format!(
+                "{} streak: {} days",
+                if self.daily_scored {
+                    "scored,"
+                } else {
+                    "not scored,"
+                },
+                self.save.daily.streak(self.daily_date)
+            );
+            graphics::set_color(ctx, self.theme.text)?;
+            self.text
+                .draw_centered(ctx, &s, center_x, self.world_size.1 / 2.0)?;
+        }
+        if self.mode == Mode::TimeAttack {
+            let per_minute = score as f32 * 60.0 / self.time_limit as f32;
+            let s = format!("{:.1} hits per minute in {}s", per_minute, self.time_limit);
+            graphics::set_color(ctx, self.theme.text)?;
+            self.text
+                .draw_centered(ctx, &s, center_x, self.world_size.1 / 2.0)?;
+        }
+        if self.players.len() > 1 {
+            let s = match player::winner(&self.players) {
+                _ if self.mode.shares_score() => {
+                    format!("P1 {} + {} P2", score, self.players[1].score)
+                }
+                _ if handover => format!("player {}'s turn", self.turn + 2),
+                Some(i) => format!("player {} wins", i + 1),
+                None => "a draw".to_owned(),
+            };
+            graphics::set_color(ctx, self.theme.text)?;
+            self.text
+                .draw_centered(ctx, &s, center_x, self.world_size.1 / 2.0)?;
+        }
+
+        let s = if handover {
+            "click to start"
+        } else {
+            "click to restart"
+        };
+        graphics::set_color(ctx, self.theme.text)?;
+        self.text.draw_centered(
+            ctx,
+            s,
+            center_x,
+            self.world_size.1 / 2.0 + self.delta_length,
+        )?;
+        let s = "esc for menu";
+        self.text.draw_centered(
+            ctx,
+            s,
+            center_x,
+            self.world_size.1 / 2.0 + 2.0 * self.delta_length,
+        )?;
+        Ok(())
+    }
+
+    // landing error and charge readout under the board
+    fn draw_practice(&mut self, ctx: &mut Context) -> GameResult<()> {
+        let center_x = self.world_size.0 / 2.0;
+        let mut y = (BLOCK_COUNT + 2) as f32 * self.delta_length + 0.4 * self.delta_length;
+        let line = self.text.height() * 1.2;
+        graphics::set_color(ctx, self.theme.text)?;
+        if let Some(ref shot) = self.last_shot {
+            let s = format!("off by {:.2} blocks", shot.error / self.delta_length);
+            self.text.draw_centered(ctx, &s, center_x, y)?;
+            y += line;
+            let s = match shot.needed {
+                Some(needed) => format!(
+                    "charge {:.0}%, needed {:.0}%",
+                    shot.charge * 100.0,
+                    needed * 100.0
+                ),
+                None => format!(
+                    "charge {:.0}%, nearest block out of reach",
+                    shot.charge * 100.0
+                ),
+            };
+            self.text.draw_centered(ctx, &s, center_x, y)?;
+            y += line;
+        }
+        if self.undo.is_some() {
+            let s = format!("{} to undo", self.settings.keys.get(Action::Undo));
+            self.text.draw_centered(ctx, &s, center_x, y)?;
+        }
         Ok(())
     }
 
-    fn draw_game_over(&mut self,ctx:&mut Context) -> GameResult<()>{
-        let s = format!("YOU GOT:{}",self.score);
-        let mut dest_point = graphics::Point2::new(
-            self.window_size.0 /2.0 - self.font.get_width(&s) as f32 /2.0,
-            self.window_size.1/2.0 -self.delta_length);
-        let text = graphics::Text::new(ctx, &s, &self.font)?;
-        graphics::set_color(ctx,graphics::Color::from_rgb(0,205,102))?;
-        graphics::draw(ctx, &text, dest_point, 0.0)?;
-
-        let s = "click to restart";
-        dest_point.x = self.window_size.0 /2.0 - self.font.get_width(&s) as f32 /2.0;
-        dest_point.y = self.window_size.1/2.0 +self.delta_length;
-        let text = graphics::Text::new(ctx, &s, &self.font)?;
-        graphics::set_color(ctx,graphics::Color::from_rgb(0,0,0))?;
-        graphics::draw(ctx, &text, dest_point, 0.0)?;
+    // a LAN game on the board a local versus game uses, with the time left
+    // and which launcher is ours
+    fn draw_lan(&mut self, ctx: &mut Context) -> GameResult<()> {
+        self.draw_game_running(ctx)?;
+        let (seconds, local) = match self.lan {
+            Some(ref game) => (game.sim().seconds_left(), game.local()),
+            None => return Ok(()),
+        };
+        let dl = self.delta_length;
+        graphics::set_color(ctx, self.theme.text)?;
+        self.text.draw_centered(
+            ctx,
+            &format!("{}s", seconds),
+            self.hud.x + self.hud.w / 2.0,
+            dl * 1.1,
+        )?;
+        let launcher = self.players[local].launcher;
+        self.text
+            .draw_centered(ctx, "you", launcher.0, launcher.1 - dl * 1.2)?;
+        Ok(())
+    }
+
+    // a race on the board a local game uses, with the ghost's game in
+    // miniature in the bottom corner
+    fn draw_race(&mut self, ctx: &mut Context) -> GameResult<()> {
+        self.draw_game_running(ctx)?;
+        let dl = self.delta_length;
+        let hud = self.hud;
+        let (seconds, lead) = match self.race {
+            Some(ref race) => (
+                race.sim.seconds_left(),
+                race.ghost_score().map(|g| race.score() as i64 - g as i64),
+            ),
+            None => return Ok(()),
+        };
+        graphics::set_color(ctx, self.theme.text)?;
+        self.text
+            .draw_centered(ctx, &format!("{}s", seconds), hud.x + hud.w / 2.0, dl * 1.1)?;
+        let lead = match lead {
+            Some(lead) => lead,
+            None => return Ok(()),
+        };
+        let (w, h) = (
+            self.world_size.0 * GHOST_SCALE,
+            self.world_size.1 * GHOST_SCALE,
+        );
+        let corner = (hud.x + 0.1 * dl, hud.y + hud.h - h - 0.1 * dl);
+        let line_width = self.line_width();
+        graphics::set_color(ctx, self.theme.background)?;
+        graphics::rectangle(
+            ctx,
+            graphics::DrawMode::Fill,
+            graphics::Rect::new(corner.0, corner.1, w, h),
+        )?;
+        graphics::set_color(ctx, self.theme.board_rim)?;
+        graphics::rectangle(
+            ctx,
+            graphics::DrawMode::Line(line_width),
+            graphics::Rect::new(corner.0, corner.1, w, h),
+        )?;
+        let s = format!("ghost: {:.0} ({:+})", self.ghost_shown.value(), lead);
+        graphics::set_color(ctx, self.theme.score)?;
+        self.text.draw(
+            ctx,
+            &s,
+            graphics::Point2::new(corner.0, corner.1 - self.text.height() * 1.2),
+        )?;
+        // the whole world shrunk into the panel, so the ghost's blocks and
+        // balls draw where the full size ones would
+        let v = self.view.visible();
+        graphics::set_screen_coordinates(
+            ctx,
+            graphics::Rect::new(
+                v.x - (corner.0 - v.x) / GHOST_SCALE,
+                v.y - (corner.1 - v.y) / GHOST_SCALE,
+                v.w / GHOST_SCALE,
+                v.h / GHOST_SCALE,
+            ),
+        )?;
+        let (x0, y0) = rules::cell_pos(self.world_size.0, dl, 0);
+        let side = dl * BLOCK_COUNT as f32;
+        let rim = line_width / GHOST_SCALE;
+        graphics::set_color(ctx, self.theme.board_rim)?;
+        graphics::rectangle(
+            ctx,
+            graphics::DrawMode::Line(rim),
+            graphics::Rect::new(x0 - rim, y0 - rim, side + 2.0 * rim, side + 2.0 * rim),
+        )?;
+        for block in self.ghost_board.blocks.iter() {
+            block.1.draw(ctx)?;
+        }
+        for b in self.ghost_board.balls.iter() {
+            b.draw(ctx)?;
+        }
+        self.camera.apply(ctx, &self.view)
+    }
+
+    fn draw_lan_over(&mut self, ctx: &mut Context) -> GameResult<()> {
+        let center = (self.world_size.0 / 2.0, self.world_size.1 / 2.0);
+        let dl = self.delta_length;
+        let (score, result) = match self.lan {
+            Some(ref game) => {
+                let sim = game.sim();
+                let (me, them) = (
+                    sim.players[game.local()].score,
+                    sim.players[1 - game.local()].score,
+                );
+                let result = if me > them {
+                    "you win"
+                } else if me < them {
+                    "you lose"
+                } else {
+                    "a draw"
+                };
+                (
+                    format!("P1 {} : {} P2", sim.players[0].score, sim.players[1].score),
+                    result,
+                )
+            }
+            None => return Ok(()),
+        };
+        graphics::set_color(ctx, self.theme.highlight)?;
+        self.text
+            .draw_centered(ctx, &score, center.0, center.1 - dl)?;
+        let s = self.lan_error.clone().unwrap_or_else(|| result.to_owned());
+        graphics::set_color(ctx, self.theme.text)?;
+        self.text.draw_centered(ctx, &s, center.0, center.1)?;
+        self.text
+            .draw_centered(ctx, "esc for menu", center.0, center.1 + dl)?;
         Ok(())
     }
 
-    fn draw_game_running(&mut self,ctx:&mut Context) -> GameResult<()>{
+    fn draw_race_over(&mut self, ctx: &mut Context) -> GameResult<()> {
+        let center = (self.world_size.0 / 2.0, self.world_size.1 / 2.0);
+        let dl = self.delta_length;
+        let (score, result) = match self.race {
+            Some(ref race) => {
+                let score = race.score();
+                let result = match race.ghost {
+                    _ if self.race_saved => "new best on this seed, replay saved".to_owned(),
+                    Some(ref g) if g.replay.score == score => "tied with the ghost".to_owned(),
+                    Some(ref g) => format!("the ghost wins by {}", g.replay.score - score),
+                    None => "nothing to keep".to_owned(),
+                };
+                (format!("YOU GOT:{}", score), result)
+            }
+            None => return Ok(()),
+        };
+        graphics::set_color(ctx, self.theme.highlight)?;
+        self.text
+            .draw_centered(ctx, &score, center.0, center.1 - dl)?;
+        graphics::set_color(ctx, self.theme.text)?;
+        self.text.draw_centered(ctx, &result, center.0, center.1)?;
+        self.text.draw_centered(
+            ctx,
+            "click to race again, esc for menu",
+            center.0,
+            center.1 + dl,
+        )?;
+        Ok(())
+    }
 
+    fn draw_game_running(&mut self, ctx: &mut Context) -> GameResult<()> {
         //draw the rim
+        let line_width = self.line_width();
         let mut rect = graphics::Rect::new(
-            self.get_left_point()-1.0 ,
-            2.0*self.delta_length-1.0,
-            self.delta_length*BLOCK_COUNT as f32 +2.0,
-            self.delta_length*BLOCK_COUNT as f32 +2.0
+            self.get_left_point() - line_width,
+            2.0 * self.delta_length - line_width,
+            self.delta_length * BLOCK_COUNT as f32 + 2.0 * line_width,
+            self.delta_length * BLOCK_COUNT as f32 + 2.0 * line_width,
+        );
+        graphics::set_color(ctx, self.theme.board_rim)?;
+        graphics::rectangle(ctx, graphics::DrawMode::Line(line_width), rect)?;
+        if self.mode.has_lives() {
+            //draw the left life
+            graphics::set_color(ctx, self.theme.lives)?;
+            rect.y = self.hud.y + 0.1 * self.delta_length;
+            rect.w = self.delta_length * 0.6;
+            rect.h = self.delta_length * 0.6;
+            for i in 0..self.left {
+                rect.x = self.hud.x + i as f32 * self.delta_length * 0.7 + 0.1 * self.delta_length;
+                graphics::rectangle(ctx, graphics::DrawMode::Fill, rect)?;
+            }
+        }
+        if self.mode == Mode::Levels {
+            let s = format!("shots: {}", self.shots_left);
+            let dest = graphics::Point2::new(
+                self.hud.x + 0.2 * self.delta_length,
+                self.hud.y + 0.2 * self.delta_length,
+            );
+            graphics::set_color(ctx, self.theme.text)?;
+            self.text.draw(ctx, &s, dest)?;
+            let name = self.puzzles[self.puzzle].name.clone();
+            self.text.draw_centered(
+                ctx,
+                &name,
+                self.world_size.0 / 2.0,
+                self.delta_length * 1.1,
+            )?;
+        }
+        if self.mode == Mode::TimeAttack {
+            self.run_timer.draw(ctx)?;
+            let left = (self.time_limit as f32 * (1.0 - self.run_timer.get_value()))
+                .max(0.0)
+                .ceil();
+            let dest = graphics::Point2::new(
+                self.hud.x + 0.2 * self.delta_length,
+                self.hud.y + 0.2 * self.delta_length,
             );
-        graphics::rectangle(ctx,graphics::DrawMode::Line(1.0),rect)?;
-        //draw the left life
-        graphics::set_color(ctx,graphics::Color::from_rgb(0,205,205))?;
-        rect.y = 0.1*self.delta_length;rect.w = self.delta_length*0.6;rect.h=self.delta_length*0.6;
-        for i in 0..self.left{
-            rect.x = i as f32* self.delta_length*0.7 + 0.1*self.delta_length ;
-            graphics::rectangle(ctx,graphics::DrawMode::Fill,rect)?;
+            graphics::set_color(ctx, self.theme.text)?;
+            self.text.draw(ctx, &format!("{}s", left), dest)?;
         }
         //draw the score
-        let s = format!("Score: {}", self.score);
-        let dest_point = graphics::Point2::new(
-            self.window_size.0  - self.font.get_width(&s) as f32 - self.delta_length,
-            0.2*self.delta_length);
-        let text = graphics::Text::new(ctx, s.as_str(), &self.font)?;
-        graphics::set_color(ctx,graphics::Color::from_rgb(110,123,139))?;
-        graphics::draw(ctx, &text, dest_point, 0.0)?;
-
-
+        graphics::set_color(ctx, self.theme.score)?;
+        let scores: Vec<(String, bool)> = if self.mode.shares_score() {
+            let shown: f32 = self.players.iter().map(|p| p.score_shown.value()).sum();
+            vec![(format!("Score: {}", shown.round() as usize), false)]
+        } else {
+            (0..self.players.len())
+                .filter(|&i| self.is_active(i))
+                .map(|i| {
+                    let shown = self.players[i].score_shown.value().round() as usize;
+                    let s = if self.players.len() > 1 {
+                        format!("P{}: {}", i + 1, shown)
+                    } else {
+                        format!("Score: {}", shown)
+                    };
+                    // player 1 of a versus game keeps to the left, under the lives
+                    (s, self.side_by_side() && i == 0)
+                })
+                .collect()
+        };
+        for (s, left) in scores {
+            let dest_point = if left {
+                graphics::Point2::new(
+                    self.hud.x + 0.2 * self.delta_length,
+                    self.hud.y + 0.9 * self.delta_length,
+                )
+            } else {
+                graphics::Point2::new(
+                    self.hud.x + self.hud.w - self.text.width(&s) - self.delta_length,
+                    self.hud.y + 0.2 * self.delta_length,
+                )
+            };
+            self.text.draw(ctx, &s, dest_point)?;
+        }
 
-        //draw power bar
-        self.power_record_bar.draw(ctx)?;
+        if self.mode == Mode::Practice {
+            self.draw_practice(ctx)?;
+        }
 
-        //draw the flag ball
-        let ball_pos = graphics::Point2::new(self.window_size.0/2.0,self.window_size.1);
-        graphics::set_color(ctx,graphics::Color::from_rgb(112,128,144))?;
-        if self.ball_ready_timer.is_stopped(){
-            graphics::circle(ctx,graphics::DrawMode::Fill,ball_pos,self.delta_length*0.3,1.0)?;
-        }else{
-            graphics::circle(ctx,graphics::DrawMode::Line(1.0),ball_pos,self.delta_length*0.3,1.0)?;
+        //draw power bars and the flag balls
+        let tolerance = self.circle_tolerance();
+        for i in 0..self.players.len() {
+            if !self.is_active(i) {
+                continue;
+            }
+            let p = &self.players[i];
+            p.power_bar.draw(ctx)?;
+            p.draw_launcher(
+                ctx,
+                self.theme.launcher,
+                self.delta_length * 0.3,
+                line_width,
+                tolerance,
+            )?;
+            if self.settings.aim_preview && p.is_charging() && p.is_ready() {
+                let vel = self.shot_velocity(p.launcher, p.aim, p.power_bar.get_value());
+                let (x, y) = Ball::landing_point(p.launcher, vel);
+                graphics::set_color(ctx, self.theme.highlight)?;
+                graphics::circle(
+                    ctx,
+                    graphics::DrawMode::Line(line_width),
+                    graphics::Point2::new(x, y),
+                    self.delta_length * 0.2,
+                    tolerance,
+                )?;
+            }
         }
 
         //draw block
-        for b in self.block_list.iter(){
+        for b in self.block_list.iter() {
             b.block.draw(ctx)?;
         }
+        self.particles.draw(ctx)?;
+        for p in self.popups.iter() {
+            let t = p.rise.value();
+            let mut color = self.theme.highlight;
+            color.a *= 1.0 - p.rise.progress();
+            graphics::set_color(ctx, color)?;
+            let y = p.pos.1 - self.delta_length * (0.5 + 1.5 * t);
+            self.text.draw_centered(ctx, &p.text, p.pos.0, y)?;
+        }
         //draw the flying ball
+        let shadow = graphics::Color {
+            a: 0.25,
+            ..self.theme.board_rim
+        };
         for b in self.ball_list.iter().filter(|b| {
-            (b.ball.is_avtive()|| !b.extra_live_timer.is_stopped())&&b.ball.get_pos().y > self.delta_length
-            }){
+            (b.ball.is_avtive() || !b.extra_live_timer.is_stopped())
+                && b.ball.get_pos().y > self.delta_length
+        }) {
+            if b.ball.is_avtive() {
+                if self.settings.effects.ball_shadow {
+                    b.ball.draw_shadow(ctx, shadow)?;
+                }
+                if self.settings.ball_trail() {
+                    b.ball.draw_trail(ctx)?;
+                }
+            }
             b.ball.draw(ctx)?;
         }
 
@@ -367,48 +2185,232 @@ impl GameState {
 
 impl event::EventHandler for GameState {
     fn update(&mut self, ctx: &mut Context) -> GameResult<()> {
-        self.update_running(ctx)?;
+        if let Some(e) = self.pending_error.take() {
+            return Err(e.into());
+        }
+        let delta_time = ((timer::get_delta(ctx)).subsec_millis() as f32) / 1.0e3;
+        if self.lan.is_some() {
+            // the simulation moves at its own rate, whatever the frame rate
+            while timer::check_update_time(ctx, sim::TICK_RATE) {
+                self.update_lan(ctx);
+            }
+            self.update_sim_board(ctx);
+        } else if self.race.is_some() {
+            while timer::check_update_time(ctx, sim::TICK_RATE) {
+                self.update_race(ctx);
+            }
+            self.update_sim_board(ctx);
+            self.ghost_shown.update(delta_time);
+        } else {
+            self.update_menu(ctx)?;
+            self.update_running(ctx)?;
+        }
+        self.update_music(delta_time)?;
         timer::yield_now();
         Ok(())
     }
 
     fn draw(&mut self, ctx: &mut Context) -> GameResult<()> {
+        self.camera.apply(ctx, &self.view)?;
         graphics::clear(ctx);
-        graphics::set_background_color(ctx,graphics::Color::new(1f32,1f32,1f32,1f32));
+        graphics::set_background_color(ctx, self.theme.background);
         match self.status {
-            GameStatus::Ready => {self.draw_game_ready(ctx)?;},
-            GameStatus::Running => {self.draw_game_running(ctx)?;},
-            GameStatus::GameOver =>{self.draw_game_over(ctx)?;},
-        }        
+            GameStatus::Running if self.lan.is_some() => {
+                self.draw_lan(ctx)?;
+            }
+            GameStatus::GameOver if self.lan.is_some() => {
+                self.draw_lan_over(ctx)?;
+            }
+            GameStatus::Running if self.race.is_some() => {
+                self.draw_race(ctx)?;
+            }
+            GameStatus::GameOver if self.race.is_some() => {
+                self.draw_race_over(ctx)?;
+            }
+            GameStatus::Running => {
+                self.draw_game_running(ctx)?;
+            }
+            GameStatus::GameOver => {
+                self.draw_game_over(ctx)?;
+            }
+            _ => {
+                self.draw_menu(ctx)?;
+            }
+        }
+        self.camera.draw_flash(ctx, &self.view)?;
         graphics::present(ctx);
         Ok(())
     }
 
-    fn mouse_button_down_event(&mut self, _ctx: &mut Context, button: event::MouseButton, _x: i32, _y: i32) {
-        if self.status == GameStatus::Running && button == event::MouseButton::Left{
-            self.power_record_bar.start(_ctx);
-            
-            self.sould_effects.energy_up.play().unwrap();
+    fn key_down_event(
+        &mut self,
+        ctx: &mut Context,
+        keycode: event::Keycode,
+        _keymod: event::Mod,
+        repeat: bool,
+    ) {
+        // escape cancels a rebind; a key that would put a hotkey on one the
+        // menus use is refused and capture goes on
+        if let Some(action) = self.capturing.take() {
+            if keycode == event::Keycode::Escape {
+                return;
+            }
+            let menu_keys: Vec<String> = UiKey::KEYS.iter().map(|k| k.name()).collect();
+            if self
+                .settings
+                .keys
+                .try_set(action, &keycode.name(), &menu_keys)
+            {
+                self.save_settings();
+            } else {
+                self.capturing = Some(action);
+            }
+            return;
+        }
+        if self.in_menu() {
+            if let Some(key) = UiKey::from_keycode(keycode) {
+                self.ui.key(key);
+            }
+        }
+        match keycode {
+            event::Keycode::Escape if self.status == GameStatus::Title => {
+                if let Err(e) = ctx.quit() {
+                    self.pending_error = Some(e.into());
+                }
+            }
+            event::Keycode::Escape if !self.in_menu() => {
+                // a run left part way still counts, as if it had ended there,
+                // unless the mode only scores finished runs
+                if self.status == GameStatus::Running && !self.on_sim() {
+                    self.game_over();
+                    if self.mode.scores_early_exit() {
+                        self.finish_run();
+                    }
+                }
+                self.show_title();
+            }
+            event::Keycode::Escape => {}
+            _ => match self.settings.keys.action_for(&keycode.name()) {
+                // key repeat would restart the charge
+                Some(action) if action.is_held() => {
+                    if !repeat {
+                        self.control_key(ctx, action, true);
+                    }
+                }
+                // the menus have their own keys
+                Some(action) => {
+                    if !self.in_menu() {
+                        if let Err(e) = self.run_action(ctx, action) {
+                            self.pending_error = Some(e);
+                        }
+                    }
+                }
+                None => {}
+            },
+        }
+    }
+
+    fn key_up_event(
+        &mut self,
+        ctx: &mut Context,
+        keycode: event::Keycode,
+        _keymod: event::Mod,
+        _repeat: bool,
+    ) {
+        if let Some(action) = self.settings.keys.action_for(&keycode.name()) {
+            if action.is_held() {
+                self.control_key(ctx, action, false);
+            }
+        }
+    }
+
+    fn resize_event(&mut self, ctx: &mut Context, _width: u32, _height: u32) {
+        if let Err(e) = self.window_changed(ctx) {
+            self.pending_error = Some(e);
+        }
+    }
+
+    fn mouse_motion_event(
+        &mut self,
+        _ctx: &mut Context,
+        _state: event::MouseState,
+        x: i32,
+        y: i32,
+        _xrel: i32,
+        _yrel: i32,
+    ) {
+        let p = self.view.to_world(x as f32, y as f32);
+        self.ui.mouse_motion(p);
+        self.sim_input.aim = (p.x, p.y);
+        // the simulation aims the launchers of its games
+        if self.on_sim() {
+            return;
+        }
+        for pl in self
+            .players
+            .iter_mut()
+            .filter(|pl| pl.control == Control::Mouse)
+        {
+            pl.aim = (p.x, p.y);
+        }
+    }
+
+    fn mouse_button_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        button: event::MouseButton,
+        _x: i32,
+        _y: i32,
+    ) {
+        if self.on_sim() {
+            if button == event::MouseButton::Left {
+                self.sim_button.set(true);
+            }
+            return;
+        }
+        if self.in_menu() && button == event::MouseButton::Left {
+            let p = self.view.to_world(_x as f32, _y as f32);
+            self.ui.mouse_down(p);
+        }
+        if self.status == GameStatus::Running && button == event::MouseButton::Left {
+            if let Some(i) = self.mouse_player() {
+                self.start_charge(_ctx, i);
+            }
         }
     }
 
-    fn mouse_button_up_event(&mut self, _ctx: &mut Context, button: event::MouseButton, x: i32, y: i32) {        
-        
-        self.power_record_bar.update(_ctx);
-        self.power_record_bar.pause();
-        if self.status == GameStatus::Running && button == event::MouseButton::Left && self.ball_ready_timer.is_stopped(){
-            //sould effetc
-            self.sould_effects.shot.play().unwrap();
-            if self.sould_effects.energy_up.playing(){
-                self.sould_effects.energy_up.stop();
-                self.sould_effects.energy_charge_reload(_ctx);
+    fn mouse_button_up_event(
+        &mut self,
+        _ctx: &mut Context,
+        button: event::MouseButton,
+        x: i32,
+        y: i32,
+    ) {
+        if self.on_sim() {
+            if button == event::MouseButton::Left {
+                self.sim_button.set(false);
+                if self.race.is_some() && self.status == GameStatus::GameOver {
+                    self.restart_race();
+                }
+            }
+            return;
+        }
+
+        if button == event::MouseButton::Left {
+            if let Some(i) = self.mouse_player() {
+                let p = self.view.to_world(x as f32, y as f32);
+                self.players[i].aim = (p.x, p.y);
+                self.release_charge(_ctx, i);
             }
-            self.throw_ball((x as f32,y as f32),_ctx);
-            
         }
 
-        if self.status != GameStatus::Running && button == event::MouseButton::Left{
-            self.game_restart(_ctx);
+        if self.in_menu() && button == event::MouseButton::Left {
+            let p = self.view.to_world(x as f32, y as f32);
+            self.ui.mouse_up(p);
+        }
+
+        if self.status == GameStatus::GameOver && button == event::MouseButton::Left {
+            self.next_round(_ctx);
         }
     }
-}
\ No newline at end of file
+}
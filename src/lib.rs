@@ -1,10 +1,40 @@
 extern crate ggez;
 extern crate rand;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate chrono;
+extern crate rodio;
+extern crate toml;
+extern crate zip;
+pub mod assets;
 pub mod ball;
 pub mod bar;
-pub mod timer;
 pub mod block;
+pub mod camera;
+pub mod daily;
+pub mod error;
 pub mod gamestate;
+pub mod menu;
+pub mod mode;
+pub mod music;
+pub mod net;
+pub mod particle;
+pub mod player;
+pub mod puzzle;
+pub mod replay;
+pub mod rules;
+pub mod save;
+pub mod settings;
+pub mod sfx;
+pub mod sim;
+pub mod spawn;
+pub mod text;
+pub mod theme;
+pub mod timer;
+pub mod tween;
+pub mod ui;
+pub mod view;
 
 #[cfg(test)]
 mod tests {
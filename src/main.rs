@@ -1,16 +1,136 @@
-extern crate ggez;
 extern crate fallingball;
+extern crate ggez;
+
+use std::net::TcpListener;
+
+use fallingball::daily;
+use fallingball::error::{Error, Result};
+use fallingball::net::{self, Connection, Lockstep};
+use fallingball::settings::{Difficulty, Settings};
+use fallingball::sim;
+
+// which end of a LAN game this instance is
+enum Lan {
+    Host(u16),
+    // "host" or "host:port"
+    Join(String),
+}
+
+struct Args {
+    date: Option<daily::NaiveDate>,
+    lan: Option<Lan>,
+}
+
+// `--date YYYY-MM-DD` plays that day's daily challenge, `--host [PORT]` and
+// `--join HOST[:PORT]` start a LAN versus game
+fn parse_args() -> Result<Args> {
+    let mut args = std::env::args().skip(1).peekable();
+    let mut parsed = Args {
+        date: None,
+        lan: None,
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--date" => {
+                let s = args
+                    .next()
+                    .ok_or_else(|| Error::Config("--date needs a YYYY-MM-DD value".to_owned()))?;
+                parsed.date = Some(daily::parse_date(&s)?);
+            }
+            "--host" => {
+                let port = match args.peek() {
+                    Some(p) if !p.starts_with("--") => Some(
+                        p.parse()
+                            .map_err(|_| Error::Config(format!("bad port {:?}", p)))?,
+                    ),
+                    _ => None,
+                };
+                if port.is_some() {
+                    args.next();
+                }
+                parsed.lan = Some(Lan::Host(port.unwrap_or(net::DEFAULT_PORT)));
+            }
+            "--join" => {
+                let host = args
+                    .next()
+                    .ok_or_else(|| Error::Config("--join needs a host to connect to".to_owned()))?;
+                let addr = if host.contains(':') {
+                    host
+                } else {
+                    format!("{}:{}", host, net::DEFAULT_PORT)
+                };
+                parsed.lan = Some(Lan::Join(addr));
+            }
+            _ => return Err(Error::Config(format!("unknown argument {:?}", arg))),
+        }
+    }
+    Ok(parsed)
+}
 
-fn main(){
+// connects before the window opens; the host waits here for the other player
+// and sets the difficulty both play at
+fn connect(lan: &Lan, difficulty: Difficulty) -> Result<Lockstep> {
+    match *lan {
+        Lan::Host(port) => {
+            let listener = TcpListener::bind(("0.0.0.0", port))
+                .map_err(|e| Error::Net(format!("can't listen on port {}: {}", port, e)))?;
+            eprintln!("falling ball: waiting for a player on port {}", port);
+            let seed = sim::random_seed();
+            let conn = Connection::accept(&listener, seed, difficulty)?;
+            Lockstep::new(conn, seed, difficulty, 0)
+        }
+        Lan::Join(ref addr) => {
+            let (conn, seed, difficulty) = Connection::connect(addr.as_str())?;
+            Lockstep::new(conn, seed, difficulty, 1)
+        }
+    }
+}
+
+fn run() -> Result<()> {
+    let args = parse_args()?;
+    // preferences are read before the window exists so it opens the right way
+    let prefs = match Settings::path() {
+        Some(p) => Settings::load_file(&p)?,
+        None => None,
+    };
+    let lan = match args.lan {
+        Some(ref lan) => Some(connect(
+            lan,
+            prefs
+                .as_ref()
+                .map_or(Difficulty::default(), |s| s.difficulty),
+        )?),
+        None => None,
+    };
     let mut c = ggez::conf::Conf::new();
 
     c.window_mode.width = 400;
-    let ctx = &mut ggez::Context::load_from_conf("falling ball", "ggez", c).unwrap();
+    c.window_mode.height = 600;
+    c.window_setup.resizable = true;
+    c.window_setup.allow_highdpi = true;
+    if prefs.as_ref().map_or(false, |s| s.fullscreen) {
+        c.window_mode.fullscreen_type = ggez::conf::FullscreenType::Desktop;
+    }
+    let ctx = &mut ggez::Context::load_from_conf("falling ball", "ggez", c)?;
     if let Ok(manifest_dir) = std::env::var("CARGO_MANIFEST_DIR") {
         let mut path = std::path::PathBuf::from(manifest_dir);
         path.push("resources");
         ctx.filesystem.mount(&path, true);
     }
-    let state = &mut fallingball::gamestate::GameState::new(ctx).unwrap();
-    ggez::event::run(ctx, state).unwrap();
-}
\ No newline at end of file
+    let state = &mut fallingball::gamestate::GameState::new(ctx, prefs)?;
+    if let Some(date) = args.date {
+        state.set_daily_date(date);
+    }
+    if let Some(game) = lan {
+        state.start_lan(game);
+    }
+    ggez::event::run(ctx, state)?;
+    Ok(())
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("falling ball: {}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,462 @@
+use ggez::graphics::Rect;
+
+use chrono::NaiveDate;
+
+use super::daily::{self, DailyHistory};
+use super::mode::{Board, Mode, TIME_ATTACK_LENGTHS};
+use super::puzzle::Puzzle;
+use super::replay::Replay;
+use super::save::SaveData;
+use super::settings::{self, Action, KeyBindings, Settings};
+use super::ui::{Anchor, Stack, Ui};
+
+// What the player picked on one of the menu screens.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum MenuAction {
+    Start(Mode),
+    // a time attack round of this many seconds
+    TimeAttack(u32),
+    // index into the puzzle list
+    Puzzle(usize),
+    PlayDaily,
+    // race the ghost at this index into the replay list, or a new seed
+    Race(Option<usize>),
+    Leaderboard,
+    NextBoard,
+    Settings,
+    KeyBindings,
+    // wait for the next key press and bind it to the action
+    Capture(Action),
+    ResetKeys,
+    Quit,
+    Back,
+}
+
+// keyboard focus for the title menu's button of `mode`
+pub fn title_focus(mode: Mode) -> usize {
+    Mode::ALL.iter().position(|&m| m == mode).unwrap_or(0)
+}
+
+// One frame of the title screen; `row` is the height of one button.
+pub fn title(ui: &mut Ui, area: Rect, row: f32, save: &SaveData) -> Option<MenuAction> {
+    let spacing = row * 0.3;
+    let rows = Mode::ALL.len() + 5;
+    let mut stack = Stack::anchored(area, Anchor::Center, row * 6.0, rows, row, spacing);
+    let mut action = None;
+    ui.begin();
+    ui.label(stack.next(row), "falling ball");
+    ui.label(
+        stack.next(row),
+        &format!("last: {}   best: {}", save.last_score, save.best_score),
+    );
+    for &mode in Mode::ALL.iter() {
+        if ui.button(stack.next(row), mode.title()) {
+            action = Some(MenuAction::Start(mode));
+        }
+    }
+    if ui.button(stack.next(row), "Leaderboard") {
+        action = Some(MenuAction::Leaderboard);
+    }
+    if ui.button(stack.next(row), "Settings") {
+        action = Some(MenuAction::Settings);
+    }
+    if ui.button(stack.next(row), "Quit") {
+        action = Some(MenuAction::Quit);
+    }
+    ui.end();
+    action
+}
+
+// keyboard focus for the time attack button of `seconds`
+pub fn time_attack_focus(seconds: u32) -> usize {
+    TIME_ATTACK_LENGTHS
+        .iter()
+        .position(|&s| s == seconds)
+        .unwrap_or(0)
+}
+
+pub fn time_attack(ui: &mut Ui, area: Rect, row: f32) -> Option<MenuAction> {
+    let spacing = row * 0.3;
+    let mut stack = Stack::anchored(
+        area,
+        Anchor::Center,
+        row * 6.0,
+        TIME_ATTACK_LENGTHS.len() + 2,
+        row,
+        spacing,
+    );
+    let mut action = None;
+    ui.begin();
+    let back_key = ui.back();
+    ui.label(stack.next(row), "Time Attack");
+    for &seconds in TIME_ATTACK_LENGTHS.iter() {
+        if ui.button(stack.next(row), &format!("{} seconds", seconds)) {
+            action = Some(MenuAction::TimeAttack(seconds));
+        }
+    }
+    if ui.button(stack.next(row), "Back") || back_key {
+        action = Some(MenuAction::Back);
+    }
+    ui.end();
+    action
+}
+
+// "**-" for two stars out of three
+pub fn stars_text(stars: u8) -> String {
+    (0..3).map(|i| if i < stars { '*' } else { '-' }).collect()
+}
+
+// puzzles two to a row, each with its best rating
+pub fn puzzles(
+    ui: &mut Ui,
+    area: Rect,
+    row: f32,
+    puzzles: &[Puzzle],
+    save: &SaveData,
+) -> Option<MenuAction> {
+    let spacing = row * 0.3;
+    let rows = (puzzles.len() + 1) / 2;
+    let mut stack = Stack::anchored(
+        area,
+        Anchor::Center,
+        row * 9.0,
+        rows.max(1) + 2,
+        row,
+        spacing,
+    );
+    let mut action = None;
+    ui.begin();
+    let back_key = ui.back();
+    ui.label(stack.next(row), "Levels");
+    if puzzles.is_empty() {
+        ui.label(stack.next(row), "no puzzles found");
+    }
+    for (i, pair) in puzzles.chunks(2).enumerate() {
+        let cols = stack.columns(row, 2);
+        for (j, p) in pair.iter().enumerate() {
+            let text = format!("{} {}", p.name, stars_text(save.puzzle_stars(&p.id)));
+            if ui.button(cols[j], &text) {
+                action = Some(MenuAction::Puzzle(i * 2 + j));
+            }
+        }
+    }
+    if ui.button(stack.next(row), "Back") || back_key {
+        action = Some(MenuAction::Back);
+    }
+    ui.end();
+    action
+}
+
+pub fn daily(
+    ui: &mut Ui,
+    area: Rect,
+    row: f32,
+    history: &DailyHistory,
+    date: NaiveDate,
+) -> Option<MenuAction> {
+    let recent = history.recent(5);
+    let spacing = row * 0.3;
+    let mut stack = Stack::anchored(
+        area,
+        Anchor::Center,
+        row * 7.0,
+        recent.len() + 6,
+        row,
+        spacing,
+    );
+    let mut action = None;
+    ui.begin();
+    let back_key = ui.back();
+    ui.label(
+        stack.next(row),
+        &format!("Daily Challenge {}", daily::format_date(date)),
+    );
+    let today = history.result(date);
+    ui.label(
+        stack.next(row),
+        &match today {
+            Some(r) => format!("today: {}", r.score),
+            None => "today: not played yet".to_owned(),
+        },
+    );
+    ui.label(
+        stack.next(row),
+        &format!(
+            "streak: {}   best: {}",
+            history.streak(date),
+            history.best_streak()
+        ),
+    );
+    for r in recent.iter() {
+        ui.label(stack.next(row), &format!("{}   {}", r.date, r.score));
+    }
+    let play = if today.is_some() {
+        "Play (not scored)"
+    } else {
+        "Play"
+    };
+    if ui.button(stack.next(row), play) {
+        action = Some(MenuAction::PlayDaily);
+    }
+    if ui.button(stack.next(row), "Back") || back_key {
+        action = Some(MenuAction::Back);
+    }
+    ui.end();
+    action
+}
+
+// replays shown on the ghost race screen
+const RACE_ROWS: usize = 8;
+
+// saved runs best first; `unreadable` counts replay files that failed to
+// load or didn't play out to their score
+pub fn race(
+    ui: &mut Ui,
+    area: Rect,
+    row: f32,
+    replays: &[Replay],
+    unreadable: usize,
+) -> Option<MenuAction> {
+    let shown = replays.len().min(RACE_ROWS);
+    let notes = if unreadable > 0 { 1 } else { 0 };
+    let spacing = row * 0.3;
+    let mut stack = Stack::anchored(
+        area,
+        Anchor::Center,
+        row * 8.0,
+        shown + notes + 3,
+        row,
+        spacing,
+    );
+    let mut action = None;
+    ui.begin();
+    let back_key = ui.back();
+    ui.label(stack.next(row), "Ghost Race");
+    if ui.button(stack.next(row), "New seed") {
+        action = Some(MenuAction::Race(None));
+    }
+    for (i, r) in replays.iter().take(shown).enumerate() {
+        // the first few hex digits are enough to tell seeds apart
+        let text = format!("{}   {}   {}", &r.file_name()[..6], r.score, r.date);
+        if ui.button(stack.next(row), &text) {
+            action = Some(MenuAction::Race(Some(i)));
+        }
+    }
+    if unreadable > 0 {
+        ui.label(
+            stack.next(row),
+            &format!("{} replays could not be used", unreadable),
+        );
+    }
+    if ui.button(stack.next(row), "Back") || back_key {
+        action = Some(MenuAction::Back);
+    }
+    ui.end();
+    action
+}
+
+pub fn leaderboard(
+    ui: &mut Ui,
+    area: Rect,
+    row: f32,
+    save: &SaveData,
+    board: Board,
+) -> Option<MenuAction> {
+    let scores = save.board(board);
+    let spacing = row * 0.2;
+    let rows = scores.len().max(1) + 2;
+    let width = row * 6.0;
+    let panel = Anchor::Center.place(
+        area,
+        width + 2.0 * spacing,
+        rows as f32 * (row + spacing) + spacing,
+        0.0,
+    );
+    let mut stack = Stack::new(panel.x + spacing, panel.y + spacing, width, spacing);
+    ui.begin();
+    let back_key = ui.back();
+    ui.panel(panel);
+    let next = ui.button(stack.next(row), &board.title());
+    if scores.is_empty() {
+        ui.label(stack.next(row), "no scores yet");
+    }
+    for (i, score) in scores.iter().enumerate() {
+        ui.label(stack.next(row), &format!("{:>2}.  {}", i + 1, score));
+    }
+    let back = ui.button(stack.next(row), "Back");
+    ui.end();
+    if back || back_key {
+        Some(MenuAction::Back)
+    } else if next {
+        Some(MenuAction::NextBoard)
+    } else {
+        None
+    }
+}
+
+// One frame of the settings screen. Changes are made to `settings` right
+// away; the caller compares against its copy to apply them.
+pub fn settings(
+    ui: &mut Ui,
+    area: Rect,
+    row: f32,
+    settings: &mut Settings,
+    themes: &[&str],
+) -> Option<MenuAction> {
+    let spacing = row * 0.3;
+    let mut stack = Stack::anchored(area, Anchor::Center, row * 7.0, 11, row, spacing);
+    let mut action = None;
+    ui.begin();
+    let back_key = ui.back();
+    ui.label(stack.next(row), "Settings");
+    let audio = &mut settings.audio;
+    ui.slider(
+        stack.next(row),
+        &format!("volume {:.0}%", audio.volume * 100.0),
+        &mut audio.volume,
+        0.0,
+        1.0,
+        0.05,
+    );
+    ui.slider(
+        stack.next(row),
+        &format!("music {:.0}%", audio.music_volume * 100.0),
+        &mut audio.music_volume,
+        0.0,
+        1.0,
+        0.05,
+    );
+    if ui.button(stack.next(row), &format!("theme: {}", settings.theme)) && !themes.is_empty() {
+        let i = themes
+            .iter()
+            .position(|&t| t == settings.theme)
+            .map_or(0, |i| (i + 1) % themes.len());
+        settings.theme = themes[i].to_owned();
+    }
+    if ui.button(
+        stack.next(row),
+        &format!("difficulty: {}", settings.difficulty.name()),
+    ) {
+        settings.difficulty = settings.difficulty.next();
+    }
+    ui.toggle(stack.next(row), "aim preview", &mut settings.aim_preview);
+    ui.toggle(
+        stack.next(row),
+        "reduce motion",
+        &mut settings.accessibility.reduce_motion,
+    );
+    let a11y = &mut settings.accessibility;
+    ui.slider(
+        stack.next(row),
+        &format!("UI scale {:.2}", a11y.ui_scale),
+        &mut a11y.ui_scale,
+        settings::UI_SCALE_MIN,
+        settings::UI_SCALE_MAX,
+        0.25,
+    );
+    ui.toggle(stack.next(row), "fullscreen", &mut settings.fullscreen);
+    if ui.button(stack.next(row), "key bindings") {
+        action = Some(MenuAction::KeyBindings);
+    }
+    if ui.button(stack.next(row), "Back") || back_key {
+        action = Some(MenuAction::Back);
+    }
+    ui.end();
+    action
+}
+
+pub fn key_bindings(
+    ui: &mut Ui,
+    area: Rect,
+    row: f32,
+    keys: &KeyBindings,
+    capturing: Option<Action>,
+) -> Option<MenuAction> {
+    let spacing = row * 0.2;
+    let row = row * 0.8;
+    let mut stack = Stack::anchored(
+        area,
+        Anchor::Center,
+        row * 9.0,
+        Action::ALL.len() + 3,
+        row,
+        spacing,
+    );
+    let mut action = None;
+    ui.begin();
+    let back_key = ui.back() && capturing.is_none();
+    ui.label(stack.next(row), "Key bindings");
+    for &a in Action::ALL.iter() {
+        let cols = stack.columns(row, 2);
+        ui.label(cols[0], a.title());
+        let key = if capturing == Some(a) {
+            "press a key".to_owned()
+        } else {
+            keys.get(a).to_owned()
+        };
+        if ui.button(cols[1], &key) {
+            action = Some(MenuAction::Capture(a));
+        }
+    }
+    if ui.button(stack.next(row), "reset to defaults") {
+        action = Some(MenuAction::ResetKeys);
+    }
+    if ui.button(stack.next(row), "Back") || back_key {
+        action = Some(MenuAction::Back);
+    }
+    ui.end();
+    action
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::ui::{UiKey, UiStyle};
+    use super::*;
+
+    #[test]
+    fn title_starts_the_focused_mode() {
+        let mut ui = Ui::new(UiStyle::default());
+        let area = Rect::new(0.0, 0.0, 400.0, 600.0);
+        let save = SaveData::default();
+        title(&mut ui, area, 30.0, &save);
+        ui.set_focus(Some(title_focus(Mode::Endless)));
+        ui.key(UiKey::Activate);
+        assert_eq!(
+            title(&mut ui, area, 30.0, &save),
+            Some(MenuAction::Start(Mode::Endless))
+        );
+        ui.set_focus(Some(title_focus(Mode::Levels)));
+        ui.key(UiKey::Activate);
+        assert_eq!(
+            title(&mut ui, area, 30.0, &save),
+            Some(MenuAction::Start(Mode::Levels))
+        );
+    }
+
+    #[test]
+    fn settings_change_in_place() {
+        let mut ui = Ui::new(UiStyle::default());
+        let area = Rect::new(0.0, 0.0, 400.0, 600.0);
+        let mut s = Settings::default();
+        let themes = ["light", "dark"];
+        settings(&mut ui, area, 30.0, &mut s, &themes);
+        // volume, music, theme, difficulty, aim preview
+        ui.set_focus(Some(2));
+        ui.key(UiKey::Activate);
+        settings(&mut ui, area, 30.0, &mut s, &themes);
+        assert_eq!(s.theme, "dark");
+        ui.set_focus(Some(4));
+        ui.key(UiKey::Activate);
+        settings(&mut ui, area, 30.0, &mut s, &themes);
+        assert!(s.aim_preview);
+        ui.set_focus(Some(0));
+        ui.key(UiKey::Decrease);
+        settings(&mut ui, area, 30.0, &mut s, &themes);
+        assert!((s.audio.volume - 0.95).abs() < 1e-6);
+        ui.key(UiKey::Back);
+        assert_eq!(
+            settings(&mut ui, area, 30.0, &mut s, &themes),
+            Some(MenuAction::Back)
+        );
+    }
+}
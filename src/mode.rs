@@ -0,0 +1,212 @@
+// Round lengths offered for time attack, in seconds.
+pub const TIME_ATTACK_LENGTHS: [u32; 3] = [60, 120, 180];
+
+// seconds added to the wait between spawns for each block on the board (up
+// to ten), and the most random time on top
+const BLOCK_GENERATE: f64 = 5.0;
+const SPAWN_SPREAD: f64 = 3.0;
+
+// The ways a run can be played, picked from the title menu.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    Endless,
+    // endless rules on a block sequence seeded from the date
+    Daily,
+    Levels,
+    TimeAttack,
+    Practice,
+    // two players take turns on the same block sequence
+    Hotseat,
+    // two launchers, one on the mouse and one on the keyboard, racing for
+    // the same blocks
+    Versus,
+    // mouse and keyboard launchers defending one board together
+    Coop,
+    // solo on a fixed seed against a saved run's ghost
+    Race,
+}
+
+impl Default for Mode {
+    fn default() -> Mode {
+        Mode::Endless
+    }
+}
+
+impl Mode {
+    pub const ALL: [Mode; 9] = [
+        Mode::Endless,
+        Mode::Daily,
+        Mode::Levels,
+        Mode::TimeAttack,
+        Mode::Practice,
+        Mode::Hotseat,
+        Mode::Versus,
+        Mode::Coop,
+        Mode::Race,
+    ];
+
+    pub fn title(self) -> &'static str {
+        match self {
+            Mode::Endless => "Play",
+            Mode::Daily => "Daily Challenge",
+            Mode::Levels => "Levels",
+            Mode::TimeAttack => "Time Attack",
+            Mode::Practice => "Practice",
+            Mode::Hotseat => "Hotseat (2P)",
+            Mode::Versus => "Versus (2P)",
+            Mode::Coop => "Co-op (2P)",
+            Mode::Race => "Ghost Race",
+        }
+    }
+
+    // whether expired blocks cost lives and can end the run
+    pub fn has_lives(self) -> bool {
+        match self {
+            Mode::Endless
+            | Mode::Daily
+            | Mode::Hotseat
+            | Mode::Versus
+            | Mode::Coop
+            | Mode::Race => true,
+            _ => false,
+        }
+    }
+
+    pub fn players(self) -> usize {
+        match self {
+            Mode::Hotseat | Mode::Versus | Mode::Coop => 2,
+            _ => 1,
+        }
+    }
+
+    // whether the players share one launcher and play a round each
+    pub fn takes_turns(self) -> bool {
+        self == Mode::Hotseat
+    }
+
+    // whether the players play for one combined score
+    pub fn shares_score(self) -> bool {
+        self == Mode::Coop
+    }
+
+    // whether blocks run out of time at all
+    pub fn blocks_expire(self) -> bool {
+        match self {
+            Mode::Practice | Mode::Levels => false,
+            _ => true,
+        }
+    }
+
+    // whether a run left part way is scored as it stood; a time attack only
+    // counts once its clock has run out
+    pub fn scores_early_exit(self) -> bool {
+        self != Mode::TimeAttack
+    }
+
+    // puzzles come with their blocks, everything else spawns them
+    pub fn spawns_blocks(self) -> bool {
+        self != Mode::Levels
+    }
+
+    // multiplier for the gap between block spawns, `progress` being how far
+    // through a timed round we are (0..1)
+    pub fn spawn_scale(self, progress: f32) -> f64 {
+        match self {
+            // ends up spawning two and a half times as fast
+            Mode::TimeAttack => 1.0 - 0.6 * f64::from(progress.max(0.0).min(1.0)),
+            // blocks come as fast for each defender as for one player alone
+            Mode::Coop => 1.0 / self.players() as f64,
+            _ => 1.0,
+        }
+    }
+
+    // seconds until the next spawn with `blocks` on the board, `roll` being a
+    // random draw in 0..1; the whole wait is scaled, not just its minimum
+    pub fn spawn_gap(self, progress: f32, blocks: usize, roll: f64) -> f64 {
+        (BLOCK_GENERATE * blocks.min(10) as f64 + SPAWN_SPREAD * roll) * self.spawn_scale(progress)
+    }
+}
+
+// One leaderboard: endless runs, or time attacks of one length.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Board {
+    Endless,
+    TimeAttack(u32),
+}
+
+impl Board {
+    pub fn all() -> Vec<Board> {
+        let mut boards = vec![Board::Endless];
+        boards.extend(TIME_ATTACK_LENGTHS.iter().map(|&s| Board::TimeAttack(s)));
+        boards
+    }
+
+    pub fn next(self) -> Board {
+        let all = Board::all();
+        let i = all
+            .iter()
+            .position(|&b| b == self)
+            .map_or(0, |i| (i + 1) % all.len());
+        all[i]
+    }
+
+    pub fn title(self) -> String {
+        match self {
+            Board::Endless => "Endless".to_owned(),
+            Board::TimeAttack(s) => format!("Time Attack {}s", s),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn time_attack_speeds_up() {
+        assert_eq!(Mode::Endless.spawn_scale(0.9), 1.0);
+        assert_eq!(Mode::TimeAttack.spawn_scale(0.0), 1.0);
+        assert!((Mode::TimeAttack.spawn_scale(1.0) - 0.4).abs() < 1e-6);
+        assert!(Mode::TimeAttack.spawn_scale(0.5) > Mode::TimeAttack.spawn_scale(0.8));
+        // the random part shrinks with the rest
+        let (slow, fast) = (
+            Mode::TimeAttack.spawn_gap(0.0, 1, 0.99),
+            Mode::TimeAttack.spawn_gap(1.0, 1, 0.99),
+        );
+        assert!((fast - 0.4 * slow).abs() < 1e-6);
+        assert!(Mode::TimeAttack.spawn_gap(1.0, 1, 0.99) < 0.4 * (BLOCK_GENERATE + SPAWN_SPREAD));
+        assert!(!Mode::TimeAttack.has_lives());
+        assert!(Mode::TimeAttack.blocks_expire() && !Mode::Practice.blocks_expire());
+        assert!(!Mode::Levels.spawns_blocks() && Mode::Practice.spawns_blocks());
+        assert!(!Mode::TimeAttack.scores_early_exit() && Mode::Endless.scores_early_exit());
+    }
+
+    #[test]
+    fn two_player_modes() {
+        assert_eq!(Mode::Endless.players(), 1);
+        assert_eq!(Mode::Versus.players(), 2);
+        assert!(Mode::Hotseat.takes_turns() && !Mode::Versus.takes_turns());
+        assert!(Mode::Versus.has_lives());
+        assert!(Mode::Coop.shares_score() && !Mode::Versus.shares_score());
+        assert_eq!(Mode::Coop.spawn_scale(0.0), 0.5);
+        assert_eq!(
+            Mode::Coop.spawn_gap(0.0, 2, 0.5),
+            Mode::Endless.spawn_gap(0.0, 2, 0.5) / 2.0
+        );
+        assert_eq!(
+            Mode::Endless.spawn_gap(0.0, 20, 0.0),
+            Mode::Endless.spawn_gap(0.0, 10, 0.0)
+        );
+    }
+
+    #[test]
+    fn boards_cycle() {
+        let mut b = Board::Endless;
+        for _ in 0..TIME_ATTACK_LENGTHS.len() {
+            b = b.next();
+            assert_ne!(b, Board::Endless);
+        }
+        assert_eq!(b.next(), Board::Endless);
+    }
+}
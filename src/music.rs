@@ -0,0 +1,424 @@
+use std::collections::HashMap;
+
+use ggez::{audio, Context};
+use toml;
+
+use super::assets::Assets;
+use super::error::{Error, Result};
+
+const PLAYLIST_NAME: &str = "/music.toml";
+const DUCK_LEVEL: f32 = 0.35;
+const DUCK_RATE: f32 = 4.0;
+
+#[repr(u8)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Scene {
+    Title = 0,
+    Gameplay = 1,
+    GameOver = 2,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Playlist {
+    pub title: Vec<String>,
+    pub gameplay: Vec<String>,
+    pub game_over: Vec<String>,
+    pub crossfade: f32,
+    pub duck_time: f32,
+}
+
+impl Default for Playlist {
+    fn default() -> Playlist {
+        Playlist {
+            title: vec![],
+            gameplay: vec![],
+            game_over: vec![],
+            crossfade: 1.5,
+            duck_time: 0.6,
+        }
+    }
+}
+
+impl Playlist {
+    pub fn from_toml(s: &str) -> Result<Playlist> {
+        toml::from_str(s).map_err(|e| Error::Config(format!("{}: {}", PLAYLIST_NAME, e)))
+    }
+
+    // an absent playlist file means the game runs without music
+    pub fn load(ctx: &mut Context, assets: &mut Assets) -> Result<Playlist> {
+        if !assets.exists(ctx, PLAYLIST_NAME) {
+            return Ok(Playlist::default());
+        }
+        let s = assets.read_string(ctx, PLAYLIST_NAME)?;
+        Playlist::from_toml(&s)
+    }
+
+    pub fn tracks(&self, scene: Scene) -> &[String] {
+        match scene {
+            Scene::Title => &self.title,
+            Scene::Gameplay => &self.gameplay,
+            Scene::GameOver => &self.game_over,
+        }
+    }
+
+    pub fn all_tracks(&self) -> Vec<&str> {
+        let mut tracks: Vec<&str> = vec![];
+        for t in self
+            .title
+            .iter()
+            .chain(self.gameplay.iter())
+            .chain(self.game_over.iter())
+        {
+            if !tracks.contains(&t.as_str()) {
+                tracks.push(t);
+            }
+        }
+        tracks
+    }
+}
+
+pub trait MusicSink {
+    fn play(&mut self, track: &str) -> Result<()>;
+    fn stop(&mut self, track: &str);
+    fn set_volume(&mut self, track: &str, volume: f32);
+    fn is_finished(&self, track: &str) -> bool;
+}
+
+#[derive(Debug)]
+pub struct SourceSink {
+    sources: HashMap<String, audio::Source>,
+}
+
+impl SourceSink {
+    pub fn new(ctx: &mut Context, assets: &mut Assets, playlist: &Playlist) -> Result<SourceSink> {
+        let mut sources = HashMap::new();
+        for track in playlist.all_tracks() {
+            sources.insert(track.to_owned(), assets.sound(ctx, track)?);
+        }
+        Ok(SourceSink { sources: sources })
+    }
+}
+
+impl MusicSink for SourceSink {
+    fn play(&mut self, track: &str) -> Result<()> {
+        match self.sources.get(track) {
+            Some(source) => source
+                .play()
+                .map_err(|e| Error::Audio(format!("{}: {}", track, e))),
+            None => Err(Error::Audio(format!("track {} is not loaded", track))),
+        }
+    }
+
+    fn stop(&mut self, track: &str) {
+        if let Some(source) = self.sources.get(track) {
+            source.stop();
+        }
+    }
+
+    fn set_volume(&mut self, track: &str, volume: f32) {
+        if let Some(source) = self.sources.get_mut(track) {
+            source.set_volume(volume);
+        }
+    }
+
+    fn is_finished(&self, track: &str) -> bool {
+        self.sources.get(track).map_or(true, |s| s.stopped())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SinkEvent {
+    Play(String),
+    Stop(String),
+}
+
+// Records what would be played instead of producing sound; tracks only end
+// when `finish` is called.
+#[derive(Debug, Default)]
+pub struct NullSink {
+    pub events: Vec<SinkEvent>,
+    volumes: HashMap<String, f32>,
+    playing: Vec<String>,
+}
+
+impl NullSink {
+    pub fn new() -> NullSink {
+        NullSink::default()
+    }
+
+    pub fn finish(&mut self, track: &str) {
+        self.playing.retain(|t| t != track);
+    }
+
+    pub fn volume(&self, track: &str) -> f32 {
+        self.volumes.get(track).cloned().unwrap_or(0.0)
+    }
+
+    pub fn played(&self) -> Vec<&str> {
+        self.events
+            .iter()
+            .filter_map(|e| match *e {
+                SinkEvent::Play(ref t) => Some(t.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl MusicSink for NullSink {
+    fn play(&mut self, track: &str) -> Result<()> {
+        self.events.push(SinkEvent::Play(track.to_owned()));
+        self.playing.push(track.to_owned());
+        Ok(())
+    }
+
+    fn stop(&mut self, track: &str) {
+        self.events.push(SinkEvent::Stop(track.to_owned()));
+        self.finish(track);
+    }
+
+    fn set_volume(&mut self, track: &str, volume: f32) {
+        self.volumes.insert(track.to_owned(), volume);
+    }
+
+    fn is_finished(&self, track: &str) -> bool {
+        !self.playing.iter().any(|t| t == track)
+    }
+}
+
+#[derive(Debug)]
+struct Channel {
+    track: String,
+    level: f32,
+}
+
+#[derive(Debug)]
+pub struct MusicPlayer<S: MusicSink> {
+    sink: S,
+    playlist: Playlist,
+    scene: Option<Scene>,
+    index: usize,
+    current: Option<Channel>,
+    fading: Vec<Channel>,
+    duck_left: f32,
+    duck_level: f32,
+    gain: f32,
+}
+
+impl<S: MusicSink> MusicPlayer<S> {
+    pub fn new(sink: S, playlist: Playlist) -> MusicPlayer<S> {
+        MusicPlayer {
+            sink: sink,
+            playlist: playlist,
+            scene: None,
+            index: 0,
+            current: None,
+            fading: vec![],
+            duck_left: 0.0,
+            duck_level: 1.0,
+            gain: 1.0,
+        }
+    }
+
+    pub fn sink(&self) -> &S {
+        &self.sink
+    }
+
+    pub fn sink_mut(&mut self) -> &mut S {
+        &mut self.sink
+    }
+
+    pub fn scene(&self) -> Option<Scene> {
+        self.scene
+    }
+
+    pub fn current_track(&self) -> Option<&str> {
+        self.current.as_ref().map(|c| c.track.as_str())
+    }
+
+    pub fn set_gain(&mut self, gain: f32) {
+        self.gain = gain.max(0.0).min(1.0);
+        self.apply_volume();
+    }
+
+    pub fn duck(&mut self) {
+        self.duck_left = self.playlist.duck_time;
+    }
+
+    pub fn set_scene(&mut self, scene: Scene) -> Result<()> {
+        if self.scene == Some(scene) {
+            return Ok(());
+        }
+        self.scene = Some(scene);
+        self.index = 0;
+        if let Some(c) = self.current.take() {
+            self.fading.push(c);
+        }
+        let level = if self.playlist.crossfade > 0.0 {
+            0.0
+        } else {
+            1.0
+        };
+        self.start_track(level)
+    }
+
+    fn start_track(&mut self, level: f32) -> Result<()> {
+        let track = match self
+            .scene
+            .and_then(|s| self.playlist.tracks(s).get(self.index))
+        {
+            Some(t) => t.clone(),
+            None => return Ok(()),
+        };
+        // the same track may still be fading out of the previous scene
+        if let Some(i) = self.fading.iter().position(|c| c.track == track) {
+            self.current = Some(self.fading.remove(i));
+            return Ok(());
+        }
+        self.sink.play(&track)?;
+        self.current = Some(Channel {
+            track: track,
+            level: level,
+        });
+        self.apply_volume();
+        Ok(())
+    }
+
+    fn next_track(&mut self) -> Result<()> {
+        let count = self.scene.map_or(0, |s| self.playlist.tracks(s).len());
+        if count == 0 {
+            self.current = None;
+            return Ok(());
+        }
+        self.index = (self.index + 1) % count;
+        self.current = None;
+        self.start_track(1.0)
+    }
+
+    fn apply_volume(&mut self) {
+        let gain = self.gain * self.duck_level;
+        if let Some(ref c) = self.current {
+            self.sink.set_volume(&c.track, c.level * gain);
+        }
+        for c in self.fading.iter() {
+            self.sink.set_volume(&c.track, c.level * gain);
+        }
+    }
+
+    pub fn update(&mut self, time_delta: f32) -> Result<()> {
+        let step = if self.playlist.crossfade > 0.0 {
+            time_delta / self.playlist.crossfade
+        } else {
+            1.0
+        };
+        if let Some(ref mut c) = self.current {
+            c.level = (c.level + step).min(1.0);
+        }
+        for c in self.fading.iter_mut() {
+            c.level -= step;
+        }
+        for c in self.fading.iter().filter(|c| c.level <= 0.0) {
+            self.sink.stop(&c.track);
+        }
+        self.fading.retain(|c| c.level > 0.0);
+
+        self.duck_left = (self.duck_left - time_delta).max(0.0);
+        let target = if self.duck_left > 0.0 {
+            DUCK_LEVEL
+        } else {
+            1.0
+        };
+        if self.duck_level > target {
+            self.duck_level = (self.duck_level - DUCK_RATE * time_delta).max(target);
+        } else {
+            self.duck_level = (self.duck_level + DUCK_RATE * time_delta).min(target);
+        }
+        self.apply_volume();
+
+        let finished = match self.current {
+            Some(ref c) => self.sink.is_finished(&c.track),
+            None => false,
+        };
+        if finished {
+            self.next_track()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn playlist() -> Playlist {
+        Playlist::from_toml(
+            r#"
+            title = ["/title.ogg"]
+            gameplay = ["/a.ogg", "/b.ogg"]
+            game_over = ["/title.ogg"]
+            crossfade = 1.0
+        "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn loops_gameplay_tracks_in_order() {
+        let mut player = MusicPlayer::new(NullSink::new(), playlist());
+        player.set_scene(Scene::Gameplay).unwrap();
+        player.update(0.1).unwrap();
+        player.sink_mut().finish("/a.ogg");
+        player.update(0.1).unwrap();
+        player.sink_mut().finish("/b.ogg");
+        player.update(0.1).unwrap();
+        assert_eq!(player.sink().played(), vec!["/a.ogg", "/b.ogg", "/a.ogg"]);
+    }
+
+    #[test]
+    fn crossfades_between_scenes() {
+        let mut player = MusicPlayer::new(NullSink::new(), playlist());
+        player.set_scene(Scene::Title).unwrap();
+        player.update(1.0).unwrap();
+        assert_eq!(player.sink().volume("/title.ogg"), 1.0);
+
+        player.set_scene(Scene::Gameplay).unwrap();
+        player.update(0.5).unwrap();
+        assert!((player.sink().volume("/title.ogg") - 0.5).abs() < 1e-4);
+        assert!((player.sink().volume("/a.ogg") - 0.5).abs() < 1e-4);
+
+        player.update(0.5).unwrap();
+        assert_eq!(
+            player.sink().events,
+            vec![
+                SinkEvent::Play("/title.ogg".to_owned()),
+                SinkEvent::Play("/a.ogg".to_owned()),
+                SinkEvent::Stop("/title.ogg".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn shared_track_is_not_restarted() {
+        let mut player = MusicPlayer::new(NullSink::new(), playlist());
+        player.set_scene(Scene::Title).unwrap();
+        player.set_scene(Scene::Gameplay).unwrap();
+        player.set_scene(Scene::GameOver).unwrap();
+        assert_eq!(player.sink().played(), vec!["/title.ogg", "/a.ogg"]);
+        assert_eq!(player.current_track(), Some("/title.ogg"));
+    }
+
+    #[test]
+    fn ducks_and_mutes() {
+        let mut player = MusicPlayer::new(NullSink::new(), playlist());
+        player.set_scene(Scene::Title).unwrap();
+        player.update(1.0).unwrap();
+        player.duck();
+        player.update(0.5).unwrap();
+        assert!((player.sink().volume("/title.ogg") - DUCK_LEVEL).abs() < 1e-4);
+        player.update(1.0).unwrap();
+        assert_eq!(player.sink().volume("/title.ogg"), 1.0);
+        player.set_gain(0.0);
+        assert_eq!(player.sink().volume("/title.ogg"), 0.0);
+    }
+}
@@ -0,0 +1,661 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+use super::error::{Error, Result};
+use super::settings::Difficulty;
+use super::sim::{Input, Sim};
+
+// Bumped whenever a message changes shape; both ends must speak the same one.
+pub const PROTOCOL_VERSION: u16 = 2;
+pub const DEFAULT_PORT: u16 = 40404;
+
+// frames between state hash checks
+const HASH_INTERVAL: u32 = 60;
+// frames an input is sent ahead of the frame it is for, so the other side
+// usually has it before it is needed
+pub const INPUT_DELAY: u32 = 3;
+// a peer silent for this long is taken as gone
+const TIMEOUT: Duration = Duration::from_secs(10);
+// nothing we send comes close
+const MAX_MESSAGE: usize = 1024;
+// starts every hello so a stray connection is turned away early
+const MAGIC: &[u8; 4] = b"FBAL";
+
+const HELLO: u8 = 1;
+const WELCOME: u8 = 2;
+const INPUT: u8 = 3;
+const HASH: u8 = 4;
+const DISCONNECT: u8 = 5;
+
+// Everything two peers say to each other. On the wire each message is a
+// big-endian u16 length, a tag byte and the fields in order.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Message {
+    // joiner to host, first thing after connecting
+    Hello {
+        version: u16,
+    },
+    // host to joiner, with the seed both simulations start from and the
+    // host's difficulty, which both play at
+    Welcome {
+        version: u16,
+        seed: [u32; 4],
+        difficulty: Difficulty,
+    },
+    // one player's input for `frame`
+    Input {
+        frame: u32,
+        input: Input,
+    },
+    // the sender's state hash after `frame` ticks
+    Hash {
+        frame: u32,
+        hash: u64,
+    },
+    Disconnect {
+        reason: String,
+    },
+}
+
+const DIFFICULTIES: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard];
+
+fn net_error(e: io::Error) -> Error {
+    match e.kind() {
+        io::ErrorKind::UnexpectedEof => {
+            Error::Net("the other player closed the connection".to_owned())
+        }
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => {
+            Error::Net("the other player stopped responding".to_owned())
+        }
+        _ => Error::Net(e.to_string()),
+    }
+}
+
+// reads fields off the front of a message body
+struct Fields<'a> {
+    body: &'a [u8],
+}
+
+impl<'a> Fields<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.body.len() < n {
+            return Err(Error::Net("message cut short".to_owned()));
+        }
+        let (head, rest) = self.body.split_at(n);
+        self.body = rest;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let b = self.take(2)?;
+        Ok(u16::from(b[0]) << 8 | u16::from(b[1]))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from(self.u16()?) << 16 | u32::from(self.u16()?))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from(self.u32()?) << 32 | u64::from(self.u32()?))
+    }
+
+    fn f32(&mut self) -> Result<f32> {
+        Ok(f32::from_bits(self.u32()?))
+    }
+}
+
+impl Message {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = vec![];
+        match *self {
+            Message::Hello { version } => {
+                out.push(HELLO);
+                out.extend_from_slice(MAGIC);
+                out.extend_from_slice(&version.to_be_bytes());
+            }
+            Message::Welcome {
+                version,
+                seed,
+                difficulty,
+            } => {
+                out.push(WELCOME);
+                out.extend_from_slice(&version.to_be_bytes());
+                for s in seed.iter() {
+                    out.extend_from_slice(&s.to_be_bytes());
+                }
+                out.push(
+                    DIFFICULTIES
+                        .iter()
+                        .position(|&d| d == difficulty)
+                        .unwrap_or(1) as u8,
+                );
+            }
+            Message::Input { frame, input } => {
+                out.push(INPUT);
+                out.extend_from_slice(&frame.to_be_bytes());
+                out.extend_from_slice(&input.aim.0.to_bits().to_be_bytes());
+                out.extend_from_slice(&input.aim.1.to_bits().to_be_bytes());
+                out.push(input.charging as u8);
+            }
+            Message::Hash { frame, hash } => {
+                out.push(HASH);
+                out.extend_from_slice(&frame.to_be_bytes());
+                out.extend_from_slice(&hash.to_be_bytes());
+            }
+            Message::Disconnect { ref reason } => {
+                out.push(DISCONNECT);
+                let bytes = reason.as_bytes();
+                let len = bytes.len().min(MAX_MESSAGE - 3);
+                out.extend_from_slice(&(len as u16).to_be_bytes());
+                out.extend_from_slice(&bytes[..len]);
+            }
+        }
+        out
+    }
+
+    pub fn decode(body: &[u8]) -> Result<Message> {
+        let mut f = Fields { body: body };
+        let m = match f.u8()? {
+            HELLO => {
+                if f.take(4)? != MAGIC {
+                    return Err(Error::Net("not a falling ball player".to_owned()));
+                }
+                Message::Hello { version: f.u16()? }
+            }
+            WELCOME => Message::Welcome {
+                version: f.u16()?,
+                seed: [f.u32()?, f.u32()?, f.u32()?, f.u32()?],
+                difficulty: match DIFFICULTIES.get(f.u8()? as usize) {
+                    Some(&d) => d,
+                    None => return Err(Error::Net("unknown difficulty".to_owned())),
+                },
+            },
+            INPUT => Message::Input {
+                frame: f.u32()?,
+                input: Input {
+                    aim: (f.f32()?, f.f32()?),
+                    charging: f.u8()? != 0,
+                },
+            },
+            HASH => Message::Hash {
+                frame: f.u32()?,
+                hash: f.u64()?,
+            },
+            DISCONNECT => {
+                let len = f.u16()? as usize;
+                Message::Disconnect {
+                    reason: String::from_utf8_lossy(f.take(len)?).into_owned(),
+                }
+            }
+            tag => return Err(Error::Net(format!("unknown message type {}", tag))),
+        };
+        if !f.body.is_empty() {
+            return Err(Error::Net("trailing bytes after a message".to_owned()));
+        }
+        Ok(m)
+    }
+}
+
+pub fn write_message<W: Write>(w: &mut W, m: &Message) -> Result<()> {
+    let body = m.encode();
+    let mut buf = (body.len() as u16).to_be_bytes().to_vec();
+    buf.extend(body);
+    w.write_all(&buf).and_then(|_| w.flush()).map_err(net_error)
+}
+
+pub fn read_message<R: Read>(r: &mut R) -> Result<Message> {
+    let mut len = [0u8; 2];
+    r.read_exact(&mut len).map_err(net_error)?;
+    let len = u16::from_be_bytes(len) as usize;
+    if len == 0 || len > MAX_MESSAGE {
+        return Err(Error::Net(format!("bad message length {}", len)));
+    }
+    let mut body = vec![0; len];
+    r.read_exact(&mut body).map_err(net_error)?;
+    Message::decode(&body)
+}
+
+// A TCP link to the other player, past the handshake.
+#[derive(Debug)]
+pub struct Connection {
+    stream: TcpStream,
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> Result<Connection> {
+        // inputs are needed a few frames after they are sent, so don't let
+        // small writes sit
+        stream.set_nodelay(true).map_err(net_error)?;
+        stream.set_read_timeout(Some(TIMEOUT)).map_err(net_error)?;
+        Ok(Connection { stream: stream })
+    }
+
+    // waits on `listener` for one player and hands them the game's seed and
+    // difficulty
+    pub fn accept(
+        listener: &TcpListener,
+        seed: [u32; 4],
+        difficulty: Difficulty,
+    ) -> Result<Connection> {
+        let (stream, _) = listener.accept().map_err(net_error)?;
+        let mut conn = Connection::new(stream)?;
+        match conn.recv()? {
+            Message::Hello { version } if version == PROTOCOL_VERSION => {}
+            Message::Hello { version } => {
+                let reason = format!(
+                    "protocol version {}, the host speaks {}",
+                    version, PROTOCOL_VERSION
+                );
+                conn.close(&reason);
+                return Err(Error::Net(reason));
+            }
+            m => return Err(Error::Net(format!("expected a hello, got {:?}", m))),
+        }
+        conn.send(&Message::Welcome {
+            version: PROTOCOL_VERSION,
+            seed: seed,
+            difficulty: difficulty,
+        })?;
+        Ok(conn)
+    }
+
+    // joins a host, returning the seed to start from and the difficulty
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> Result<(Connection, [u32; 4], Difficulty)> {
+        let stream = TcpStream::connect(addr).map_err(net_error)?;
+        let mut conn = Connection::new(stream)?;
+        conn.send(&Message::Hello {
+            version: PROTOCOL_VERSION,
+        })?;
+        match conn.recv()? {
+            Message::Welcome {
+                version,
+                seed,
+                difficulty,
+            } if version == PROTOCOL_VERSION => Ok((conn, seed, difficulty)),
+            Message::Welcome { version, .. } => Err(Error::Net(format!(
+                "the host speaks protocol version {}, we speak {}",
+                version, PROTOCOL_VERSION
+            ))),
+            Message::Disconnect { reason } => {
+                Err(Error::Net(format!("the host refused: {}", reason)))
+            }
+            m => Err(Error::Net(format!("expected a welcome, got {:?}", m))),
+        }
+    }
+
+    pub fn send(&mut self, m: &Message) -> Result<()> {
+        write_message(&mut self.stream, m)
+    }
+
+    pub fn recv(&mut self) -> Result<Message> {
+        read_message(&mut self.stream)
+    }
+
+    // best effort, the other side may already be gone; shutting the socket
+    // down also ends a read waiting on it
+    pub fn close(&mut self, reason: &str) {
+        let _ = self.send(&Message::Disconnect {
+            reason: reason.to_owned(),
+        });
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+// A versus game run in step with a remote peer. Each side sends its input a
+// few frames ahead of the frame it is for, and the simulation only moves on
+// once the other side's input for the next frame is in; nothing here ever
+// waits on the socket, which is read on a thread of its own.
+#[derive(Debug)]
+pub struct Lockstep {
+    sim: Sim,
+    conn: Connection,
+    incoming: Receiver<Result<Message>>,
+    // our player, the host is 0
+    local: usize,
+    // inputs sent for the frames from the sim's next one on, ours and theirs
+    ours: VecDeque<Input>,
+    theirs: VecDeque<Input>,
+    // both players' inputs on the last frame played
+    played: [Input; 2],
+    // hashes after the same frames, until both sides' are in
+    our_hashes: VecDeque<(u32, u64)>,
+    their_hashes: VecDeque<(u32, u64)>,
+    // why the other side stopped talking; only an error once we run out of
+    // their inputs
+    gone: Option<Error>,
+}
+
+// reads messages off `stream` until it fails or the other side says goodbye
+fn read_loop(mut stream: TcpStream, tx: Sender<Result<Message>>) {
+    loop {
+        let m = read_message(&mut stream);
+        let last = match m {
+            Ok(Message::Disconnect { .. }) | Err(_) => true,
+            _ => false,
+        };
+        if tx.send(m).is_err() || last {
+            return;
+        }
+    }
+}
+
+impl Lockstep {
+    pub fn new(
+        conn: Connection,
+        seed: [u32; 4],
+        difficulty: Difficulty,
+        local: usize,
+    ) -> Result<Lockstep> {
+        let reader = conn.stream.try_clone().map_err(net_error)?;
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || read_loop(reader, tx));
+        let mut game = Lockstep {
+            sim: Sim::new(seed, difficulty),
+            conn: conn,
+            incoming: rx,
+            local: local,
+            ours: VecDeque::new(),
+            theirs: VecDeque::new(),
+            played: [Input::default(); 2],
+            our_hashes: VecDeque::new(),
+            their_hashes: VecDeque::new(),
+            gone: None,
+        };
+        // nobody has pressed anything yet in the first frames
+        for _ in 0..INPUT_DELAY {
+            game.send_input(Input::default());
+        }
+        Ok(game)
+    }
+
+    pub fn sim(&self) -> &Sim {
+        &self.sim
+    }
+
+    pub fn local(&self) -> usize {
+        self.local
+    }
+
+    pub fn inputs(&self) -> &[Input] {
+        &self.played
+    }
+
+    // whether our input for another frame is due; it never runs more than
+    // INPUT_DELAY frames ahead of the simulation
+    pub fn wants_input(&self) -> bool {
+        (self.ours.len() as u32) < INPUT_DELAY + 1
+    }
+
+    pub fn send_input(&mut self, input: Input) {
+        let frame = self.sim.tick() + self.ours.len() as u32;
+        self.send(&Message::Input {
+            frame: frame,
+            input: input,
+        });
+        self.ours.push_back(input);
+    }
+
+    // the other side may have finished and hung up while we play out the
+    // inputs it already sent, so a failed send only counts once those run out
+    fn send(&mut self, m: &Message) {
+        if let Err(e) = self.conn.send(m) {
+            self.hang_up(e);
+        }
+    }
+
+    // takes in what has arrived so far, without waiting for more
+    fn receive(&mut self) -> Result<()> {
+        loop {
+            let m = match self.incoming.try_recv() {
+                Ok(m) => m,
+                Err(TryRecvError::Empty) => return Ok(()),
+                // the reader has stopped and said why already
+                Err(TryRecvError::Disconnected) => return Ok(()),
+            };
+            match m {
+                Ok(Message::Input { frame, input }) => {
+                    let expected = self.sim.tick() + self.theirs.len() as u32;
+                    if frame != expected {
+                        return Err(Error::Net(format!(
+                            "got input for frame {} when {} was due",
+                            frame, expected
+                        )));
+                    }
+                    self.theirs.push_back(input);
+                }
+                Ok(Message::Hash { frame, hash }) => self.their_hashes.push_back((frame, hash)),
+                Ok(Message::Disconnect { reason }) => {
+                    self.hang_up(Error::Net(format!("the other player left: {}", reason)))
+                }
+                Ok(m) => {
+                    return Err(Error::Net(format!(
+                        "unexpected {:?} at frame {}",
+                        m,
+                        self.sim.tick()
+                    )))
+                }
+                Err(e) => self.hang_up(e),
+            }
+        }
+    }
+
+    // keeps the first reason the link went down
+    fn hang_up(&mut self, e: Error) {
+        if self.gone.is_none() {
+            self.gone = Some(e);
+        }
+    }
+
+    fn check_hashes(&mut self) -> Result<()> {
+        while !self.our_hashes.is_empty() && !self.their_hashes.is_empty() {
+            match (self.our_hashes.pop_front(), self.their_hashes.pop_front()) {
+                (Some((f, h)), Some((g, k))) if f == g && h != k => {
+                    return Err(Error::Net(format!(
+                        "the games went out of sync at frame {}",
+                        f
+                    )));
+                }
+                (Some((f, _)), Some((g, _))) if f != g => {
+                    return Err(Error::Net(format!(
+                        "got a hash for frame {} out of turn",
+                        g
+                    )));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    // plays the next frame if both inputs for it are in, returning whether
+    // it did
+    pub fn advance(&mut self) -> Result<bool> {
+        self.receive()?;
+        self.check_hashes()?;
+        if self.ours.is_empty() {
+            return Ok(false);
+        }
+        let remote = match self.theirs.pop_front() {
+            Some(input) => input,
+            None => {
+                return match self.gone.take() {
+                    Some(e) => Err(e),
+                    None => Ok(false),
+                }
+            }
+        };
+        let mut inputs = [remote; 2];
+        inputs[self.local] = self.ours.pop_front().unwrap_or_default();
+        self.sim.step(&inputs);
+        self.played = inputs;
+        let frame = self.sim.tick();
+        if frame % HASH_INTERVAL == 0 {
+            let hash = self.sim.hash();
+            self.send(&Message::Hash {
+                frame: frame,
+                hash: hash,
+            });
+            self.our_hashes.push_back((frame, hash));
+        }
+        Ok(true)
+    }
+
+    pub fn close(&mut self, reason: &str) {
+        self.conn.close(reason);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::rules;
+    use super::super::sim::ticks;
+    use super::*;
+
+    // charges for a block, the host going for the oldest and the guest for
+    // the newest
+    fn bot(sim: &Sim, player: usize) -> Input {
+        let block = if player == 0 {
+            sim.blocks.first()
+        } else {
+            sim.blocks.last()
+        };
+        match block {
+            Some(b) => {
+                let target = sim.cell_target(b.cell);
+                let needed = sim.charge_for(player, target).unwrap_or(1.0);
+                let p = &sim.players[player];
+                // the input lands INPUT_DELAY ticks from now, so let go early
+                let late = INPUT_DELAY as f32 / ticks(rules::CHARGE_TIME) as f32;
+                Input {
+                    aim: target,
+                    charging: p.is_ready() && p.charge() + late < needed,
+                }
+            }
+            None => Input::default(),
+        }
+    }
+
+    fn play(mut game: Lockstep) -> Result<(u32, u64, Vec<usize>)> {
+        while !game.sim().is_over() {
+            if game.wants_input() {
+                let input = bot(game.sim(), game.local());
+                game.send_input(input);
+            }
+            if !game.advance()? {
+                thread::yield_now();
+            }
+        }
+        game.close("game over");
+        let sim = game.sim();
+        Ok((
+            sim.tick(),
+            sim.hash(),
+            sim.players.iter().map(|p| p.score).collect(),
+        ))
+    }
+
+    // host and guest on loopback, each on its own seed
+    fn match_up(
+        host_seed: [u32; 4],
+        guest_seed: Option<[u32; 4]>,
+    ) -> (
+        Result<(u32, u64, Vec<usize>)>,
+        Result<(u32, u64, Vec<usize>)>,
+    ) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let host = thread::spawn(move || {
+            let conn = Connection::accept(&listener, host_seed, Difficulty::Hard)?;
+            play(Lockstep::new(conn, host_seed, Difficulty::Hard, 0)?)
+        });
+        let (conn, seed, difficulty) = Connection::connect(addr).unwrap();
+        assert_eq!((seed, difficulty), (host_seed, Difficulty::Hard));
+        let guest = Lockstep::new(conn, guest_seed.unwrap_or(seed), difficulty, 1).and_then(play);
+        (host.join().unwrap(), guest)
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        let messages = vec![
+            Message::Hello {
+                version: PROTOCOL_VERSION,
+            },
+            Message::Welcome {
+                version: 1,
+                seed: [1, 2, 3, 0xffff_ffff],
+                difficulty: Difficulty::Easy,
+            },
+            Message::Input {
+                frame: 7,
+                input: Input {
+                    aim: (-1.5, 300.25),
+                    charging: true,
+                },
+            },
+            Message::Hash {
+                frame: 60,
+                hash: 0x0123_4567_89ab_cdef,
+            },
+            Message::Disconnect {
+                reason: "game over".to_owned(),
+            },
+        ];
+        let mut wire = vec![];
+        for m in messages.iter() {
+            write_message(&mut wire, m).unwrap();
+        }
+        let mut r = &wire[..];
+        for m in messages.iter() {
+            assert_eq!(&read_message(&mut r).unwrap(), m);
+        }
+        assert!(read_message(&mut r).is_err());
+        assert!(Message::decode(&[9]).is_err());
+        assert!(Message::decode(&[HASH, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn refuses_other_versions() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let host = thread::spawn(move || {
+            Connection::accept(&listener, [1, 2, 3, 4], Difficulty::Normal).map(|_| ())
+        });
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write_message(
+            &mut stream,
+            &Message::Hello {
+                version: PROTOCOL_VERSION + 1,
+            },
+        )
+        .unwrap();
+        match read_message(&mut stream).unwrap() {
+            Message::Disconnect { reason } => assert!(reason.contains("protocol version")),
+            m => panic!("expected a disconnect, got {:?}", m),
+        }
+        assert!(host.join().unwrap().is_err());
+    }
+
+    #[test]
+    fn two_peers_play_to_the_end() {
+        let (host, guest) = match_up([11, 22, 33, 44], None);
+        let (host, guest) = (host.unwrap(), guest.unwrap());
+        assert_eq!(host, guest);
+        assert!(host.0 > 0);
+        // both bots got some hits in, so the balls went the same way too
+        assert!(host.2.iter().all(|&s| s > 0));
+    }
+
+    #[test]
+    fn notices_a_desync() {
+        let (host, guest) = match_up([11, 22, 33, 44], Some([55, 66, 77, 88]));
+        assert!(host.is_err() || guest.is_err());
+    }
+}
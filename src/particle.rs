@@ -0,0 +1,357 @@
+use ggez::graphics::{self, Color, DrawMode, Point2};
+use ggez::{Context, GameResult};
+use rand::{Rng, SeedableRng, XorShiftRng};
+
+// Colours a particle passes through over its life, `t` going 0..1.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColorRamp {
+    stops: Vec<(f32, Color)>,
+}
+
+impl ColorRamp {
+    pub fn new(stops: Vec<(f32, Color)>) -> ColorRamp {
+        ColorRamp { stops: stops }
+    }
+
+    // one colour that fades out to transparent
+    pub fn fade(color: Color) -> ColorRamp {
+        ColorRamp::new(vec![
+            (0.0, color),
+            (1.0, Color::new(color.r, color.g, color.b, 0.0)),
+        ])
+    }
+
+    pub fn sample(&self, t: f32) -> Color {
+        let first = match self.stops.first() {
+            Some(s) => s,
+            None => return Color::new(1.0, 1.0, 1.0, 1.0),
+        };
+        if t <= first.0 {
+            return first.1;
+        }
+        for w in self.stops.windows(2) {
+            let ((t0, c0), (t1, c1)) = (w[0], w[1]);
+            if t <= t1 {
+                let k = if t1 > t0 { (t - t0) / (t1 - t0) } else { 1.0 };
+                return Color::new(
+                    c0.r + (c1.r - c0.r) * k,
+                    c0.g + (c1.g - c0.g) * k,
+                    c0.b + (c1.b - c0.b) * k,
+                    c0.a + (c1.a - c0.a) * k,
+                );
+            }
+        }
+        self.stops[self.stops.len() - 1].1
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Shape {
+    Circle,
+    Square,
+    // an outline whose stroke is a fifth of its radius
+    Ring,
+}
+
+// How a burst of particles starts out. Ranges are (min, max) and picked
+// per particle; angles are radians with 0 pointing right and y down.
+#[derive(Clone, Debug)]
+pub struct Emitter {
+    pub count: usize,
+    pub speed: (f32, f32),
+    pub direction: f32,
+    pub spread: f32,
+    pub life: (f32, f32),
+    pub size: (f32, f32),
+    // change of size per second, negative to shrink
+    pub grow: f32,
+    pub gravity: f32,
+    // fraction of velocity lost per second
+    pub drag: f32,
+    pub shape: Shape,
+    pub ramp: ColorRamp,
+}
+
+impl Emitter {
+    // square chips thrown up and out of a destroyed block
+    pub fn shards(color: Color, width: f32) -> Emitter {
+        Emitter {
+            count: 12,
+            speed: (width * 1.5, width * 4.0),
+            direction: -::std::f32::consts::FRAC_PI_2,
+            spread: ::std::f32::consts::PI,
+            life: (0.4, 0.8),
+            size: (width * 0.08, width * 0.16),
+            grow: 0.0,
+            gravity: width * 12.0,
+            drag: 0.5,
+            shape: Shape::Square,
+            ramp: ColorRamp::fade(color),
+        }
+    }
+
+    // a slow ring of dust where a ball hits the ground
+    pub fn dust(color: Color, width: f32) -> Emitter {
+        Emitter {
+            count: 8,
+            speed: (width * 0.3, width * 0.9),
+            direction: 0.0,
+            spread: ::std::f32::consts::PI,
+            life: (0.3, 0.6),
+            size: (width * 0.05, width * 0.1),
+            grow: width * 0.2,
+            gravity: 0.0,
+            drag: 3.0,
+            shape: Shape::Circle,
+            ramp: ColorRamp::new(vec![
+                (0.0, Color::new(color.r, color.g, color.b, 0.6)),
+                (1.0, Color::new(color.r, color.g, color.b, 0.0)),
+            ]),
+        }
+    }
+
+    // an outline spreading from where a ball touches down
+    pub fn splash(color: Color, width: f32) -> Emitter {
+        Emitter {
+            count: 1,
+            speed: (0.0, 0.0),
+            direction: 0.0,
+            spread: 0.0,
+            life: (0.3, 0.3),
+            size: (width * 0.15, width * 0.15),
+            grow: width * 1.2,
+            gravity: 0.0,
+            drag: 0.0,
+            shape: Shape::Ring,
+            ramp: ColorRamp::fade(color),
+        }
+    }
+
+    // a single swelling disc over a block that ran out of time
+    pub fn flash(color: Color, width: f32) -> Emitter {
+        Emitter {
+            count: 1,
+            speed: (0.0, 0.0),
+            direction: 0.0,
+            spread: 0.0,
+            life: (0.35, 0.35),
+            size: (width * 0.4, width * 0.4),
+            grow: width * 1.5,
+            gravity: 0.0,
+            drag: 0.0,
+            shape: Shape::Circle,
+            ramp: ColorRamp::new(vec![
+                (0.0, Color::new(color.r, color.g, color.b, 0.8)),
+                (0.3, Color::new(color.r, color.g, color.b, 0.5)),
+                (1.0, Color::new(color.r, color.g, color.b, 0.0)),
+            ]),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Particle {
+    pos: (f32, f32),
+    vel: (f32, f32),
+    age: f32,
+    life: f32,
+    size: f32,
+    grow: f32,
+    gravity: f32,
+    drag: f32,
+    shape: Shape,
+    // index into ParticleSystem::ramps
+    ramp: usize,
+}
+
+impl Particle {
+    fn progress(&self) -> f32 {
+        (self.age / self.life).min(1.0)
+    }
+}
+
+// Owns every live particle. Spawning uses its own seeded generator and
+// updates only depend on the time step, so a run can be replayed exactly.
+#[derive(Debug)]
+pub struct ParticleSystem {
+    particles: Vec<Particle>,
+    ramps: Vec<ColorRamp>,
+    rng: XorShiftRng,
+    limit: usize,
+}
+
+impl ParticleSystem {
+    pub fn new(seed: u32, limit: usize) -> ParticleSystem {
+        ParticleSystem {
+            particles: vec![],
+            ramps: vec![],
+            rng: XorShiftRng::from_seed([seed | 1, 0x9e37_79b9, 0x85eb_ca6b, 0xc2b2_ae35]),
+            limit: limit,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.particles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.particles.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.particles.clear();
+        self.ramps.clear();
+    }
+
+    fn pick(&mut self, (min, max): (f32, f32)) -> f32 {
+        if max > min {
+            self.rng.gen_range(min, max)
+        } else {
+            min
+        }
+    }
+
+    pub fn emit(&mut self, emitter: &Emitter, x: f32, y: f32) {
+        let ramp = match self.ramps.iter().position(|r| *r == emitter.ramp) {
+            Some(i) => i,
+            None => {
+                self.ramps.push(emitter.ramp.clone());
+                self.ramps.len() - 1
+            }
+        };
+        for _ in 0..emitter.count {
+            if self.particles.len() >= self.limit {
+                break;
+            }
+            let half = emitter.spread / 2.0;
+            let angle = emitter.direction + self.pick((-half, half));
+            let speed = self.pick(emitter.speed);
+            let p = Particle {
+                pos: (x, y),
+                vel: (angle.cos() * speed, angle.sin() * speed),
+                age: 0.0,
+                life: self.pick(emitter.life).max(1e-3),
+                size: self.pick(emitter.size),
+                grow: emitter.grow,
+                gravity: emitter.gravity,
+                drag: emitter.drag,
+                shape: emitter.shape,
+                ramp: ramp,
+            };
+            self.particles.push(p);
+        }
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        for p in self.particles.iter_mut() {
+            p.age += dt;
+            let keep = (1.0 - p.drag * dt).max(0.0);
+            p.vel.0 *= keep;
+            p.vel.1 = p.vel.1 * keep + p.gravity * dt;
+            p.pos.0 += p.vel.0 * dt;
+            p.pos.1 += p.vel.1 * dt;
+            p.size = (p.size + p.grow * dt).max(0.0);
+        }
+        self.particles.retain(|p| p.age < p.life);
+        if self.particles.is_empty() {
+            self.ramps.clear();
+        }
+    }
+
+    pub fn draw(&self, ctx: &mut Context) -> GameResult<()> {
+        for p in self.particles.iter() {
+            let color = self.ramps[p.ramp].sample(p.progress());
+            if color.a <= 0.0 || p.size <= 0.0 {
+                continue;
+            }
+            graphics::set_color(ctx, color)?;
+            match p.shape {
+                Shape::Circle => {
+                    graphics::circle(
+                        ctx,
+                        DrawMode::Fill,
+                        Point2::new(p.pos.0, p.pos.1),
+                        p.size,
+                        0.5,
+                    )?;
+                }
+                Shape::Ring => {
+                    let p2 = Point2::new(p.pos.0, p.pos.1);
+                    graphics::circle(ctx, DrawMode::Line(p.size * 0.2), p2, p.size, 0.5)?;
+                }
+                Shape::Square => {
+                    let r = graphics::Rect::new(
+                        p.pos.0 - p.size / 2.0,
+                        p.pos.1 - p.size / 2.0,
+                        p.size,
+                        p.size,
+                    );
+                    graphics::rectangle(ctx, DrawMode::Fill, r)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // position and colour of every particle, mostly for tests
+    pub fn snapshot(&self) -> Vec<((f32, f32), Color)> {
+        self.particles
+            .iter()
+            .map(|p| (p.pos, self.ramps[p.ramp].sample(p.progress())))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(seed: u32) -> Vec<((f32, f32), Color)> {
+        let mut ps = ParticleSystem::new(seed, 256);
+        ps.emit(
+            &Emitter::shards(Color::from_rgb(54, 100, 139), 40.0),
+            100.0,
+            100.0,
+        );
+        ps.emit(&Emitter::dust(Color::from_rgb(0, 0, 0), 40.0), 200.0, 600.0);
+        for _ in 0..10 {
+            ps.update(1.0 / 60.0);
+        }
+        ps.snapshot()
+    }
+
+    #[test]
+    fn same_seed_same_particles() {
+        let a = run(7);
+        assert_eq!(a.len(), 20);
+        assert_eq!(a, run(7));
+        assert_ne!(a, run(8));
+    }
+
+    #[test]
+    fn particles_fade_and_expire() {
+        let mut ps = ParticleSystem::new(1, 16);
+        ps.emit(
+            &Emitter::flash(Color::from_rgb(255, 0, 0), 40.0),
+            50.0,
+            50.0,
+        );
+        let start = ps.snapshot()[0].1.a;
+        ps.update(0.2);
+        let (pos, color) = ps.snapshot()[0];
+        assert_eq!(pos, (50.0, 50.0));
+        assert!(color.a < start);
+        ps.update(0.2);
+        assert!(ps.is_empty());
+    }
+
+    #[test]
+    fn respects_limit_and_ramps() {
+        let mut ps = ParticleSystem::new(3, 5);
+        ps.emit(&Emitter::shards(Color::from_rgb(0, 0, 0), 40.0), 0.0, 0.0);
+        assert_eq!(ps.len(), 5);
+        let ramp = ColorRamp::fade(Color::new(1.0, 0.0, 0.0, 1.0));
+        assert_eq!(ramp.sample(0.5), Color::new(1.0, 0.0, 0.0, 0.5));
+        assert_eq!(ramp.sample(2.0).a, 0.0);
+    }
+}
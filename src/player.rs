@@ -0,0 +1,246 @@
+use std::f32::consts::PI;
+
+use ggez::{graphics, Context, GameResult};
+
+use super::bar::{BarDirection, Ring, TimerBar};
+use super::rules::{CHARGE_TIME, READY_TIME};
+use super::sim::SimPlayer;
+use super::theme::Theme;
+use super::timer::Timer;
+use super::tween::{Easing, Tween};
+
+// seconds a score takes to roll up to a new value
+pub const SCORE_ROLL: f32 = 0.6;
+
+// how fast the keyboard aim turns, radians a second
+const TURN_SPEED: f32 = 1.5;
+// furthest the keyboard aim turns either side of straight up
+const MAX_ANGLE: f32 = PI * 0.45;
+
+// How a player aims and charges their launcher.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Control {
+    Mouse,
+    // aim keys turn the launcher, the charge key works like the mouse button
+    Keyboard,
+}
+
+// Everything one player owns: their launcher, power bar, cooldown and score.
+#[derive(Debug)]
+pub struct Player {
+    pub control: Control,
+    pub score: usize,
+    pub score_shown: Tween,
+    pub combo: usize,
+    // where this player's balls start, on the bottom edge
+    pub launcher: (f32, f32),
+    pub power_bar: TimerBar,
+    // runs while the next ball gets ready
+    pub ready_timer: Timer,
+    // cooldown around the launcher
+    pub ring: Ring,
+    // world point being aimed at
+    pub aim: (f32, f32),
+    // keyboard aim in radians from straight up, and which way it is turning
+    angle: f32,
+    turning: f32,
+}
+
+// the point `reach` away from `launcher` at `angle` from straight up
+pub fn keyboard_aim(launcher: (f32, f32), angle: f32, reach: f32) -> (f32, f32) {
+    (
+        launcher.0 + reach * angle.sin(),
+        launcher.1 - reach * angle.cos(),
+    )
+}
+
+// index of the player with the highest score, None on a draw
+pub fn winner(players: &[Player]) -> Option<usize> {
+    let best = players.iter().map(|p| p.score).max()?;
+    let mut leaders = players.iter().enumerate().filter(|&(_, p)| p.score == best);
+    match (leaders.next(), leaders.next()) {
+        (Some((i, _)), None) => Some(i),
+        _ => None,
+    }
+}
+
+impl Player {
+    // placed properly by `place` once the layout is known
+    pub fn new(control: Control) -> Player {
+        let mut power_bar = TimerBar::new(CHARGE_TIME, 0.0, 0.0, 1.0, 1.0);
+        power_bar.set_direction(BarDirection::Vertical);
+        power_bar.set_increase(true);
+        power_bar.set_smoothing(0.15);
+        Player {
+            control: control,
+            score: 0,
+            score_shown: Tween::new(0.0, 0.0, SCORE_ROLL, Easing::EaseOut),
+            combo: 0,
+            launcher: (0.0, 0.0),
+            power_bar: power_bar,
+            ready_timer: Timer::new(READY_TIME),
+            ring: Ring::new(0.0, 0.0, 1.0, 1.0),
+            aim: (0.0, 0.0),
+            angle: 0.0,
+            turning: 0.0,
+        }
+    }
+
+    // `bar_x` is the left edge of the power bar
+    pub fn place(&mut self, launcher: (f32, f32), bar_x: f32, delta_length: f32) {
+        self.launcher = launcher;
+        self.power_bar
+            .set_position(bar_x, launcher.1 - delta_length * 3.2);
+        self.power_bar
+            .set_size(delta_length * 3.0, delta_length * 0.4);
+        self.ring.set_position(launcher.0, launcher.1);
+        self.ring.set_radius(delta_length * 0.42);
+        self.ring.set_thickness(delta_length * 0.08);
+    }
+
+    pub fn apply_theme(&mut self, theme: &Theme, line_width: f32) {
+        self.power_bar.set_color(theme.bar_fill);
+        self.power_bar.set_rim_color(theme.bar_rim);
+        self.power_bar.set_rim_width(line_width);
+        self.ring.set_color(theme.launcher);
+        self.ring.set_rim_color(theme.bar_rim);
+    }
+
+    // back to the start of a round
+    pub fn reset(&mut self) {
+        self.score = 0;
+        self.score_shown.snap(0.0);
+        self.combo = 0;
+        self.ready_timer.stop();
+        self.power_bar.pause();
+        self.angle = 0.0;
+        self.turning = 0.0;
+    }
+
+    // holds the cooldown and charge still, as during hit-stop
+    pub fn delay(&mut self, seconds: f64) {
+        self.ready_timer.delay(seconds);
+        self.power_bar.delay(seconds);
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready_timer.is_stopped()
+    }
+
+    pub fn is_charging(&self) -> bool {
+        !self.power_bar.is_stopped() && !self.power_bar.is_paused()
+    }
+
+    // -1 turns left, 1 right, 0 stops
+    pub fn set_turning(&mut self, direction: f32) {
+        self.turning = direction;
+    }
+
+    // moves the keyboard aim; `reach` is how far ahead the aim point sits
+    pub fn turn(&mut self, dt: f32, reach: f32) {
+        if self.control != Control::Keyboard {
+            return;
+        }
+        self.angle = (self.angle + self.turning * TURN_SPEED * dt)
+            .max(-MAX_ANGLE)
+            .min(MAX_ANGLE);
+        self.aim = keyboard_aim(self.launcher, self.angle, reach);
+    }
+
+    pub fn update(&mut self, ctx: &Context, dt: f32) {
+        self.ready_timer.update(ctx);
+        self.ring.set_value(self.ready_timer.get_value().min(1.0));
+        self.ready_timer.get_event();
+        self.power_bar.update(ctx);
+        self.animate(dt);
+    }
+
+    // the power bar and score easing towards their values
+    pub fn animate(&mut self, dt: f32) {
+        self.power_bar.animate(dt);
+        self.score_shown.retarget(self.score as f32);
+        self.score_shown.update(dt);
+    }
+
+    // shows a simulated player: the simulation keeps the time, so the
+    // clocks only start and stop to say whether a ball is ready and the bar
+    // is filling
+    pub fn mirror(&mut self, ctx: &Context, player: &SimPlayer, aim: (f32, f32)) {
+        self.score = player.score;
+        self.aim = aim;
+        if player.is_ready() {
+            self.ready_timer.stop();
+        } else if self.ready_timer.is_stopped() {
+            self.ready_timer.start(ctx);
+        }
+        self.ring.set_value(player.readiness());
+        if player.is_charging() && !self.is_charging() {
+            self.power_bar.start(ctx);
+        } else if !player.is_charging() && self.is_charging() {
+            self.power_bar.pause();
+        }
+        if player.is_charging() {
+            self.power_bar.set_value(player.charge());
+        }
+    }
+
+    // filled when a ball is ready, an outline and the cooldown ring otherwise
+    pub fn draw_launcher(
+        &self,
+        ctx: &mut Context,
+        color: graphics::Color,
+        radius: f32,
+        line_width: f32,
+        tolerance: f32,
+    ) -> GameResult<()> {
+        let pos = graphics::Point2::new(self.launcher.0, self.launcher.1);
+        graphics::set_color(ctx, color)?;
+        if self.is_ready() {
+            graphics::circle(ctx, graphics::DrawMode::Fill, pos, radius, tolerance)?;
+        } else {
+            graphics::circle(
+                ctx,
+                graphics::DrawMode::Line(line_width),
+                pos,
+                radius,
+                tolerance,
+            )?;
+            self.ring.draw(ctx)?;
+        }
+        if self.control == Control::Keyboard {
+            // the keyboard player can't see a cursor, so show the aim
+            let (x, y) = keyboard_aim(self.launcher, self.angle, radius * 3.0);
+            graphics::line(ctx, &[pos, graphics::Point2::new(x, y)], line_width * 2.0)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keyboard_aim_turns_and_clamps() {
+        let (x, y) = keyboard_aim((100.0, 200.0), 0.0, 50.0);
+        assert!((x - 100.0).abs() < 1e-4 && (y - 150.0).abs() < 1e-4);
+        let mut p = Player::new(Control::Keyboard);
+        p.launcher = (100.0, 200.0);
+        p.set_turning(1.0);
+        p.turn(10.0, 50.0);
+        assert!(p.aim.0 > 140.0 && p.aim.1 < 200.0);
+        p.set_turning(-1.0);
+        p.turn(0.5, 50.0);
+        assert!(p.aim.0 < 140.0);
+    }
+
+    #[test]
+    fn picks_the_winner() {
+        let mut players = vec![Player::new(Control::Mouse), Player::new(Control::Keyboard)];
+        assert_eq!(winner(&players), None);
+        players[1].score = 3;
+        assert_eq!(winner(&players), Some(1));
+        players[0].score = 4;
+        assert_eq!(winner(&players), Some(0));
+    }
+}
@@ -0,0 +1,140 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use ggez::Context;
+use toml;
+
+use super::assets::Assets;
+use super::error::{Error, Result};
+
+const PUZZLE_DIR: &str = "/puzzles";
+
+fn default_two_stars() -> usize {
+    1
+}
+
+fn default_three_stars() -> usize {
+    2
+}
+
+// A hand-made board: blocks on fixed cells and a number of shots to clear
+// them with. Blocks in a puzzle never time out.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Puzzle {
+    // key for completion in the save file, the file name if left out
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    pub shots: usize,
+    // [row, column] of each block, row 0 at the top of the board
+    pub blocks: Vec<(usize, usize)>,
+    // shots left over needed for two and three stars; any clear is one
+    #[serde(default = "default_two_stars")]
+    pub two_stars: usize,
+    #[serde(default = "default_three_stars")]
+    pub three_stars: usize,
+}
+
+impl Puzzle {
+    // `board` is the number of cells along each side
+    pub fn from_toml(s: &str, board: usize) -> Result<Puzzle> {
+        let p: Puzzle = toml::from_str(s).map_err(|e| Error::Config(e.to_string()))?;
+        if p.shots == 0 {
+            return Err(Error::Config("a puzzle needs at least one shot".to_owned()));
+        }
+        if p.blocks.is_empty() {
+            return Err(Error::Config(
+                "a puzzle needs at least one block".to_owned(),
+            ));
+        }
+        let mut seen = HashSet::new();
+        for &(row, col) in p.blocks.iter() {
+            if row >= board || col >= board {
+                return Err(Error::Config(format!(
+                    "block [{}, {}] is off the {}x{} board",
+                    row, col, board, board
+                )));
+            }
+            if !seen.insert((row, col)) {
+                return Err(Error::Config(format!(
+                    "block [{}, {}] is listed twice",
+                    row, col
+                )));
+            }
+        }
+        if p.three_stars < p.two_stars {
+            return Err(Error::Config(
+                "three_stars can't be below two_stars".to_owned(),
+            ));
+        }
+        Ok(p)
+    }
+
+    // cell indices the way the board counts them, row by row
+    pub fn cells(&self, board: usize) -> Vec<usize> {
+        self.blocks
+            .iter()
+            .map(|&(row, col)| row * board + col)
+            .collect()
+    }
+
+    // rating for a clear with `shots_left` to spare, 1 to 3
+    pub fn stars(&self, shots_left: usize) -> u8 {
+        1 + (shots_left >= self.two_stars) as u8 + (shots_left >= self.three_stars) as u8
+    }
+}
+
+// every *.toml under /puzzles, in file name order
+pub fn load(ctx: &mut Context, assets: &mut Assets, board: usize) -> Result<Vec<Puzzle>> {
+    let mut files: Vec<String> = match ctx.filesystem.read_dir(PUZZLE_DIR) {
+        Ok(dir) => dir
+            .filter(|p| p.extension().map_or(false, |e| e == "toml"))
+            .filter_map(|p| p.to_str().map(|s| s.to_owned()))
+            .collect(),
+        Err(_) => vec![],
+    };
+    files.sort();
+    let mut list = vec![];
+    for f in files.iter() {
+        let s = assets.read_string(ctx, f)?;
+        let mut p =
+            Puzzle::from_toml(&s, board).map_err(|e| Error::Config(format!("{}: {}", f, e)))?;
+        if p.id.is_empty() {
+            p.id = Path::new(f)
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or("")
+                .to_owned();
+        }
+        list.push(p);
+    }
+    Ok(list)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_rates() {
+        let p = Puzzle::from_toml(
+            "name = \"corners\"\nshots = 5\nblocks = [[0, 0], [7, 7]]\nthree_stars = 3",
+            8,
+        )
+        .unwrap();
+        assert_eq!(p.cells(8), vec![0, 63]);
+        assert_eq!(p.stars(0), 1);
+        assert_eq!(p.stars(1), 2);
+        assert_eq!(p.stars(3), 3);
+    }
+
+    #[test]
+    fn rejects_bad_layouts() {
+        assert!(Puzzle::from_toml("name = \"x\"\nshots = 2\nblocks = [[8, 0]]", 8).is_err());
+        assert!(
+            Puzzle::from_toml("name = \"x\"\nshots = 2\nblocks = [[1, 1], [1, 1]]", 8).is_err()
+        );
+        assert!(Puzzle::from_toml("name = \"x\"\nshots = 0\nblocks = [[1, 1]]", 8).is_err());
+        assert!(Puzzle::from_toml("name = \"x\"\nshots = 2\nblocks = []", 8).is_err());
+    }
+}
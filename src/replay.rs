@@ -0,0 +1,347 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use toml;
+
+use super::error::{Error, Result};
+use super::save::{self, config_dir};
+use super::sim::{Input, Sim, ROUND_TICKS};
+
+pub const REPLAY_VERSION: u32 = 1;
+const REPLAY_DIR: &str = "replays";
+
+// The charge button going down or up, and where the player was aiming on
+// that tick. The sim only reads the aim when a charge is released, so these
+// are all of a run's input it needs.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Press {
+    pub tick: u32,
+    pub x: f32,
+    pub y: f32,
+    pub down: bool,
+}
+
+// A solo run: the seed it was played on and its input, enough to play it
+// again exactly.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    pub seed: [u32; 4],
+    pub score: usize,
+    // day it was played, "2024-03-01"
+    pub date: String,
+    pub presses: Vec<Press>,
+}
+
+// replays/ in the config directory
+pub fn dir() -> Option<PathBuf> {
+    config_dir().map(|d| d.join(REPLAY_DIR))
+}
+
+// every readable replay in `dir`, best first, and what was wrong with the rest
+pub fn load_all(dir: &Path) -> (Vec<Replay>, Vec<Error>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return (vec![], vec![]),
+        Err(e) => {
+            return (
+                vec![],
+                vec![Error::Replay(format!("{}: {}", dir.display(), e))],
+            )
+        }
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().map_or(false, |x| x == "toml"))
+        .collect();
+    paths.sort();
+    let (mut replays, mut errors) = (vec![], vec![]);
+    for path in paths {
+        match Replay::load(&path) {
+            Ok(r) => replays.push(r),
+            Err(e) => errors.push(e),
+        }
+    }
+    replays.sort_by(|a, b| b.score.cmp(&a.score));
+    (replays, errors)
+}
+
+impl Replay {
+    pub fn new(seed: [u32; 4], date: &str) -> Replay {
+        Replay {
+            version: REPLAY_VERSION,
+            seed: seed,
+            score: 0,
+            date: date.to_owned(),
+            presses: vec![],
+        }
+    }
+
+    // checks the presses make sense; whether they get the score claimed is
+    // left to `verify`, which has to play the whole run
+    pub fn from_toml(s: &str) -> Result<Replay> {
+        let r: Replay = toml::from_str(s).map_err(|e| Error::Replay(e.to_string()))?;
+        if r.version > REPLAY_VERSION {
+            return Err(Error::Replay(format!(
+                "written by a newer version (format {}, this build reads up to {})",
+                r.version, REPLAY_VERSION
+            )));
+        }
+        let mut down = false;
+        let mut tick = 0;
+        for p in r.presses.iter() {
+            if p.tick < tick || p.tick >= ROUND_TICKS {
+                return Err(Error::Replay(format!(
+                    "press at tick {} is out of order or past the end",
+                    p.tick
+                )));
+            }
+            if p.down == down {
+                return Err(Error::Replay(format!(
+                    "button {} twice at tick {}",
+                    if down { "down" } else { "up" },
+                    p.tick
+                )));
+            }
+            down = p.down;
+            tick = p.tick + 1;
+        }
+        Ok(Replay {
+            version: REPLAY_VERSION,
+            ..r
+        })
+    }
+
+    // plays the run out and checks it gets the score it claims, before it
+    // is raced
+    pub fn verify(&self) -> Result<()> {
+        let score = self.play_out();
+        if score != self.score {
+            return Err(Error::Replay(format!(
+                "{}: claims a score of {} but plays out to {}",
+                self.file_name(),
+                self.score,
+                score
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        toml::Value::try_from(self)
+            .and_then(|v| toml::to_string(&v))
+            .map_err(|e| Error::Replay(e.to_string()))
+    }
+
+    pub fn load(path: &Path) -> Result<Replay> {
+        let name = path.file_name().map_or(path.to_path_buf(), PathBuf::from);
+        fs::read_to_string(path)
+            .map_err(Error::from)
+            .and_then(|s| Replay::from_toml(&s))
+            .map_err(|e| Error::Replay(format!("{}: {}", name.display(), e)))
+    }
+
+    // one file per seed, so a better run on it replaces the old one
+    pub fn file_name(&self) -> String {
+        let s = self.seed;
+        format!("{:08x}{:08x}{:08x}{:08x}.toml", s[0], s[1], s[2], s[3])
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        let path = dir.join(self.file_name());
+        save::write_atomic(&path, &self.to_toml()?)
+            .map_err(|e| Error::Replay(format!("{}: {}", path.display(), e)))
+    }
+
+    // adds `input` if the button changed since the last press
+    pub fn record(&mut self, tick: u32, input: Input) {
+        let down = self.presses.last().map_or(false, |p| p.down);
+        if input.charging != down {
+            self.presses.push(Press {
+                tick: tick,
+                x: input.aim.0,
+                y: input.aim.1,
+                down: input.charging,
+            });
+        }
+    }
+
+    // the score the presses actually get
+    pub fn play_out(&self) -> usize {
+        let mut ghost = Ghost::new(self.clone());
+        while !ghost.sim.is_over() {
+            ghost.step();
+        }
+        ghost.sim.players[0].score
+    }
+}
+
+// A saved run played back tick by tick.
+#[derive(Debug)]
+pub struct Ghost {
+    pub replay: Replay,
+    pub sim: Sim,
+    // index of the next press to apply
+    next: usize,
+    input: Input,
+}
+
+impl Ghost {
+    pub fn new(replay: Replay) -> Ghost {
+        Ghost {
+            sim: Sim::solo(replay.seed),
+            replay: replay,
+            next: 0,
+            input: Input::default(),
+        }
+    }
+
+    pub fn step(&mut self) {
+        let tick = self.sim.tick();
+        while let Some(p) = self
+            .replay
+            .presses
+            .get(self.next)
+            .filter(|p| p.tick == tick)
+        {
+            self.input = Input {
+                aim: (p.x, p.y),
+                charging: p.down,
+            };
+            self.next += 1;
+        }
+        self.sim.step(&[self.input]);
+    }
+}
+
+// A solo run against an optional ghost on the same seed, recorded as it
+// goes.
+#[derive(Debug)]
+pub struct Race {
+    pub sim: Sim,
+    pub ghost: Option<Ghost>,
+    recording: Replay,
+}
+
+impl Race {
+    pub fn new(seed: [u32; 4], ghost: Option<Replay>, date: &str) -> Race {
+        Race {
+            sim: Sim::solo(seed),
+            ghost: ghost.map(Ghost::new),
+            recording: Replay::new(seed, date),
+        }
+    }
+
+    pub fn seed(&self) -> [u32; 4] {
+        self.recording.seed
+    }
+
+    pub fn score(&self) -> usize {
+        self.sim.players[0].score
+    }
+
+    pub fn ghost_score(&self) -> Option<usize> {
+        self.ghost.as_ref().map(|g| g.sim.players[0].score)
+    }
+
+    pub fn step(&mut self, input: Input) {
+        if self.sim.is_over() {
+            return;
+        }
+        self.recording.record(self.sim.tick(), input);
+        self.sim.step(&[input]);
+        if let Some(ref mut g) = self.ghost {
+            g.step();
+        }
+    }
+
+    // the finished run if it is worth keeping: a new best on its seed
+    pub fn result(&self) -> Option<Replay> {
+        let beat = self.ghost.as_ref().map_or(0, |g| g.replay.score);
+        if self.score() <= beat {
+            return None;
+        }
+        Some(Replay {
+            score: self.score(),
+            ..self.recording.clone()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // charges just enough for the first block on the board
+    fn bot(sim: &Sim) -> Input {
+        match sim.blocks.first() {
+            Some(b) => {
+                let target = sim.cell_target(b.cell);
+                let needed = sim.charge_for(0, target).unwrap_or(1.0);
+                Input {
+                    aim: target,
+                    charging: sim.players[0].is_ready() && sim.players[0].charge() < needed,
+                }
+            }
+            None => Input::default(),
+        }
+    }
+
+    fn race(seed: [u32; 4], ghost: Option<Replay>) -> Race {
+        let mut race = Race::new(seed, ghost, "2024-03-01");
+        while !race.sim.is_over() {
+            let input = bot(&race.sim);
+            race.step(input);
+        }
+        race
+    }
+
+    #[test]
+    fn ghost_replays_the_run() {
+        let first = race([9, 8, 7, 6], None);
+        let replay = first.result().unwrap();
+        assert!(replay.score > 0);
+        assert!(replay.verify().is_ok());
+        let back = Replay::from_toml(&replay.to_toml().unwrap()).unwrap();
+        assert_eq!(back, replay);
+
+        // the same moves again only tie, so there is nothing new to keep
+        let second = race([9, 8, 7, 6], Some(replay.clone()));
+        assert_eq!(second.ghost_score(), Some(replay.score));
+        assert_eq!(second.ghost.as_ref().unwrap().sim.hash(), second.sim.hash());
+        assert_eq!(second.result(), None);
+    }
+
+    #[test]
+    fn rejects_doctored_replays() {
+        let replay = race([1, 1, 2, 3], None).result().unwrap();
+        let mut r = replay.clone();
+        r.score += 1;
+        assert!(Replay::from_toml(&r.to_toml().unwrap())
+            .unwrap()
+            .verify()
+            .is_err());
+        let mut r = replay.clone();
+        r.presses.swap(0, 1);
+        assert!(Replay::from_toml(&r.to_toml().unwrap()).is_err());
+        assert!(Replay::from_toml("version = 99").is_err());
+    }
+
+    #[test]
+    fn keeps_one_file_per_seed() {
+        let dir = std::env::temp_dir().join(format!("fallingball-replays-{}", std::process::id()));
+        assert_eq!(load_all(&dir).0, vec![]);
+        let a = race([4, 3, 2, 1], None).result().unwrap();
+        let b = race([5, 3, 2, 1], None).result().unwrap();
+        a.save(&dir).unwrap();
+        b.save(&dir).unwrap();
+        a.save(&dir).unwrap();
+        fs::write(dir.join("junk.toml"), "score = 3").unwrap();
+        let (replays, errors) = load_all(&dir);
+        assert_eq!(replays.len(), 2);
+        assert!(replays[0].score >= replays[1].score);
+        assert_eq!(errors.len(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,160 @@
+// The rules every way of playing shares: the board, the lives, how long
+// things take, how hard a ball is thrown and what it breaks. The local game
+// runs them on its clocks and the simulation behind LAN games and races in
+// ticks, so the two can't drift apart.
+
+use rand::Rng;
+
+use super::ball::Ball;
+use super::mode::Mode;
+use super::settings::Difficulty;
+use super::spawn;
+
+// cells along each side of the board
+pub const BOARD: usize = 8;
+pub const CELLS: usize = BOARD * BOARD;
+// lives at the start; losing a block with none left ends the game
+pub const LIVES: usize = 5;
+// seconds a fully charged ball stays up
+pub const BALL_MAX_TIME: f32 = 1.5;
+// seconds a block stays up at normal difficulty
+pub const BLOCK_ALIVE: f64 = 10.0;
+// seconds before a launcher can throw again
+pub const READY_TIME: f64 = 1.0;
+// seconds the power bar takes to fill
+pub const CHARGE_TIME: f64 = 1.0;
+// seconds before the first block on an empty board
+pub const FIRST_SPAWN: f64 = 0.1;
+
+// side of one cell in a world `world_height` tall
+pub fn delta_length(world_height: f32) -> f32 {
+    world_height / (BOARD + 8) as f32
+}
+
+// left edge of the board, centred in a world `world_width` wide
+pub fn left_point(world_width: f32, delta_length: f32) -> f32 {
+    (world_width - BOARD as f32 * delta_length) / 2.0
+}
+
+// top left corner of a board cell
+pub fn cell_pos(world_width: f32, delta_length: f32, cell: usize) -> (f32, f32) {
+    (
+        (cell % BOARD) as f32 * delta_length + left_point(world_width, delta_length),
+        (cell / BOARD + 2) as f32 * delta_length,
+    )
+}
+
+// player `i`'s launcher on the bottom edge: under each half of the board when
+// two play side by side, in the middle otherwise
+pub fn launcher_pos(
+    (width, height): (f32, f32),
+    delta_length: f32,
+    side_by_side: bool,
+    i: usize,
+) -> (f32, f32) {
+    if !side_by_side {
+        return (width / 2.0, height);
+    }
+    let share = if i == 0 { 0.25 } else { 0.75 };
+    (
+        left_point(width, delta_length) + delta_length * BOARD as f32 * share,
+        height,
+    )
+}
+
+// the longest throw, from a launcher to the far top corner it has to reach
+pub fn max_range(delta_length: f32, side_by_side: bool) -> f32 {
+    // a launcher under one half of the board still reaches the far corner
+    let share = if side_by_side { 0.75 } else { 0.5 };
+    let w = delta_length * BOARD as f32 * share;
+    let h = delta_length * (6 + BOARD) as f32;
+    (w * w + h * h).sqrt()
+}
+
+pub fn max_vel(delta_length: f32, side_by_side: bool) -> f32 {
+    max_range(delta_length, side_by_side) / BALL_MAX_TIME
+}
+
+pub fn ball_radius(delta_length: f32) -> f32 {
+    delta_length * 0.2
+}
+
+// launch velocity of a ball thrown from `from` at `aim` with the power bar
+// at `charge`, which tops out at 1
+pub fn shot_velocity(
+    from: (f32, f32),
+    aim: (f32, f32),
+    charge: f32,
+    max_vel: f32,
+) -> (f32, f32, f32) {
+    let point = (aim.0 - from.0, aim.1 - from.1);
+    let len = (point.0 * point.0 + point.1 * point.1).sqrt().max(1e-3);
+    let power = Ball::get_vel_alpha(charge.min(1.0), BALL_MAX_TIME);
+    (
+        point.0 / len * max_vel * power,
+        point.1 / len * max_vel * power,
+        power,
+    )
+}
+
+// where a ball has to come down to break the block in the cell whose top
+// left corner is `pos`
+pub fn hit_centre(pos: (f32, f32), width: f32) -> (f32, f32) {
+    (pos.0 + width / 2.0, pos.1 + width * 0.6)
+}
+
+// whether a ball of `radius` landing at `point` breaks that block
+pub fn is_hit(pos: (f32, f32), width: f32, point: (f32, f32), radius: f32) -> bool {
+    let c = hit_centre(pos, width);
+    let reach = 0.48 * width + radius;
+    (point.0 - c.0).powi(2) + (point.1 - c.1).powi(2) <= reach * reach
+}
+
+// seconds a new block stays up
+pub fn block_time(difficulty: Difficulty) -> f64 {
+    BLOCK_ALIVE * difficulty.block_time()
+}
+
+// an empty cell picked at random, None on a full board
+pub fn spawn_cell<R: Rng>(rng: &mut R, cells: &[bool]) -> Option<usize> {
+    let free = cells.iter().filter(|&&taken| !taken).count();
+    if free == 0 {
+        return None;
+    }
+    spawn::nth_free(cells, rng.gen_range(0, free))
+}
+
+// seconds until the next spawn with `blocks` on the board, `roll` being a
+// random draw in 0..1; an empty board fills straight away
+pub fn spawn_wait(
+    mode: Mode,
+    difficulty: Difficulty,
+    progress: f32,
+    blocks: usize,
+    roll: f64,
+) -> f64 {
+    if blocks == 0 {
+        return FIRST_SPAWN;
+    }
+    difficulty.spawn_time() * mode.spawn_gap(progress, blocks, roll)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn launchers_reach_the_far_corner() {
+        let world = (400.0, 600.0);
+        let dl = delta_length(world.1);
+        for &(side_by_side, i) in [(false, 0), (true, 0), (true, 1)].iter() {
+            let from = launcher_pos(world, dl, side_by_side, i);
+            let far = if i == 0 { BOARD - 1 } else { 0 };
+            let (x, y) = hit_centre(cell_pos(world.0, dl, far), dl);
+            let range = ((x - from.0).powi(2) + (y - from.1).powi(2)).sqrt();
+            assert!(range < max_range(dl, side_by_side));
+        }
+        assert!(is_hit((0.0, 0.0), 10.0, (5.0, 6.0 + 4.7), 0.0));
+        assert!(!is_hit((0.0, 0.0), 10.0, (5.0, 6.0 + 4.9), 0.0));
+    }
+}
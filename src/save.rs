@@ -0,0 +1,228 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use toml;
+
+use super::daily::DailyHistory;
+use super::error::{Error, Result};
+use super::mode::{Board, Mode, TIME_ATTACK_LENGTHS};
+
+pub const SAVE_VERSION: u32 = 1;
+const SAVE_NAME: &str = "save.toml";
+const LEADERBOARD_SIZE: usize = 10;
+
+// Where the game keeps files it writes: $XDG_CONFIG_HOME/fallingball,
+// ~/.config/fallingball or %APPDATA%\fallingball. Plain std::fs so it can
+// be read before the ggez context exists.
+pub fn config_dir() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))
+        .or_else(|| env::var_os("HOME").map(|h| Path::new(&h).join(".config")));
+    base.map(|b| b.join("fallingball"))
+}
+
+// writes next to the target and renames, so a crash never leaves half a file
+pub fn write_atomic(path: &Path, contents: &str) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension("tmp");
+    {
+        let mut f = fs::File::create(&tmp)?;
+        f.write_all(contents.as_bytes())?;
+        f.sync_all()?;
+    }
+    fs::rename(&tmp, path)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SaveData {
+    pub version: u32,
+    pub last_mode: Mode,
+    pub last_score: usize,
+    pub best_score: usize,
+    // best endless scores, highest first
+    pub leaderboard: Vec<usize>,
+    // round length last picked for time attack, in seconds
+    pub time_attack_length: u32,
+    // best time attack scores keyed by round length in seconds
+    pub time_attack: BTreeMap<String, Vec<usize>>,
+    // best star rating of every cleared puzzle, by puzzle id
+    pub puzzles: BTreeMap<String, u8>,
+    pub daily: DailyHistory,
+}
+
+// puts `score` in its place on a highest-first board
+fn insert_score(board: &mut Vec<usize>, score: usize) {
+    let pos = board.iter().position(|&s| s < score).unwrap_or(board.len());
+    if pos < LEADERBOARD_SIZE && score > 0 {
+        board.insert(pos, score);
+        board.truncate(LEADERBOARD_SIZE);
+    }
+}
+
+impl Default for SaveData {
+    fn default() -> SaveData {
+        SaveData {
+            version: SAVE_VERSION,
+            last_mode: Mode::Endless,
+            last_score: 0,
+            best_score: 0,
+            leaderboard: vec![],
+            time_attack_length: TIME_ATTACK_LENGTHS[0],
+            time_attack: BTreeMap::new(),
+            puzzles: BTreeMap::new(),
+            daily: DailyHistory::default(),
+        }
+    }
+}
+
+impl SaveData {
+    pub fn path() -> Option<PathBuf> {
+        config_dir().map(|d| d.join(SAVE_NAME))
+    }
+
+    pub fn from_toml(s: &str) -> Result<SaveData> {
+        let data: SaveData = toml::from_str(s).map_err(|e| Error::SaveData(e.to_string()))?;
+        if data.version > SAVE_VERSION {
+            return Err(Error::SaveData(format!(
+                "written by a newer version (format {}, this build reads up to {})",
+                data.version, SAVE_VERSION
+            )));
+        }
+        Ok(SaveData {
+            version: SAVE_VERSION,
+            ..data
+        })
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        // going through a Value puts the plain keys ahead of the tables
+        toml::Value::try_from(self)
+            .and_then(|v| toml::to_string(&v))
+            .map_err(|e| Error::SaveData(e.to_string()))
+    }
+
+    // a missing file is a fresh start, anything else unreadable is an error
+    pub fn load(path: &Path) -> Result<SaveData> {
+        match fs::read_to_string(path) {
+            Ok(s) => SaveData::from_toml(&s)
+                .map_err(|e| Error::SaveData(format!("{}: {}", path.display(), e))),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(SaveData::default()),
+            Err(e) => Err(Error::SaveData(format!("{}: {}", path.display(), e))),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        write_atomic(path, &self.to_toml()?)
+            .map_err(|e| Error::SaveData(format!("{}: {}", path.display(), e)))
+    }
+
+    // returns true for a new best
+    pub fn record_score(&mut self, score: usize) -> bool {
+        self.last_score = score;
+        insert_score(&mut self.leaderboard, score);
+        if score > self.best_score {
+            self.best_score = score;
+            return true;
+        }
+        false
+    }
+
+    // returns true for a new best at this round length
+    pub fn record_time_attack(&mut self, seconds: u32, score: usize) -> bool {
+        self.last_score = score;
+        let board = self
+            .time_attack
+            .entry(seconds.to_string())
+            .or_insert_with(Vec::new);
+        let best = board.first().map_or(score > 0, |&b| score > b);
+        insert_score(board, score);
+        best
+    }
+
+    // returns true if the puzzle was cleared with more stars than before
+    pub fn record_puzzle(&mut self, id: &str, stars: u8) -> bool {
+        let best = self.puzzles.entry(id.to_owned()).or_insert(0);
+        if stars > *best {
+            *best = stars;
+            return true;
+        }
+        false
+    }
+
+    pub fn puzzle_stars(&self, id: &str) -> u8 {
+        self.puzzles.get(id).cloned().unwrap_or(0)
+    }
+
+    pub fn board(&self, board: Board) -> &[usize] {
+        match board {
+            Board::Endless => &self.leaderboard,
+            Board::TimeAttack(s) => self.time_attack.get(&s.to_string()).map_or(&[], |b| &b[..]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_and_checks_version() {
+        let mut data = SaveData::default();
+        data.last_mode = Mode::Practice;
+        data.record_score(12);
+        let back = SaveData::from_toml(&data.to_toml().unwrap()).unwrap();
+        assert_eq!(back, data);
+        assert_eq!(SaveData::from_toml("").unwrap(), SaveData::default());
+        assert!(SaveData::from_toml("version = 99").is_err());
+    }
+
+    #[test]
+    fn keeps_best_scores() {
+        let mut data = SaveData::default();
+        for s in 0..15 {
+            data.record_score(s);
+        }
+        assert!(!data.record_score(3));
+        assert_eq!(data.best_score, 14);
+        assert_eq!(data.last_score, 3);
+        assert_eq!(data.leaderboard.len(), 10);
+        assert_eq!(data.leaderboard[0], 14);
+        assert_eq!(data.leaderboard[9], 5);
+    }
+
+    #[test]
+    fn time_attack_has_its_own_boards() {
+        let mut data = SaveData::default();
+        assert!(data.record_time_attack(60, 20));
+        assert!(!data.record_time_attack(60, 15));
+        assert!(data.record_time_attack(120, 5));
+        assert_eq!(data.board(Board::TimeAttack(60)), &[20, 15]);
+        assert_eq!(data.board(Board::TimeAttack(180)), &[] as &[usize]);
+        assert!(data.board(Board::Endless).is_empty());
+        assert!(data.record_puzzle("corners", 2));
+        assert!(!data.record_puzzle("corners", 1));
+        assert_eq!(data.puzzle_stars("corners"), 2);
+        assert_eq!(data.puzzle_stars("diamond"), 0);
+        let back = SaveData::from_toml(&data.to_toml().unwrap()).unwrap();
+        assert_eq!(back, data);
+    }
+
+    #[test]
+    fn saves_to_disk() {
+        let dir = env::temp_dir().join(format!("fallingball-save-{}", std::process::id()));
+        let path = dir.join(SAVE_NAME);
+        assert_eq!(SaveData::load(&path).unwrap(), SaveData::default());
+        let mut data = SaveData::default();
+        data.record_score(7);
+        data.save(&path).unwrap();
+        assert_eq!(SaveData::load(&path).unwrap(), data);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
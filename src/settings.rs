@@ -0,0 +1,501 @@
+use std::fs;
+use std::io::{self, Read};
+use std::path::{self, Path, PathBuf};
+
+use ggez::Context;
+use toml;
+
+use super::block::TimerStyle;
+use super::error::{Error, Result};
+use super::save;
+use super::theme::Palette;
+
+const SETTINGS_NAME: &str = "/settings.toml";
+const PREFERENCES_NAME: &str = "preferences.toml";
+pub const PREFERENCES_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioSettings {
+    pub volume: f32,
+    pub music_volume: f32,
+    pub muted: bool,
+}
+
+impl Default for AudioSettings {
+    fn default() -> AudioSettings {
+        AudioSettings {
+            volume: 1.0,
+            music_volume: 0.6,
+            muted: false,
+        }
+    }
+}
+
+impl AudioSettings {
+    pub fn effect_volume(&self) -> f32 {
+        if self.muted {
+            0.0
+        } else {
+            self.volume
+        }
+    }
+
+    pub fn music_gain(&self) -> f32 {
+        if self.muted {
+            0.0
+        } else {
+            self.volume * self.music_volume
+        }
+    }
+}
+
+pub const FONT_SIZE_MIN: u32 = 8;
+pub const FONT_SIZE_MAX: u32 = 32;
+pub const UI_SCALE_MIN: f32 = 0.5;
+pub const UI_SCALE_MAX: f32 = 3.0;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AccessibilitySettings {
+    pub palette: Palette,
+    pub high_contrast: bool,
+    pub urgency_cues: bool,
+    pub font_size: u32,
+    // multiplies text size and line widths on top of the window scale
+    pub ui_scale: f32,
+    // turns off trails, splashes and other decoration that moves
+    pub reduce_motion: bool,
+}
+
+impl Default for AccessibilitySettings {
+    fn default() -> AccessibilitySettings {
+        AccessibilitySettings {
+            palette: Palette::Default,
+            high_contrast: false,
+            urgency_cues: false,
+            font_size: 12,
+            ui_scale: 1.0,
+            reduce_motion: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EffectSettings {
+    pub ball_shadow: bool,
+    pub ball_trail: bool,
+    pub landing_splash: bool,
+    // screen shake, hit-stop and flashes; 0 turns them off
+    pub camera_intensity: f32,
+}
+
+impl Default for EffectSettings {
+    fn default() -> EffectSettings {
+        EffectSettings {
+            ball_shadow: true,
+            ball_trail: true,
+            landing_splash: true,
+            camera_intensity: 1.0,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Default for Difficulty {
+    fn default() -> Difficulty {
+        Difficulty::Normal
+    }
+}
+
+impl Difficulty {
+    pub fn next(self) -> Difficulty {
+        match self {
+            Difficulty::Easy => Difficulty::Normal,
+            Difficulty::Normal => Difficulty::Hard,
+            Difficulty::Hard => Difficulty::Easy,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Normal => "normal",
+            Difficulty::Hard => "hard",
+        }
+    }
+
+    // scales how long a block stays up
+    pub fn block_time(self) -> f64 {
+        match self {
+            Difficulty::Easy => 1.5,
+            Difficulty::Normal => 1.0,
+            Difficulty::Hard => 0.7,
+        }
+    }
+
+    // scales the wait between new blocks
+    pub fn spawn_time(self) -> f64 {
+        match self {
+            Difficulty::Easy => 1.3,
+            Difficulty::Normal => 1.0,
+            Difficulty::Hard => 0.75,
+        }
+    }
+}
+
+// Things a key can be bound to during play.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Action {
+    Mute,
+    Fullscreen,
+    Theme,
+    Palette,
+    HighContrast,
+    UrgencyCues,
+    ReduceMotion,
+    BlockTimer,
+    FontSmaller,
+    FontLarger,
+    UiSmaller,
+    UiLarger,
+    // practice only
+    Undo,
+    // the keyboard player's launcher in two-player games
+    AimLeft,
+    AimRight,
+    Charge,
+}
+
+impl Action {
+    pub const ALL: [Action; 16] = [
+        Action::Mute,
+        Action::Fullscreen,
+        Action::Theme,
+        Action::Palette,
+        Action::HighContrast,
+        Action::UrgencyCues,
+        Action::ReduceMotion,
+        Action::BlockTimer,
+        Action::FontSmaller,
+        Action::FontLarger,
+        Action::UiSmaller,
+        Action::UiLarger,
+        Action::Undo,
+        Action::AimLeft,
+        Action::AimRight,
+        Action::Charge,
+    ];
+
+    pub fn title(self) -> &'static str {
+        match self {
+            Action::Mute => "mute",
+            Action::Fullscreen => "fullscreen",
+            Action::Theme => "next theme",
+            Action::Palette => "colour palette",
+            Action::HighContrast => "high contrast",
+            Action::UrgencyCues => "urgency stripes",
+            Action::ReduceMotion => "reduce motion",
+            Action::BlockTimer => "block timer style",
+            Action::FontSmaller => "smaller text",
+            Action::FontLarger => "larger text",
+            Action::UiSmaller => "smaller UI",
+            Action::UiLarger => "larger UI",
+            Action::Undo => "undo shot (practice)",
+            Action::AimLeft => "player 2 aim left",
+            Action::AimRight => "player 2 aim right",
+            Action::Charge => "player 2 charge",
+        }
+    }
+
+    // game controls that act while the key is held rather than on a press
+    pub fn is_held(self) -> bool {
+        match self {
+            Action::AimLeft | Action::AimRight | Action::Charge => true,
+            _ => false,
+        }
+    }
+}
+
+// Key names as SDL spells them ("M", "F11", "[" ...).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyBindings {
+    pub mute: String,
+    pub fullscreen: String,
+    pub theme: String,
+    pub palette: String,
+    pub high_contrast: String,
+    pub urgency_cues: String,
+    pub reduce_motion: String,
+    pub block_timer: String,
+    pub font_smaller: String,
+    pub font_larger: String,
+    pub ui_smaller: String,
+    pub ui_larger: String,
+    pub undo: String,
+    pub aim_left: String,
+    pub aim_right: String,
+    pub charge: String,
+}
+
+impl Default for KeyBindings {
+    fn default() -> KeyBindings {
+        KeyBindings {
+            mute: "M".to_owned(),
+            fullscreen: "F11".to_owned(),
+            theme: "T".to_owned(),
+            palette: "C".to_owned(),
+            high_contrast: "H".to_owned(),
+            urgency_cues: "U".to_owned(),
+            reduce_motion: "R".to_owned(),
+            block_timer: "B".to_owned(),
+            font_smaller: "-".to_owned(),
+            font_larger: "=".to_owned(),
+            ui_smaller: "[".to_owned(),
+            ui_larger: "]".to_owned(),
+            undo: "Z".to_owned(),
+            aim_left: "Left".to_owned(),
+            aim_right: "Right".to_owned(),
+            charge: "Space".to_owned(),
+        }
+    }
+}
+
+impl KeyBindings {
+    fn slot(&mut self, action: Action) -> &mut String {
+        match action {
+            Action::Mute => &mut self.mute,
+            Action::Fullscreen => &mut self.fullscreen,
+            Action::Theme => &mut self.theme,
+            Action::Palette => &mut self.palette,
+            Action::HighContrast => &mut self.high_contrast,
+            Action::UrgencyCues => &mut self.urgency_cues,
+            Action::ReduceMotion => &mut self.reduce_motion,
+            Action::BlockTimer => &mut self.block_timer,
+            Action::FontSmaller => &mut self.font_smaller,
+            Action::FontLarger => &mut self.font_larger,
+            Action::UiSmaller => &mut self.ui_smaller,
+            Action::UiLarger => &mut self.ui_larger,
+            Action::Undo => &mut self.undo,
+            Action::AimLeft => &mut self.aim_left,
+            Action::AimRight => &mut self.aim_right,
+            Action::Charge => &mut self.charge,
+        }
+    }
+
+    pub fn get(&self, action: Action) -> &str {
+        match action {
+            Action::Mute => &self.mute,
+            Action::Fullscreen => &self.fullscreen,
+            Action::Theme => &self.theme,
+            Action::Palette => &self.palette,
+            Action::HighContrast => &self.high_contrast,
+            Action::UrgencyCues => &self.urgency_cues,
+            Action::ReduceMotion => &self.reduce_motion,
+            Action::BlockTimer => &self.block_timer,
+            Action::FontSmaller => &self.font_smaller,
+            Action::FontLarger => &self.font_larger,
+            Action::UiSmaller => &self.ui_smaller,
+            Action::UiLarger => &self.ui_larger,
+            Action::Undo => &self.undo,
+            Action::AimLeft => &self.aim_left,
+            Action::AimRight => &self.aim_right,
+            Action::Charge => &self.charge,
+        }
+    }
+
+    // binds `key` to `action`; whatever had that key gets the action's old one
+    pub fn set(&mut self, action: Action, key: &str) {
+        let old = self.get(action).to_owned();
+        if let Some(other) = self.action_for(key) {
+            *self.slot(other) = old;
+        }
+        *self.slot(action) = key.to_owned();
+    }
+
+    // like `set`, but refuses to leave a hotkey on one of the `reserved`
+    // keys, either directly or through the swap
+    pub fn try_set(&mut self, action: Action, key: &str, reserved: &[String]) -> bool {
+        let taken = |k: &str| reserved.iter().any(|r| r.eq_ignore_ascii_case(k));
+        if !action.is_held() && taken(key) {
+            return false;
+        }
+        if let Some(other) = self.action_for(key) {
+            if other != action && !other.is_held() && taken(self.get(action)) {
+                return false;
+            }
+        }
+        self.set(action, key);
+        true
+    }
+
+    pub fn action_for(&self, key: &str) -> Option<Action> {
+        Action::ALL
+            .iter()
+            .cloned()
+            .find(|&a| self.get(a).eq_ignore_ascii_case(key))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    // format of the preferences file, see PREFERENCES_VERSION
+    pub version: u32,
+    pub audio: AudioSettings,
+    // directory or zip with a pack.toml, see assets::ResourcePack
+    pub resource_pack: Option<String>,
+    pub theme: String,
+    pub accessibility: AccessibilitySettings,
+    pub effects: EffectSettings,
+    pub block_timer: TimerStyle,
+    pub difficulty: Difficulty,
+    // marks where the charged shot will land
+    pub aim_preview: bool,
+    pub fullscreen: bool,
+    pub keys: KeyBindings,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            version: PREFERENCES_VERSION,
+            audio: AudioSettings::default(),
+            resource_pack: None,
+            theme: "light".to_owned(),
+            accessibility: AccessibilitySettings::default(),
+            effects: EffectSettings::default(),
+            block_timer: TimerStyle::Bar,
+            difficulty: Difficulty::Normal,
+            aim_preview: false,
+            fullscreen: false,
+            keys: KeyBindings::default(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn ball_trail(&self) -> bool {
+        self.effects.ball_trail && !self.accessibility.reduce_motion
+    }
+
+    pub fn landing_splash(&self) -> bool {
+        self.effects.landing_splash && !self.accessibility.reduce_motion
+    }
+
+    pub fn camera_intensity(&self) -> f32 {
+        if self.accessibility.reduce_motion {
+            0.0
+        } else {
+            self.effects.camera_intensity.max(0.0)
+        }
+    }
+
+    pub fn from_toml(s: &str) -> Result<Settings> {
+        let settings: Settings = toml::from_str(s).map_err(|e| Error::Config(e.to_string()))?;
+        if settings.version > PREFERENCES_VERSION {
+            return Err(Error::Config(format!(
+                "written by a newer version (format {}, this build reads up to {})",
+                settings.version, PREFERENCES_VERSION
+            )));
+        }
+        Ok(Settings {
+            version: PREFERENCES_VERSION,
+            ..settings
+        })
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        // going through a Value puts the plain keys ahead of the tables
+        toml::Value::try_from(self)
+            .and_then(|v| toml::to_string(&v))
+            .map_err(|e| Error::Config(e.to_string()))
+    }
+
+    // the player's own preferences, next to the save file
+    pub fn path() -> Option<PathBuf> {
+        save::config_dir().map(|d| d.join(PREFERENCES_NAME))
+    }
+
+    // None when the player has never saved preferences
+    pub fn load_file(path: &Path) -> Result<Option<Settings>> {
+        match fs::read_to_string(path) {
+            Ok(s) => Settings::from_toml(&s)
+                .map(Some)
+                .map_err(|e| Error::Config(format!("{}: {}", path.display(), e))),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(Error::Config(format!("{}: {}", path.display(), e))),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        save::write_atomic(path, &self.to_toml()?)
+            .map_err(|e| Error::Config(format!("{}: {}", path.display(), e)))
+    }
+
+    // falls back to the defaults when no settings file is mounted
+    pub fn load(ctx: &mut Context) -> Result<Settings> {
+        let settings_path = path::Path::new(SETTINGS_NAME);
+        if !ctx.filesystem.is_file(settings_path) {
+            return Ok(Settings::default());
+        }
+        let mut file = ctx.filesystem.open(settings_path)?;
+        let mut s = String::new();
+        file.read_to_string(&mut s)?;
+        Settings::from_toml(&s).map_err(|e| Error::Config(format!("{}: {}", SETTINGS_NAME, e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preferences_round_trip() {
+        let mut settings = Settings::default();
+        settings.difficulty = Difficulty::Hard;
+        settings.aim_preview = true;
+        settings.keys.set(Action::Mute, "N");
+        let back = Settings::from_toml(&settings.to_toml().unwrap()).unwrap();
+        assert_eq!(back.difficulty, Difficulty::Hard);
+        assert!(back.aim_preview);
+        assert_eq!(back.keys, settings.keys);
+        assert!(Settings::from_toml("version = 2").is_err());
+        assert_eq!(
+            Settings::from_toml("").unwrap().version,
+            PREFERENCES_VERSION
+        );
+    }
+
+    #[test]
+    fn rebinding_swaps_keys() {
+        let mut keys = KeyBindings::default();
+        assert_eq!(keys.action_for("m"), Some(Action::Mute));
+        keys.set(Action::Mute, "T");
+        assert_eq!(keys.action_for("T"), Some(Action::Mute));
+        assert_eq!(keys.get(Action::Theme), "M");
+        assert_eq!(keys.action_for("Q"), None);
+    }
+
+    #[test]
+    fn hotkeys_stay_off_menu_keys() {
+        let menu: Vec<String> = vec!["Space".to_owned(), "Return".to_owned()];
+        let mut keys = KeyBindings::default();
+        assert!(!keys.try_set(Action::Theme, "return", &menu));
+        // undo would end up on Space
+        assert!(!keys.try_set(Action::Charge, "Z", &menu));
+        assert_eq!(keys, KeyBindings::default());
+        assert!(keys.try_set(Action::Charge, "Return", &menu));
+        assert!(keys.try_set(Action::Theme, "M", &menu));
+        assert_eq!(keys.get(Action::Mute), "T");
+    }
+}
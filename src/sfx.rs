@@ -0,0 +1,236 @@
+use std::f32::consts::FRAC_PI_4;
+use std::io::Cursor;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use ggez::audio::SoundData;
+use rodio::source::ChannelVolume;
+use rodio::{self, Sample, Source};
+
+use super::error::{Error, Result};
+
+const COMBO_PITCH_STEP: f32 = 0.06;
+const COMBO_PITCH_MAX: usize = 10;
+const CHARGE_PITCH_MIN: f32 = 0.8;
+const CHARGE_PITCH_RANGE: f32 = 0.6;
+
+#[repr(u8)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Effect {
+    Boom = 0,
+    Loss = 1,
+    Shot = 2,
+    EnergyCharge = 3,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct EffectParams {
+    pub volume: f32,
+    // -1 is hard left, 1 is hard right
+    pub pan: f32,
+    pub pitch: f32,
+}
+
+impl Default for EffectParams {
+    fn default() -> EffectParams {
+        EffectParams {
+            volume: 1.0,
+            pan: 0.0,
+            pitch: 1.0,
+        }
+    }
+}
+
+// left and right gains for `pan`, keeping the loudness the same across
+pub fn pan_gains(pan: f32) -> (f32, f32) {
+    let angle = (pan.max(-1.0).min(1.0) + 1.0) * FRAC_PI_4;
+    (angle.cos(), angle.sin())
+}
+
+// A playback rate that can change while a sound plays.
+#[derive(Clone, Debug)]
+pub struct Pitch(Arc<AtomicU32>);
+
+impl Pitch {
+    pub fn new(pitch: f32) -> Pitch {
+        Pitch(Arc::new(AtomicU32::new(pitch.to_bits())))
+    }
+
+    pub fn set(&self, pitch: f32) {
+        self.0.store(pitch.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+}
+
+// Reports its input's sample rate scaled by the pitch; rodio resamples every
+// frame from what it reports, so the pitch follows changes as it plays.
+struct Glide<I> {
+    input: I,
+    pitch: Pitch,
+}
+
+impl<I> Iterator for Glide<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        self.input.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<I> Source for Glide<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        ((self.input.sample_rate() as f32 * self.pitch.get()) as u32).max(1)
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+// Plays effects through rodio directly: ggez's Source only has a volume
+// control, and effects need stereo balance and pitch too.
+pub struct Voices {
+    device: rodio::Device,
+}
+
+impl Voices {
+    pub fn new() -> Result<Voices> {
+        rodio::default_output_device()
+            .map(|device| Voices { device: device })
+            .ok_or_else(|| Error::Audio("no audio output device".to_owned()))
+    }
+
+    // plays `data` to the end with `params`, `volume` being the master volume
+    pub fn play(&self, data: &SoundData, params: EffectParams, volume: f32) -> Result<()> {
+        self.start(data, params, volume, &Pitch::new(params.pitch))
+            .map(rodio::Sink::detach)
+    }
+
+    // like `play`, but at `pitch` in place of the params' own, and the sound
+    // stops when the returned sink is dropped
+    pub fn start(
+        &self,
+        data: &SoundData,
+        params: EffectParams,
+        volume: f32,
+        pitch: &Pitch,
+    ) -> Result<rodio::Sink> {
+        let decoder = rodio::Decoder::new(Cursor::new(data.clone()))
+            .map_err(|e| Error::Audio(e.to_string()))?;
+        let (left, right) = pan_gains(params.pan);
+        let source = ChannelVolume::new(
+            Glide {
+                input: decoder,
+                pitch: pitch.clone(),
+            },
+            vec![left, right],
+        );
+        let mut sink = rodio::Sink::new(&self.device);
+        sink.set_volume(volume * params.volume);
+        sink.append(source);
+        Ok(sink)
+    }
+}
+
+pub fn pan_for(x: f32, width: f32) -> f32 {
+    if width <= 0.0 {
+        return 0.0;
+    }
+    (x / width * 2.0 - 1.0).max(-1.0).min(1.0)
+}
+
+pub fn landing_params(effect: Effect, landing_x: f32, width: f32, combo: usize) -> EffectParams {
+    let pitch = match effect {
+        Effect::Boom => 1.0 + COMBO_PITCH_STEP * combo.min(COMBO_PITCH_MAX) as f32,
+        _ => 1.0,
+    };
+    EffectParams {
+        volume: 1.0,
+        pan: pan_for(landing_x, width),
+        pitch: pitch,
+    }
+}
+
+pub fn charge_params(charge: f32) -> EffectParams {
+    EffectParams {
+        volume: 1.0,
+        pan: 0.0,
+        pitch: CHARGE_PITCH_MIN + CHARGE_PITCH_RANGE * charge.max(0.0).min(1.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pan_keeps_loudness() {
+        let (l, r) = pan_gains(0.0);
+        assert!((l - r).abs() < 1e-6);
+        assert!(pan_gains(-1.0).1.abs() < 1e-6);
+        assert!(pan_gains(1.0).0.abs() < 1e-6);
+        for &pan in [-1.0, -0.4, 0.0, 0.7, 1.0].iter() {
+            let (l, r) = pan_gains(pan);
+            assert!((l * l + r * r - 1.0).abs() < 1e-5);
+        }
+        let p = Pitch::new(1.0);
+        p.set(1.3);
+        assert_eq!(p.clone().get(), 1.3);
+    }
+
+    #[test]
+    fn pans_by_landing_x() {
+        assert_eq!(landing_params(Effect::Loss, 0.0, 400.0, 0).pan, -1.0);
+        assert_eq!(landing_params(Effect::Loss, 200.0, 400.0, 0).pan, 0.0);
+        assert_eq!(landing_params(Effect::Boom, 400.0, 400.0, 0).pan, 1.0);
+        assert_eq!(landing_params(Effect::Boom, 900.0, 400.0, 0).pan, 1.0);
+    }
+
+    #[test]
+    fn boom_pitch_rises_with_combo() {
+        let low = landing_params(Effect::Boom, 200.0, 400.0, 0).pitch;
+        let high = landing_params(Effect::Boom, 200.0, 400.0, 3).pitch;
+        let capped = landing_params(Effect::Boom, 200.0, 400.0, 50).pitch;
+        assert_eq!(low, 1.0);
+        assert!(high > low);
+        assert_eq!(
+            capped,
+            landing_params(Effect::Boom, 200.0, 400.0, COMBO_PITCH_MAX).pitch
+        );
+        assert_eq!(landing_params(Effect::Loss, 200.0, 400.0, 5).pitch, 1.0);
+    }
+
+    #[test]
+    fn charge_pitch_follows_power() {
+        assert_eq!(charge_params(0.0).pitch, CHARGE_PITCH_MIN);
+        assert_eq!(
+            charge_params(1.0).pitch,
+            CHARGE_PITCH_MIN + CHARGE_PITCH_RANGE
+        );
+        assert!(charge_params(0.7).pitch > charge_params(0.3).pitch);
+    }
+}
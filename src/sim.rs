@@ -0,0 +1,738 @@
+use std::collections::VecDeque;
+
+use rand::{thread_rng, Rng, SeedableRng, XorShiftRng};
+
+use super::ball::Ball;
+use super::mode::Mode;
+use super::rules::{self, CELLS};
+use super::settings::Difficulty;
+use super::view;
+
+// The shared rules stepped at a fixed rate with no clocks and no Context, so
+// two machines fed the same seed and inputs stay in step.
+pub const TICK_RATE: u32 = 60;
+pub const TICK: f32 = 1.0 / TICK_RATE as f32;
+
+// a match also ends after three minutes, however well both defend
+pub const ROUND_TICKS: u32 = 180 * TICK_RATE;
+
+// the first tick at or after `seconds`, as the clocks of a local game see it
+pub fn ticks(seconds: f64) -> u32 {
+    (seconds * f64::from(TICK_RATE)).ceil() as u32
+}
+
+// What one player does during one tick.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Input {
+    // world point being aimed at
+    pub aim: (f32, f32),
+    // the charge button is held
+    pub charging: bool,
+}
+
+// The charge button as the ticks see it. Presses and releases come in
+// between ticks, and each one is kept for a tick of its own, so a click
+// quicker than a tick still charges and throws.
+#[derive(Clone, Debug, Default)]
+pub struct Button {
+    held: bool,
+    changes: VecDeque<bool>,
+}
+
+impl Button {
+    pub fn set(&mut self, down: bool) {
+        if down != self.held {
+            self.held = down;
+            self.changes.push_back(down);
+        }
+    }
+
+    // whether the next tick sees the button down
+    pub fn tick(&mut self) -> bool {
+        self.changes.pop_front().unwrap_or(self.held)
+    }
+}
+
+#[derive(Debug)]
+pub struct SimPlayer {
+    pub score: usize,
+    pub launcher: (f32, f32),
+    // ticks the button has been held, None when not charging
+    charge_ticks: Option<u32>,
+    // ticks until the next ball is ready
+    ready_in: u32,
+}
+
+impl SimPlayer {
+    pub fn charge(&self) -> f32 {
+        self.charge_ticks.map_or(0.0, |t| {
+            (t as f32 / ticks(rules::CHARGE_TIME) as f32).min(1.0)
+        })
+    }
+
+    pub fn is_charging(&self) -> bool {
+        self.charge_ticks.is_some()
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready_in == 0
+    }
+
+    // share of the cooldown gone by, 1 once ready
+    pub fn readiness(&self) -> f32 {
+        1.0 - self.ready_in as f32 / ticks(rules::READY_TIME) as f32
+    }
+}
+
+#[derive(Debug)]
+pub struct SimBlock {
+    pub cell: usize,
+    // tick it goes at, and how many ticks it had
+    expires: u32,
+    life: u32,
+}
+
+// Something that happened on a tick, for the screen and the speakers.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    // a block came on in the cell
+    Spawned(usize),
+    // the player threw a ball
+    Thrown(usize),
+    // a ball of `owner`'s came down at `pos` and broke the blocks in `cells`
+    Landed {
+        owner: usize,
+        pos: (f32, f32),
+        cells: Vec<usize>,
+    },
+    // the block in the cell ran out of time
+    Expired(usize),
+}
+
+#[derive(Debug)]
+pub struct SimBall {
+    pub ball: Ball,
+    pub owner: usize,
+}
+
+#[derive(Debug)]
+pub struct Sim {
+    rng: XorShiftRng,
+    tick: u32,
+    mode: Mode,
+    difficulty: Difficulty,
+    delta_length: f32,
+    pub players: Vec<SimPlayer>,
+    pub blocks: Vec<SimBlock>,
+    pub balls: Vec<SimBall>,
+    // what happened on the last tick, in order
+    pub events: Vec<Event>,
+    // shared lives, the game ends when one is lost with none left
+    pub left: usize,
+    // tick the next block comes on
+    spawn_at: u32,
+    // two launchers under the two halves of the board
+    side_by_side: bool,
+    // set when the lives run out
+    over: bool,
+}
+
+pub fn random_seed() -> [u32; 4] {
+    let mut seed: [u32; 4] = thread_rng().gen();
+    // an all-zero seed would stall the generator
+    seed[0] |= 1;
+    seed
+}
+
+// FNV-1a, enough to notice two simulations drifting apart
+fn mix(hash: &mut u64, value: u64) {
+    for i in 0..8 {
+        *hash ^= (value >> (i * 8)) & 0xff;
+        *hash = hash.wrapping_mul(0x100_0000_01b3);
+    }
+}
+
+impl Sim {
+    // a versus game: two launchers side by side under the board
+    pub fn new(seed: [u32; 4], difficulty: Difficulty) -> Sim {
+        Sim::with_players(seed, Mode::Versus, difficulty, 2)
+    }
+
+    // a race: one launcher in the middle, as in an endless game; always at
+    // normal difficulty, like the daily, so every run on a seed compares
+    pub fn solo(seed: [u32; 4]) -> Sim {
+        Sim::with_players(seed, Mode::Race, Difficulty::Normal, 1)
+    }
+
+    fn with_players(seed: [u32; 4], mode: Mode, difficulty: Difficulty, players: usize) -> Sim {
+        let delta_length = rules::delta_length(view::WORLD_HEIGHT);
+        let side_by_side = players > 1;
+        let mut sim = Sim {
+            rng: XorShiftRng::from_seed(seed),
+            tick: 0,
+            mode: mode,
+            difficulty: difficulty,
+            delta_length: delta_length,
+            players: (0..players)
+                .map(|i| SimPlayer {
+                    score: 0,
+                    launcher: rules::launcher_pos(
+                        (view::WORLD_WIDTH, view::WORLD_HEIGHT),
+                        delta_length,
+                        side_by_side,
+                        i,
+                    ),
+                    charge_ticks: None,
+                    ready_in: 0,
+                })
+                .collect(),
+            blocks: vec![],
+            balls: vec![],
+            events: vec![],
+            left: rules::LIVES,
+            spawn_at: 0,
+            side_by_side: side_by_side,
+            over: false,
+        };
+        sim.spawn_at = sim.spawn_wait();
+        sim
+    }
+
+    pub fn tick(&self) -> u32 {
+        self.tick
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn difficulty(&self) -> Difficulty {
+        self.difficulty
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.over || self.tick >= ROUND_TICKS
+    }
+
+    pub fn seconds_left(&self) -> u32 {
+        (ROUND_TICKS.saturating_sub(self.tick) + TICK_RATE - 1) / TICK_RATE
+    }
+
+    pub fn delta_length(&self) -> f32 {
+        self.delta_length
+    }
+
+    // top left corner of a board cell
+    pub fn cell_pos(&self, cell: usize) -> (f32, f32) {
+        rules::cell_pos(view::WORLD_WIDTH, self.delta_length, cell)
+    }
+
+    // where a ball has to come down to break the block in `cell`
+    pub fn cell_target(&self, cell: usize) -> (f32, f32) {
+        rules::hit_centre(self.cell_pos(cell), self.delta_length)
+    }
+
+    // share of its time a block has left, 1 when it appears
+    pub fn time_left(&self, block: &SimBlock) -> f32 {
+        block.expires.saturating_sub(self.tick) as f32 / block.life as f32
+    }
+
+    fn max_vel(&self) -> f32 {
+        rules::max_vel(self.delta_length, self.side_by_side)
+    }
+
+    // charge that brings a ball from `player`'s launcher down on `target`
+    pub fn charge_for(&self, player: usize, target: (f32, f32)) -> Option<f32> {
+        let from = self.players[player].launcher;
+        let range = ((target.0 - from.0).powi(2) + (target.1 - from.1).powi(2)).sqrt();
+        Ball::charge_for_range(range, self.max_vel(), rules::BALL_MAX_TIME)
+    }
+
+    fn cells(&self) -> [bool; CELLS] {
+        let mut cells = [false; CELLS];
+        for b in self.blocks.iter() {
+            cells[b.cell] = true;
+        }
+        cells
+    }
+
+    // tick of the next spawn, drawing its random part as a local game does
+    fn spawn_wait(&mut self) -> u32 {
+        let roll = self.rng.gen::<f64>();
+        let wait = rules::spawn_wait(self.mode, self.difficulty, 0.0, self.blocks.len(), roll);
+        self.tick + ticks(wait)
+    }
+
+    fn spawn_block(&mut self) {
+        let cells = self.cells();
+        if let Some(cell) = rules::spawn_cell(&mut self.rng, &cells) {
+            let life = ticks(rules::block_time(self.difficulty));
+            self.blocks.push(SimBlock {
+                cell: cell,
+                expires: self.tick + life,
+                life: life,
+            });
+            self.events.push(Event::Spawned(cell));
+        }
+    }
+
+    fn throw(&mut self, player: usize, aim: (f32, f32)) {
+        let from = self.players[player].launcher;
+        let vel = rules::shot_velocity(from, aim, self.players[player].charge(), self.max_vel());
+        let ball = Ball::new(rules::ball_radius(self.delta_length), from, vel);
+        self.balls.push(SimBall {
+            ball: ball,
+            owner: player,
+        });
+        self.players[player].ready_in = ticks(rules::READY_TIME);
+        self.events.push(Event::Thrown(player));
+    }
+
+    // one tick with every player's input, in player order: the presses and
+    // releases first, then the board moves on as update_running moves it
+    pub fn step(&mut self, inputs: &[Input]) {
+        self.events.clear();
+        if self.is_over() {
+            return;
+        }
+        self.tick += 1;
+        for (i, input) in inputs.iter().enumerate().take(self.players.len()) {
+            match (self.players[i].charge_ticks, input.charging) {
+                (None, true) => self.players[i].charge_ticks = Some(0),
+                (Some(_), false) => {
+                    if self.players[i].is_ready() {
+                        self.throw(i, input.aim);
+                    }
+                    self.players[i].charge_ticks = None;
+                }
+                _ => {}
+            }
+        }
+        for p in self.players.iter_mut() {
+            p.ready_in = p.ready_in.saturating_sub(1);
+            p.charge_ticks = p.charge_ticks.map(|t| t + 1);
+        }
+
+        if self.tick >= self.spawn_at {
+            self.spawn_block();
+            self.spawn_at = self.spawn_wait();
+        }
+
+        for b in self.balls.iter_mut() {
+            b.ball.update(TICK);
+        }
+
+        let tick = self.tick;
+        let expired: Vec<usize> = self
+            .blocks
+            .iter()
+            .filter(|b| b.expires <= tick)
+            .map(|b| b.cell)
+            .collect();
+        self.blocks.retain(|b| b.expires > tick);
+        for cell in expired {
+            self.events.push(Event::Expired(cell));
+            if self.left == 0 {
+                self.over = true;
+                return;
+            }
+            self.left -= 1;
+        }
+
+        let width = self.delta_length;
+        let landed: Vec<(usize, (f32, f32), f32)> = self
+            .balls
+            .iter()
+            .filter(|b| b.ball.is_on_ground())
+            .map(|b| {
+                (
+                    b.owner,
+                    (b.ball.get_pos().x, b.ball.get_pos().y),
+                    b.ball.get_radius(),
+                )
+            })
+            .collect();
+        self.balls.retain(|b| !b.ball.is_on_ground());
+        for (owner, point, radius) in landed {
+            let broken: Vec<usize> = self
+                .blocks
+                .iter()
+                .map(|b| b.cell)
+                .filter(|&c| rules::is_hit(self.cell_pos(c), width, point, radius))
+                .collect();
+            self.blocks.retain(|b| !broken.contains(&b.cell));
+            self.players[owner].score += broken.len();
+            self.events.push(Event::Landed {
+                owner: owner,
+                pos: point,
+                cells: broken,
+            });
+        }
+    }
+
+    pub fn hash(&self) -> u64 {
+        let mut h = 0xcbf2_9ce4_8422_2325;
+        mix(&mut h, u64::from(self.tick));
+        mix(&mut h, self.left as u64);
+        mix(&mut h, u64::from(self.spawn_at));
+        for p in self.players.iter() {
+            mix(&mut h, p.score as u64);
+            mix(&mut h, u64::from(p.ready_in));
+            mix(&mut h, p.charge_ticks.map_or(u64::max_value(), u64::from));
+        }
+        for b in self.blocks.iter() {
+            mix(&mut h, b.cell as u64);
+            mix(&mut h, u64::from(b.expires));
+        }
+        for b in self.balls.iter() {
+            let pos = b.ball.get_pos();
+            mix(&mut h, b.owner as u64);
+            mix(&mut h, u64::from(pos.x.to_bits()));
+            mix(&mut h, u64::from(pos.y.to_bits()));
+        }
+        h
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::block::Block;
+    use super::*;
+
+    // A local game as update_running plays it, on a clock of seconds rather
+    // than ticks: its spawn wait, block lifetimes and cooldowns are the ones
+    // its timers run, and Ball and Block do the flying and the hitting. It
+    // can't be a GameState, which needs a Context for its clocks.
+    struct Local {
+        rng: XorShiftRng,
+        mode: Mode,
+        difficulty: Difficulty,
+        // frames played; times are kept as the frame they started on
+        frames: u32,
+        delta_length: f32,
+        side_by_side: bool,
+        // (launcher, charging since, thrown at, score) for each player
+        players: Vec<((f32, f32), Option<u32>, Option<u32>, usize)>,
+        // (block, cell, spawned at)
+        blocks: Vec<(Block, usize, u32)>,
+        cells: [bool; CELLS],
+        balls: Vec<(Ball, usize)>,
+        // the spawn timer's start and duration
+        spawn_timer: (u32, f64),
+        left: usize,
+        over: bool,
+    }
+
+    impl Local {
+        fn new(seed: [u32; 4], mode: Mode, difficulty: Difficulty, players: usize) -> Local {
+            let world = (view::WORLD_WIDTH, view::WORLD_HEIGHT);
+            let delta_length = rules::delta_length(world.1);
+            let side_by_side = players > 1;
+            let mut local = Local {
+                rng: XorShiftRng::from_seed(seed),
+                mode: mode,
+                difficulty: difficulty,
+                frames: 0,
+                delta_length: delta_length,
+                side_by_side: side_by_side,
+                players: (0..players)
+                    .map(|i| {
+                        (
+                            rules::launcher_pos(world, delta_length, side_by_side, i),
+                            None,
+                            None,
+                            0,
+                        )
+                    })
+                    .collect(),
+                blocks: vec![],
+                cells: [false; CELLS],
+                balls: vec![],
+                spawn_timer: (0, 0.0),
+                left: rules::LIVES,
+                over: false,
+            };
+            local.restore_timer();
+            local
+        }
+
+        // seconds since frame `start`
+        fn since(&self, start: u32) -> f64 {
+            f64::from(self.frames - start) / f64::from(TICK_RATE)
+        }
+
+        fn restore_timer(&mut self) {
+            let roll = self.rng.gen::<f64>();
+            let wait = rules::spawn_wait(self.mode, self.difficulty, 0.0, self.blocks.len(), roll);
+            self.spawn_timer = (self.frames, wait);
+        }
+
+        // the mouse events that came in since the last frame, then the frame
+        fn step(&mut self, inputs: &[Input]) {
+            if self.over || self.frames >= ROUND_TICKS {
+                return;
+            }
+            let now = self.frames;
+            for (i, input) in inputs.iter().enumerate().take(self.players.len()) {
+                match (self.players[i].1, input.charging) {
+                    (None, true) => self.players[i].1 = Some(now),
+                    (Some(since), false) => {
+                        let ready = self.players[i]
+                            .2
+                            .map_or(true, |t| self.since(t) >= rules::READY_TIME);
+                        if ready {
+                            let charge = (self.since(since) / rules::CHARGE_TIME) as f32;
+                            let from = self.players[i].0;
+                            let max_vel = rules::max_vel(self.delta_length, self.side_by_side);
+                            let vel = rules::shot_velocity(from, input.aim, charge, max_vel);
+                            self.balls.push((
+                                Ball::new(rules::ball_radius(self.delta_length), from, vel),
+                                i,
+                            ));
+                            self.players[i].2 = Some(now);
+                        }
+                        self.players[i].1 = None;
+                    }
+                    _ => {}
+                }
+            }
+
+            self.frames += 1;
+            let now = self.frames;
+            if self.since(self.spawn_timer.0) >= self.spawn_timer.1 {
+                if let Some(cell) = rules::spawn_cell(&mut self.rng, &self.cells) {
+                    let pos = rules::cell_pos(view::WORLD_WIDTH, self.delta_length, cell);
+                    let alive = rules::block_time(self.difficulty);
+                    self.blocks
+                        .push((Block::new(alive, pos, self.delta_length), cell, now));
+                    self.cells[cell] = true;
+                }
+                self.restore_timer();
+            }
+            for b in self.balls.iter_mut() {
+                b.0.update(TICK);
+            }
+            let alive = rules::block_time(self.difficulty);
+            let expired: Vec<usize> = self
+                .blocks
+                .iter()
+                .filter(|b| self.since(b.2) >= alive)
+                .map(|b| b.1)
+                .collect();
+            for cell in expired {
+                self.blocks.retain(|b| b.1 != cell);
+                self.cells[cell] = false;
+                if self.left == 0 {
+                    self.over = true;
+                    return;
+                }
+                self.left -= 1;
+            }
+            let landed: Vec<(Ball, usize)> = self.balls.drain(..).collect();
+            for (ball, owner) in landed {
+                if !ball.is_on_ground() {
+                    self.balls.push((ball, owner));
+                    continue;
+                }
+                let hit: Vec<usize> = self
+                    .blocks
+                    .iter()
+                    .filter(|b| b.0.is_hit_cricle(ball.get_pos(), ball.get_radius()))
+                    .map(|b| b.1)
+                    .collect();
+                for &cell in hit.iter() {
+                    self.blocks.retain(|b| b.1 != cell);
+                    self.cells[cell] = false;
+                }
+                self.players[owner].3 += hit.len();
+            }
+        }
+
+        fn cells(&self) -> Vec<usize> {
+            let mut cells: Vec<usize> = self.blocks.iter().map(|b| b.1).collect();
+            cells.sort();
+            cells
+        }
+    }
+
+    // each player charges just enough for a block, player 0 going for the
+    // oldest and player 1 for the newest; both leave every third cell be, so
+    // lives go too
+    fn bots(sim: &Sim) -> Vec<Input> {
+        (0..sim.players.len())
+            .map(|i| {
+                let mut wanted = sim.blocks.iter().filter(|b| b.cell % 3 != 0);
+                let block = if i == 0 { wanted.next() } else { wanted.last() };
+                match block {
+                    Some(b) => {
+                        let target = sim.cell_target(b.cell);
+                        let needed = sim.charge_for(i, target).unwrap_or(1.0);
+                        let p = &sim.players[i];
+                        Input {
+                            aim: target,
+                            charging: p.is_ready() && p.charge() < needed,
+                        }
+                    }
+                    None => Input::default(),
+                }
+            })
+            .collect()
+    }
+
+    // player 0 charges just enough for the first block, player 1 never throws
+    fn script(sim: &Sim) -> [Input; 2] {
+        let mut inputs = [Input::default(); 2];
+        if let Some(b) = sim.blocks.first() {
+            let target = sim.cell_target(b.cell);
+            let needed = sim.charge_for(0, target).unwrap_or(1.0);
+            inputs[0] = Input {
+                aim: target,
+                charging: sim.players[0].is_ready() && sim.players[0].charge() < needed,
+            };
+        }
+        inputs
+    }
+
+    #[test]
+    fn same_seed_same_game() {
+        let (mut a, mut b) = (
+            Sim::new([1, 2, 3, 4], Difficulty::Normal),
+            Sim::new([1, 2, 3, 4], Difficulty::Normal),
+        );
+        while !a.is_over() {
+            let inputs = script(&a);
+            a.step(&inputs);
+            b.step(&inputs);
+            assert_eq!(a.hash(), b.hash());
+        }
+        assert!(b.is_over());
+        assert!(a.players[0].score > 0 && a.players[1].score == 0);
+        assert_ne!(Sim::new([1, 2, 3, 4], Difficulty::Normal).hash(), {
+            let mut c = Sim::new([1, 2, 3, 4], Difficulty::Normal);
+            c.step(&[Input::default(); 2]);
+            c.hash()
+        });
+    }
+
+    #[test]
+    fn plays_like_a_local_game() {
+        let games = vec![
+            (
+                Sim::solo([3, 1, 4, 1]),
+                Local::new([3, 1, 4, 1], Mode::Race, Difficulty::Normal, 1),
+            ),
+            (
+                Sim::new([2, 7, 1, 8], Difficulty::Hard),
+                Local::new([2, 7, 1, 8], Mode::Versus, Difficulty::Hard, 2),
+            ),
+        ];
+        for (mut sim, mut local) in games.into_iter() {
+            let mut shots = 0;
+            while !sim.is_over() {
+                let inputs = bots(&sim);
+                sim.step(&inputs);
+                local.step(&inputs);
+                assert_eq!(sim.over, local.over, "game over at tick {}", sim.tick());
+                if sim.over {
+                    break;
+                }
+                let mut cells: Vec<usize> = sim.blocks.iter().map(|b| b.cell).collect();
+                cells.sort();
+                assert_eq!(cells, local.cells(), "blocks at tick {}", sim.tick());
+                assert_eq!(
+                    sim.balls.len(),
+                    local.balls.len(),
+                    "balls at tick {}",
+                    sim.tick()
+                );
+                assert_eq!(sim.left, local.left, "lives at tick {}", sim.tick());
+                for (p, l) in sim.players.iter().zip(local.players.iter()) {
+                    assert_eq!(p.score, l.3, "score at tick {}", sim.tick());
+                }
+                shots = shots.max(sim.players.iter().map(|p| p.score).sum());
+            }
+            assert!(shots > 0 && sim.left < rules::LIVES);
+        }
+    }
+
+    #[test]
+    fn events_tell_the_whole_game() {
+        let mut sim = Sim::new([2, 7, 1, 8], Difficulty::Hard);
+        let (mut on, mut thrown, mut scores, mut lost) = (vec![], 0, [0; 2], 0);
+        while !sim.is_over() {
+            let inputs = bots(&sim);
+            sim.step(&inputs);
+            for e in sim.events.iter() {
+                match *e {
+                    Event::Spawned(cell) => on.push(cell),
+                    Event::Thrown(_) => thrown += 1,
+                    Event::Landed {
+                        owner, ref cells, ..
+                    } => {
+                        on.retain(|c| !cells.contains(c));
+                        scores[owner] += cells.len();
+                        thrown -= 1;
+                    }
+                    Event::Expired(cell) => {
+                        on.retain(|&c| c != cell);
+                        lost += 1;
+                    }
+                }
+            }
+            if sim.is_over() {
+                break;
+            }
+            let mut cells: Vec<usize> = sim.blocks.iter().map(|b| b.cell).collect();
+            cells.sort();
+            on.sort();
+            assert_eq!(on, cells, "blocks at tick {}", sim.tick());
+            assert_eq!(thrown, sim.balls.len());
+            assert_eq!(rules::LIVES - lost, sim.left);
+        }
+        assert_eq!(
+            scores.to_vec(),
+            sim.players.iter().map(|p| p.score).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn charging_throws_on_release() {
+        let mut sim = Sim::new([5, 6, 7, 8], Difficulty::Normal);
+        let held = Input {
+            aim: (200.0, 100.0),
+            charging: true,
+        };
+        for _ in 0..30 {
+            sim.step(&[held, Input::default()]);
+        }
+        assert!(sim.players[0].is_charging());
+        sim.step(&[
+            Input {
+                charging: false,
+                ..held
+            },
+            Input::default(),
+        ]);
+        assert_eq!(sim.balls.len(), 1);
+        assert!(!sim.players[0].is_ready());
+    }
+
+    #[test]
+    fn clicks_between_ticks_are_kept() {
+        let mut button = Button::default();
+        button.set(true);
+        button.set(false);
+        let mut sim = Sim::new([5, 6, 7, 8], Difficulty::Normal);
+        for _ in 0..2 {
+            let input = Input {
+                aim: (200.0, 100.0),
+                charging: button.tick(),
+            };
+            sim.step(&[input, Input::default()]);
+        }
+        assert_eq!(sim.balls.len(), 1);
+        assert!(!button.tick());
+        button.set(true);
+        assert!(button.tick() && button.tick());
+    }
+}
@@ -0,0 +1,115 @@
+// Where new blocks go on the board, and the seeded spawn sequence shared by
+// everyone playing the same seed.
+
+use rand::{Rng, SeedableRng, XorShiftRng};
+
+// blocks a seeded run works its waits out for, whatever is really on the
+// board, so hits can't change what comes next
+pub const SEEDED_LOAD: usize = 1;
+
+// index of the `n`th empty cell, counting only empty ones
+pub fn nth_free(cells: &[bool], n: usize) -> Option<usize> {
+    cells
+        .iter()
+        .enumerate()
+        .filter(|&(_, &taken)| !taken)
+        .map(|(i, _)| i)
+        .nth(n)
+}
+
+// the first empty cell at or after `cell`, wrapping round the board
+pub fn free_from(cells: &[bool], cell: usize) -> Option<usize> {
+    (0..cells.len())
+        .map(|i| (cell + i) % cells.len())
+        .find(|&i| !cells[i])
+}
+
+// One spawn of a seeded run: the cell it goes to if that is empty, and the
+// random part of the wait before the next one (0..1).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Spawn {
+    pub cell: usize,
+    pub roll: f64,
+}
+
+impl Spawn {
+    // where it lands on `cells`, skipping ahead past taken ones
+    pub fn place(&self, cells: &[bool]) -> Option<usize> {
+        free_from(cells, self.cell)
+    }
+}
+
+// The spawns of a seeded run, drawn from the seed alone: every spawn takes
+// the same two draws whatever is on the board.
+#[derive(Debug)]
+pub struct Sequence {
+    rng: XorShiftRng,
+    cells: usize,
+}
+
+impl Sequence {
+    pub fn new(seed: [u32; 4], cells: usize) -> Sequence {
+        Sequence {
+            rng: XorShiftRng::from_seed(seed),
+            cells: cells,
+        }
+    }
+}
+
+impl Iterator for Sequence {
+    type Item = Spawn;
+
+    fn next(&mut self) -> Option<Spawn> {
+        let cell = self.rng.gen_range(0, self.cells);
+        Some(Spawn {
+            cell: cell,
+            roll: self.rng.gen(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_among_empty_cells() {
+        let cells = [true, false, true, false, false];
+        assert_eq!(nth_free(&cells, 0), Some(1));
+        assert_eq!(nth_free(&cells, 2), Some(4));
+        assert_eq!(nth_free(&cells, 3), None);
+        assert_eq!(free_from(&cells, 4), Some(4));
+        assert_eq!(free_from(&[false, true, true], 1), Some(0));
+        assert_eq!(free_from(&[true, true], 0), None);
+    }
+
+    // one player who hits every block at once and one who hits none
+    #[test]
+    fn same_spawns_whatever_is_hit() {
+        let seed = [7, 1, 2, 3];
+        let play = |hits: bool| {
+            let mut cells = [false; 16];
+            Sequence::new(seed, 16)
+                .take(12)
+                .map(|s| {
+                    let cell = s.place(&cells);
+                    if let Some(c) = cell {
+                        cells[c] = !hits;
+                    }
+                    (s, cell)
+                })
+                .collect::<Vec<_>>()
+        };
+        let (missed, hit) = (play(false), play(true));
+        assert_eq!(
+            missed.iter().map(|x| x.0).collect::<Vec<_>>(),
+            hit.iter().map(|x| x.0).collect::<Vec<_>>()
+        );
+        assert!(hit.iter().all(|&(s, cell)| cell == Some(s.cell)));
+        assert!(missed.iter().all(|&(_, cell)| cell.is_some()));
+        assert_ne!(
+            Sequence::new(seed, 16).next(),
+            Sequence::new([8, 1, 2, 3], 16).next()
+        );
+    }
+}
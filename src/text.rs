@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+
+use ggez::graphics::{self, DrawParam, Font, Point2, Text};
+use ggez::{Context, GameResult};
+
+const CACHE_LIMIT: usize = 64;
+
+// Text rendered at the real pixel density and drawn scaled back down into
+// world units, so it stays sharp however large the window is.
+#[derive(Debug)]
+pub struct TextCache {
+    font: Font,
+    pixels_per_unit: f32,
+    texts: HashMap<String, Text>,
+}
+
+impl TextCache {
+    pub fn new(font: Font, pixels_per_unit: f32) -> TextCache {
+        TextCache {
+            font: font,
+            pixels_per_unit: pixels_per_unit,
+            texts: HashMap::new(),
+        }
+    }
+
+    pub fn set_font(&mut self, font: Font, pixels_per_unit: f32) {
+        self.font = font;
+        self.pixels_per_unit = pixels_per_unit;
+        self.texts.clear();
+    }
+
+    pub fn width(&self, s: &str) -> f32 {
+        self.font.get_width(s) as f32 / self.pixels_per_unit
+    }
+
+    pub fn height(&self) -> f32 {
+        self.font.get_height() as f32 / self.pixels_per_unit
+    }
+
+    pub fn draw(&mut self, ctx: &mut Context, s: &str, dest: Point2) -> GameResult<()> {
+        if !self.texts.contains_key(s) {
+            if self.texts.len() >= CACHE_LIMIT {
+                self.texts.clear();
+            }
+            let text = Text::new(ctx, s, &self.font)?;
+            self.texts.insert(s.to_owned(), text);
+        }
+        let scale = 1.0 / self.pixels_per_unit;
+        let param = DrawParam {
+            dest: dest,
+            scale: Point2::new(scale, scale),
+            ..Default::default()
+        };
+        graphics::draw_ex(ctx, &self.texts[s], param)
+    }
+
+    pub fn draw_centered(
+        &mut self,
+        ctx: &mut Context,
+        s: &str,
+        center_x: f32,
+        y: f32,
+    ) -> GameResult<()> {
+        let x = center_x - self.width(s) / 2.0;
+        self.draw(ctx, s, Point2::new(x, y))
+    }
+}
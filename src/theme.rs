@@ -0,0 +1,349 @@
+use std::collections::HashMap;
+
+use ggez::graphics::Color;
+use ggez::Context;
+use toml;
+
+use super::assets::Assets;
+use super::error::{Error, Result};
+
+const THEME_DIR: &str = "/themes";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Theme {
+    pub name: String,
+    pub background: Color,
+    pub text: Color,
+    pub highlight: Color,
+    pub score: Color,
+    pub lives: Color,
+    pub board_rim: Color,
+    pub block: Color,
+    pub block_timer: Color,
+    pub block_urgent: Color,
+    pub bar_rim: Color,
+    pub bar_fill: Color,
+    pub launcher: Color,
+    pub ball: Color,
+    // the second player's balls
+    pub ball_2: Color,
+    pub danger: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme::light()
+    }
+}
+
+// The part of a theme file that is not a colour; every other key is a
+// colour field of `Theme` written as "#rrggbb" or "#rrggbbaa".
+#[derive(Debug, Deserialize)]
+struct ThemeFile {
+    name: String,
+    base: Option<String>,
+    #[serde(flatten)]
+    colors: HashMap<String, String>,
+}
+
+pub fn parse_color(s: &str) -> Result<Color> {
+    let hex = s.trim_start_matches('#');
+    let bad = || {
+        Error::Config(format!(
+            "bad colour {:?}, expected \"#rrggbb\" or \"#rrggbbaa\"",
+            s
+        ))
+    };
+    // checked byte by byte first, slicing would panic inside a multi-byte char
+    if !s.starts_with('#')
+        || (hex.len() != 6 && hex.len() != 8)
+        || !hex.bytes().all(|b| b.is_ascii_hexdigit())
+    {
+        return Err(bad());
+    }
+    let mut c = [255u8; 4];
+    for (i, v) in c.iter_mut().enumerate().take(hex.len() / 2) {
+        *v = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| bad())?;
+    }
+    Ok(Color::from_rgba(c[0], c[1], c[2], c[3]))
+}
+
+impl Theme {
+    pub fn light() -> Theme {
+        Theme {
+            name: "light".to_owned(),
+            background: Color::from_rgb(255, 255, 255),
+            text: Color::from_rgb(0, 0, 0),
+            highlight: Color::from_rgb(0, 205, 102),
+            score: Color::from_rgb(110, 123, 139),
+            lives: Color::from_rgb(0, 205, 205),
+            board_rim: Color::from_rgb(0, 0, 0),
+            block: Color::from_rgb(54, 100, 139),
+            block_timer: Color::from_rgb(0, 255, 0),
+            block_urgent: Color::from_rgb(255, 99, 71),
+            bar_rim: Color::new(0.74218, 0.74218, 0.74218, 1.0),
+            bar_fill: Color::from_rgb(0, 255, 0),
+            launcher: Color::from_rgb(112, 128, 144),
+            ball: Color::from_rgb(0, 0, 0),
+            ball_2: Color::from_rgb(230, 97, 0),
+            danger: Color::from_rgb(220, 20, 60),
+        }
+    }
+
+    pub fn dark() -> Theme {
+        Theme {
+            name: "dark".to_owned(),
+            background: Color::from_rgb(28, 30, 36),
+            text: Color::from_rgb(220, 222, 228),
+            highlight: Color::from_rgb(80, 230, 150),
+            score: Color::from_rgb(170, 182, 198),
+            lives: Color::from_rgb(0, 205, 205),
+            board_rim: Color::from_rgb(120, 126, 138),
+            block: Color::from_rgb(99, 160, 214),
+            block_timer: Color::from_rgb(80, 230, 120),
+            block_urgent: Color::from_rgb(255, 120, 90),
+            bar_rim: Color::from_rgb(70, 74, 84),
+            bar_fill: Color::from_rgb(80, 230, 120),
+            launcher: Color::from_rgb(150, 164, 180),
+            ball: Color::from_rgb(240, 240, 240),
+            ball_2: Color::from_rgb(255, 176, 59),
+            danger: Color::from_rgb(255, 90, 90),
+        }
+    }
+
+    pub fn high_contrast() -> Theme {
+        Theme {
+            name: "high contrast".to_owned(),
+            background: Color::from_rgb(0, 0, 0),
+            text: Color::from_rgb(255, 255, 255),
+            highlight: Color::from_rgb(255, 255, 0),
+            score: Color::from_rgb(255, 255, 255),
+            lives: Color::from_rgb(0, 255, 255),
+            board_rim: Color::from_rgb(255, 255, 255),
+            block: Color::from_rgb(255, 255, 255),
+            block_timer: Color::from_rgb(255, 255, 0),
+            block_urgent: Color::from_rgb(255, 0, 255),
+            bar_rim: Color::from_rgb(128, 128, 128),
+            bar_fill: Color::from_rgb(255, 255, 0),
+            launcher: Color::from_rgb(255, 255, 255),
+            ball: Color::from_rgb(0, 255, 255),
+            ball_2: Color::from_rgb(255, 128, 0),
+            danger: Color::from_rgb(255, 0, 0),
+        }
+    }
+
+    // Swaps the bar and block colours for ones that stay apart under the
+    // given colour vision deficiency (picked from the Okabe-Ito set).
+    pub fn with_palette(&self, palette: Palette) -> Theme {
+        let mut theme = self.clone();
+        let (block, timer, urgent, fill, lives) = match palette {
+            Palette::Default => return theme,
+            Palette::Deuteranopia | Palette::Protanopia => (
+                Color::from_rgb(0, 114, 178),
+                Color::from_rgb(86, 180, 233),
+                Color::from_rgb(230, 159, 0),
+                Color::from_rgb(0, 114, 178),
+                Color::from_rgb(86, 180, 233),
+            ),
+            Palette::Tritanopia => (
+                Color::from_rgb(213, 94, 0),
+                Color::from_rgb(0, 158, 115),
+                Color::from_rgb(204, 121, 167),
+                Color::from_rgb(0, 158, 115),
+                Color::from_rgb(0, 158, 115),
+            ),
+        };
+        theme.block = block;
+        theme.block_timer = timer;
+        theme.block_urgent = urgent;
+        theme.bar_fill = fill;
+        theme.lives = lives;
+        theme
+    }
+
+    // colour of the balls thrown by `player`, counting from 0
+    pub fn ball_color(&self, player: usize) -> Color {
+        if player == 0 {
+            self.ball
+        } else {
+            self.ball_2
+        }
+    }
+
+    pub fn builtin(name: &str) -> Option<Theme> {
+        match name {
+            "light" => Some(Theme::light()),
+            "dark" => Some(Theme::dark()),
+            "high contrast" => Some(Theme::high_contrast()),
+            _ => None,
+        }
+    }
+
+    pub fn set_color(&mut self, key: &str, color: Color) -> Result<()> {
+        let field = match key {
+            "background" => &mut self.background,
+            "text" => &mut self.text,
+            "highlight" => &mut self.highlight,
+            "score" => &mut self.score,
+            "lives" => &mut self.lives,
+            "board_rim" => &mut self.board_rim,
+            "block" => &mut self.block,
+            "block_timer" => &mut self.block_timer,
+            "block_urgent" => &mut self.block_urgent,
+            "bar_rim" => &mut self.bar_rim,
+            "bar_fill" => &mut self.bar_fill,
+            "launcher" => &mut self.launcher,
+            "ball" => &mut self.ball,
+            "ball_2" => &mut self.ball_2,
+            "danger" => &mut self.danger,
+            _ => return Err(Error::Config(format!("unknown theme colour {:?}", key))),
+        };
+        *field = color;
+        Ok(())
+    }
+
+    pub fn from_toml(s: &str) -> Result<Theme> {
+        let file: ThemeFile = toml::from_str(s).map_err(|e| Error::Config(e.to_string()))?;
+        let base = file.base.as_ref().map_or("light", |b| b.as_str());
+        let mut theme = Theme::builtin(base)
+            .ok_or_else(|| Error::Config(format!("unknown base theme {:?}", base)))?;
+        theme.name = file.name;
+        for (key, value) in file.colors.iter() {
+            theme.set_color(key, parse_color(value)?)?;
+        }
+        Ok(theme)
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Palette {
+    Default,
+    Deuteranopia,
+    Protanopia,
+    Tritanopia,
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette::Default
+    }
+}
+
+impl Palette {
+    pub fn next(self) -> Palette {
+        match self {
+            Palette::Default => Palette::Deuteranopia,
+            Palette::Deuteranopia => Palette::Protanopia,
+            Palette::Protanopia => Palette::Tritanopia,
+            Palette::Tritanopia => Palette::Default,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Themes {
+    list: Vec<Theme>,
+    current: usize,
+}
+
+impl Themes {
+    pub fn new(list: Vec<Theme>) -> Themes {
+        Themes {
+            list: list,
+            current: 0,
+        }
+    }
+
+    // built-in themes followed by every *.toml under /themes
+    pub fn load(ctx: &mut Context, assets: &mut Assets) -> Result<Themes> {
+        let mut list = vec![Theme::light(), Theme::dark()];
+        let mut files: Vec<String> = match ctx.filesystem.read_dir(THEME_DIR) {
+            Ok(dir) => dir
+                .filter(|p| p.extension().map_or(false, |e| e == "toml"))
+                .filter_map(|p| p.to_str().map(|s| s.to_owned()))
+                .collect(),
+            Err(_) => vec![],
+        };
+        files.sort();
+        for f in files.iter() {
+            let s = assets.read_string(ctx, f)?;
+            let theme = Theme::from_toml(&s).map_err(|e| Error::Config(format!("{}: {}", f, e)))?;
+            list.retain(|t| t.name != theme.name);
+            list.push(theme);
+        }
+        Ok(Themes::new(list))
+    }
+
+    pub fn current(&self) -> &Theme {
+        &self.list[self.current]
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.list.iter().map(|t| t.name.as_str()).collect()
+    }
+
+    pub fn select(&mut self, name: &str) -> bool {
+        match self.list.iter().position(|t| t.name == name) {
+            Some(i) => {
+                self.current = i;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn next(&mut self) -> &Theme {
+        self.current = (self.current + 1) % self.list.len();
+        self.current()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_colours() {
+        assert_eq!(
+            parse_color("#36648b").unwrap(),
+            Color::from_rgb(54, 100, 139)
+        );
+        assert_eq!(
+            parse_color("#36648b80").unwrap(),
+            Color::from_rgba(54, 100, 139, 128)
+        );
+        assert!(parse_color("36648b").is_err());
+        assert!(parse_color("#3664").is_err());
+        assert!(parse_color("#zz648b").is_err());
+        assert!(parse_color("#ééé").is_err());
+        assert!(parse_color("#+1+1+1").is_err());
+    }
+
+    #[test]
+    fn palettes_only_touch_bars_and_blocks() {
+        let light = Theme::light();
+        assert_eq!(light.with_palette(Palette::Default), light);
+        let safe = light.with_palette(Palette::Deuteranopia);
+        assert_ne!(safe.block_timer, light.block_timer);
+        assert_ne!(safe.block_timer, safe.block_urgent);
+        assert_eq!(safe.background, light.background);
+        assert_eq!(safe.text, light.text);
+    }
+
+    #[test]
+    fn theme_file_overrides_base() {
+        let theme = Theme::from_toml(
+            r##"
+            name = "ocean"
+            base = "dark"
+            block = "#ff8800"
+        "##,
+        )
+        .unwrap();
+        assert_eq!(theme.name, "ocean");
+        assert_eq!(theme.block, Color::from_rgb(255, 136, 0));
+        assert_eq!(theme.background, Theme::dark().background);
+        assert!(Theme::from_toml("name = \"x\"\nblocks = \"#000000\"").is_err());
+    }
+}
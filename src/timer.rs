@@ -1,85 +1,100 @@
-use std::time;
 use ggez::timer;
 use ggez::Context;
+use std::time;
 
 #[derive(Debug)]
 pub struct Timer {
-    duration : f64,
-    started : time::Duration,
+    duration: f64,
+    started: time::Duration,
     paused: f64,
-    event_flag: (bool,bool),
+    event_flag: (bool, bool),
     value: f32,
 }
 
 impl Timer {
     pub fn new(duration: f64) -> Timer {
-        Timer{
-            duration:duration,
-            started: time::Duration::new(0,0),
+        Timer {
+            duration: duration,
+            started: time::Duration::new(0, 0),
             paused: 0f64,
-            event_flag:(true,true),
-            value:0f32,
+            event_flag: (true, true),
+            value: 0f32,
         }
     }
 
-    pub fn get_value(&self) ->f32{
+    pub fn get_value(&self) -> f32 {
         self.value
     }
 
-    pub fn get_event(&mut self) ->bool{
-        if self.event_flag.0 && ! self.event_flag.1{
+    pub fn get_event(&mut self) -> bool {
+        if self.event_flag.0 && !self.event_flag.1 {
             self.event_flag.1 = true;
             return true;
         }
         return false;
     }
 
-    pub fn pause(&mut self){
-        self.event_flag = (false,true);
+    pub fn pause(&mut self) {
+        self.event_flag = (false, true);
     }
 
-    pub fn pause_triggle(&mut self){
-        self.event_flag = (false,!self.event_flag.1);
+    pub fn pause_triggle(&mut self) {
+        self.event_flag = (false, !self.event_flag.1);
     }
 
-    pub fn is_paused(&self) ->bool{
+    pub fn is_paused(&self) -> bool {
         !self.event_flag.0 && self.event_flag.1
     }
 
-    pub fn stop(&mut self){
-        self.event_flag = (true,true);
+    pub fn stop(&mut self) {
+        self.event_flag = (true, true);
     }
 
-    pub fn is_stopped(&self) ->bool{
+    pub fn is_stopped(&self) -> bool {
         self.event_flag.0 && self.event_flag.1
     }
 
-    pub fn on_start(&self) ->bool{
+    pub fn on_start(&self) -> bool {
         !(self.event_flag.1 || self.event_flag.0)
     }
 
-    pub fn start(&mut self,ctx:&Context){
+    pub fn start(&mut self, ctx: &Context) {
         self.value = 0f32;
-        self.event_flag = (false,false);
+        self.event_flag = (false, false);
         self.started = timer::get_time_since_start(ctx);
         self.paused = 0f64;
     }
 
-    pub fn restore(&mut self,duration:f64){
+    // holds a running timer still for `seconds` that have already passed
+    pub fn delay(&mut self, seconds: f64) {
+        if self.on_start() {
+            self.started += time::Duration::from_secs_f64(seconds.max(0.0));
+        }
+    }
+
+    // shows `value` without the clock, for a timer something else drives
+    pub fn set_value(&mut self, value: f32) {
+        self.value = value;
+    }
+
+    pub fn restore(&mut self, duration: f64) {
         self.value = 0f32;
         self.duration = duration;
     }
 
-    pub fn update(&mut self,ctx: &Context) {
+    pub fn update(&mut self, ctx: &Context) {
         if self.on_start() {
-            let time_passed = timer::duration_to_f64(timer::get_time_since_start(ctx)) - timer::duration_to_f64(self.started) - self.paused;
-            self.value = (time_passed / self.duration)as f32;
+            let time_passed = timer::duration_to_f64(timer::get_time_since_start(ctx))
+                - timer::duration_to_f64(self.started)
+                - self.paused;
+            self.value = (time_passed / self.duration) as f32;
             self.event_flag.0 = self.value > 1f32;
             return;
         }
-        if self.is_paused(){
-            self.paused = timer::duration_to_f64(timer::get_time_since_start(ctx)) - timer::duration_to_f64(self.started)*self.value as f64;
+        if self.is_paused() {
+            self.paused = timer::duration_to_f64(timer::get_time_since_start(ctx))
+                - timer::duration_to_f64(self.started) * self.value as f64;
             return;
         }
     }
-}
\ No newline at end of file
+}
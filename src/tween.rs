@@ -0,0 +1,172 @@
+use std::f32::consts::PI;
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+    // overshoots and settles, for things popping into place
+    Elastic,
+    Bounce,
+}
+
+impl Easing {
+    // maps progress 0..1 to eased progress; starts at 0 and ends at 1
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.max(0.0).min(1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t * t,
+            Easing::EaseOut => {
+                let u = 1.0 - t;
+                1.0 - u * u * u
+            }
+            Easing::EaseInOut => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    let u = -2.0 * t + 2.0;
+                    1.0 - u * u * u / 2.0
+                }
+            }
+            Easing::Elastic => {
+                if t == 0.0 || t == 1.0 {
+                    t
+                } else {
+                    2f32.powf(-10.0 * t) * ((t * 10.0 - 0.75) * (2.0 * PI / 3.0)).sin() + 1.0
+                }
+            }
+            Easing::Bounce => bounce(t),
+        }
+    }
+}
+
+fn bounce(t: f32) -> f32 {
+    let (n, d) = (7.5625, 2.75);
+    if t < 1.0 / d {
+        n * t * t
+    } else if t < 2.0 / d {
+        let t = t - 1.5 / d;
+        n * t * t + 0.75
+    } else if t < 2.5 / d {
+        let t = t - 2.25 / d;
+        n * t * t + 0.9375
+    } else {
+        let t = t - 2.625 / d;
+        n * t * t + 0.984375
+    }
+}
+
+// A value moving from one number to another over `duration` seconds of
+// game time. Nothing moves unless `update` is called.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Tween {
+    from: f32,
+    to: f32,
+    duration: f32,
+    elapsed: f32,
+    easing: Easing,
+}
+
+impl Tween {
+    pub fn new(from: f32, to: f32, duration: f32, easing: Easing) -> Tween {
+        Tween {
+            from: from,
+            to: to,
+            duration: duration.max(0.0),
+            elapsed: 0.0,
+            easing: easing,
+        }
+    }
+
+    // a tween already resting at `value`
+    pub fn fixed(value: f32) -> Tween {
+        Tween::new(value, value, 0.0, Easing::Linear)
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.elapsed = (self.elapsed + dt).min(self.duration);
+    }
+
+    pub fn progress(&self) -> f32 {
+        if self.duration > 0.0 {
+            self.elapsed / self.duration
+        } else {
+            1.0
+        }
+    }
+
+    pub fn value(&self) -> f32 {
+        self.from + (self.to - self.from) * self.easing.apply(self.progress())
+    }
+
+    pub fn target(&self) -> f32 {
+        self.to
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+
+    // heads for a new value from wherever the tween is now
+    pub fn retarget(&mut self, to: f32) {
+        if to != self.to {
+            self.from = self.value();
+            self.to = to;
+            self.elapsed = 0.0;
+        }
+    }
+
+    // jumps straight to `value` with no animation
+    pub fn snap(&mut self, value: f32) {
+        self.from = value;
+        self.to = value;
+        self.elapsed = self.duration;
+    }
+
+    pub fn restart(&mut self, from: f32, to: f32) {
+        self.from = from;
+        self.to = to;
+        self.elapsed = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn easings_hit_both_ends() {
+        for e in [
+            Easing::Linear,
+            Easing::EaseIn,
+            Easing::EaseOut,
+            Easing::EaseInOut,
+            Easing::Elastic,
+            Easing::Bounce,
+        ]
+        .iter()
+        {
+            assert!(e.apply(0.0).abs() < 1e-4, "{:?}", e);
+            assert!((e.apply(1.0) - 1.0).abs() < 1e-4, "{:?}", e);
+        }
+        assert!(Easing::EaseIn.apply(0.5) < 0.5);
+        assert!(Easing::EaseOut.apply(0.5) > 0.5);
+        assert!((0..100).any(|i| Easing::Elastic.apply(i as f32 / 100.0) > 1.0));
+    }
+
+    #[test]
+    fn tween_follows_game_time() {
+        let mut t = Tween::new(0.0, 10.0, 2.0, Easing::Linear);
+        assert_eq!(t.value(), 0.0);
+        t.update(1.0);
+        assert_eq!(t.value(), 5.0);
+        t.retarget(20.0);
+        t.update(1.0);
+        assert_eq!(t.value(), 12.5);
+        t.update(5.0);
+        assert!(t.is_finished());
+        assert_eq!(t.value(), 20.0);
+    }
+}
@@ -0,0 +1,664 @@
+use ggez::event::Keycode;
+use ggez::graphics::{self, Color, DrawMode, Point2, Rect};
+use ggez::{Context, GameResult};
+
+use super::bar::{Bar, BarLabel};
+use super::text::TextCache;
+use super::theme::Theme;
+
+// Immediate-mode widgets. Menus call the widget functions every update
+// between `begin` and `end`; each call handles input and returns what
+// happened, and records what to draw for the next `draw`. Widgets are told
+// apart by the order they are called in, so keep that order stable.
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    // a w by h box placed inside `area`, `margin` away from the edges it
+    // is anchored to
+    pub fn place(self, area: Rect, w: f32, h: f32, margin: f32) -> Rect {
+        let left = area.x + margin;
+        let center = area.x + (area.w - w) / 2.0;
+        let right = area.x + area.w - w - margin;
+        let top = area.y + margin;
+        let middle = area.y + (area.h - h) / 2.0;
+        let bottom = area.y + area.h - h - margin;
+        let (x, y) = match self {
+            Anchor::TopLeft => (left, top),
+            Anchor::Top => (center, top),
+            Anchor::TopRight => (right, top),
+            Anchor::Left => (left, middle),
+            Anchor::Center => (center, middle),
+            Anchor::Right => (right, middle),
+            Anchor::BottomLeft => (left, bottom),
+            Anchor::Bottom => (center, bottom),
+            Anchor::BottomRight => (right, bottom),
+        };
+        Rect::new(x, y, w, h)
+    }
+}
+
+// Hands out rows of a fixed width one under another.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Stack {
+    x: f32,
+    y: f32,
+    width: f32,
+    spacing: f32,
+}
+
+impl Stack {
+    pub fn new(x: f32, y: f32, width: f32, spacing: f32) -> Stack {
+        Stack {
+            x: x,
+            y: y,
+            width: width,
+            spacing: spacing,
+        }
+    }
+
+    // a stack of `rows` rows of `row_height`, anchored inside `area`
+    pub fn anchored(
+        area: Rect,
+        anchor: Anchor,
+        width: f32,
+        rows: usize,
+        row_height: f32,
+        spacing: f32,
+    ) -> Stack {
+        let h = rows as f32 * (row_height + spacing) - spacing;
+        let r = anchor.place(area, width, h.max(0.0), spacing);
+        Stack::new(r.x, r.y, width, spacing)
+    }
+
+    pub fn next(&mut self, height: f32) -> Rect {
+        let r = Rect::new(self.x, self.y, self.width, height);
+        self.y += height + self.spacing;
+        r
+    }
+
+    // splits the next row into `n` equal columns
+    pub fn columns(&mut self, height: f32, n: usize) -> Vec<Rect> {
+        let row = self.next(height);
+        let n = n.max(1);
+        let w = (row.w - self.spacing * (n - 1) as f32) / n as f32;
+        (0..n)
+            .map(|i| Rect::new(row.x + i as f32 * (w + self.spacing), row.y, w, height))
+            .collect()
+    }
+
+    pub fn bottom(&self) -> f32 {
+        self.y - self.spacing
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct UiStyle {
+    pub text: Color,
+    pub disabled: Color,
+    pub panel: Color,
+    pub panel_rim: Color,
+    pub button: Color,
+    pub button_hot: Color,
+    pub focus: Color,
+    pub track: Color,
+    pub fill: Color,
+    pub line_width: f32,
+}
+
+impl UiStyle {
+    pub fn from_theme(theme: &Theme, line_width: f32) -> UiStyle {
+        let bg = theme.background;
+        UiStyle {
+            text: theme.text,
+            disabled: Color::new(theme.text.r, theme.text.g, theme.text.b, 0.35),
+            panel: Color::new(bg.r, bg.g, bg.b, 0.92),
+            panel_rim: theme.board_rim,
+            button: theme.bar_rim,
+            button_hot: theme.launcher,
+            focus: theme.highlight,
+            track: theme.bar_rim,
+            fill: theme.bar_fill,
+            line_width: line_width,
+        }
+    }
+}
+
+impl Default for UiStyle {
+    fn default() -> UiStyle {
+        UiStyle::from_theme(&Theme::default(), 1.0)
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum UiKey {
+    Next,
+    Prev,
+    Activate,
+    Increase,
+    Decrease,
+    Back,
+}
+
+impl UiKey {
+    // every key the menus listen to
+    pub const KEYS: [Keycode; 10] = [
+        Keycode::Tab,
+        Keycode::Down,
+        Keycode::Up,
+        Keycode::Return,
+        Keycode::KpEnter,
+        Keycode::Space,
+        Keycode::Right,
+        Keycode::Left,
+        Keycode::Escape,
+        Keycode::Backspace,
+    ];
+
+    pub fn from_keycode(keycode: Keycode) -> Option<UiKey> {
+        match keycode {
+            Keycode::Tab | Keycode::Down => Some(UiKey::Next),
+            Keycode::Up => Some(UiKey::Prev),
+            Keycode::Return | Keycode::KpEnter | Keycode::Space => Some(UiKey::Activate),
+            Keycode::Right => Some(UiKey::Increase),
+            Keycode::Left => Some(UiKey::Decrease),
+            Keycode::Escape | Keycode::Backspace => Some(UiKey::Back),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Command {
+    Panel(Rect),
+    Label {
+        rect: Rect,
+        text: String,
+        color: Color,
+    },
+    Button {
+        rect: Rect,
+        text: String,
+        hot: bool,
+        focused: bool,
+        enabled: bool,
+    },
+    Toggle {
+        rect: Rect,
+        text: String,
+        on: bool,
+        hot: bool,
+        focused: bool,
+        enabled: bool,
+    },
+    Slider {
+        rect: Rect,
+        bar: Bar,
+        focused: bool,
+    },
+}
+
+#[derive(Debug)]
+pub struct Ui {
+    style: UiStyle,
+    mouse: Point2,
+    mouse_down: bool,
+    // pressed and released since the last `end`
+    pressed: bool,
+    clicked: bool,
+    keys: Vec<UiKey>,
+    focus: Option<usize>,
+    // widget being dragged, e.g. a slider
+    active: Option<usize>,
+    next_id: usize,
+    last_count: usize,
+    enabled: bool,
+    commands: Vec<Command>,
+}
+
+impl Ui {
+    pub fn new(style: UiStyle) -> Ui {
+        Ui {
+            style: style,
+            mouse: Point2::new(-1.0, -1.0),
+            mouse_down: false,
+            pressed: false,
+            clicked: false,
+            keys: vec![],
+            focus: None,
+            active: None,
+            next_id: 0,
+            last_count: 0,
+            enabled: true,
+            commands: vec![],
+        }
+    }
+
+    pub fn set_style(&mut self, style: UiStyle) {
+        self.style = style;
+    }
+
+    // input, in world coordinates, from the event callbacks
+
+    pub fn mouse_motion(&mut self, p: Point2) {
+        self.mouse = p;
+    }
+
+    pub fn mouse_down(&mut self, p: Point2) {
+        self.mouse = p;
+        self.mouse_down = true;
+        self.pressed = true;
+    }
+
+    pub fn mouse_up(&mut self, p: Point2) {
+        self.mouse = p;
+        self.mouse_down = false;
+        self.clicked = true;
+    }
+
+    pub fn key(&mut self, key: UiKey) {
+        self.keys.push(key);
+    }
+
+    pub fn focus(&self) -> Option<usize> {
+        self.focus
+    }
+
+    pub fn set_focus(&mut self, id: Option<usize>) {
+        self.focus = id;
+    }
+
+    // Back was pressed this frame
+    pub fn back(&self) -> bool {
+        self.keys.contains(&UiKey::Back)
+    }
+
+    pub fn begin(&mut self) {
+        self.commands.clear();
+        self.next_id = 0;
+        self.enabled = true;
+        if self.last_count > 0 {
+            for k in self.keys.iter() {
+                self.focus = match (*k, self.focus) {
+                    (UiKey::Next, None) => Some(0),
+                    (UiKey::Next, Some(i)) => Some((i + 1) % self.last_count),
+                    (UiKey::Prev, None) => Some(self.last_count - 1),
+                    (UiKey::Prev, Some(i)) => Some((i + self.last_count - 1) % self.last_count),
+                    (_, f) => f,
+                };
+            }
+        }
+    }
+
+    pub fn end(&mut self) {
+        self.last_count = self.next_id;
+        if let Some(f) = self.focus {
+            if f >= self.last_count {
+                self.focus = None;
+            }
+        }
+        if !self.mouse_down {
+            self.active = None;
+        }
+        self.pressed = false;
+        self.clicked = false;
+        self.keys.clear();
+    }
+
+    // widgets after this are greyed out and ignore input when false
+    pub fn enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    fn id(&mut self) -> usize {
+        self.next_id += 1;
+        self.next_id - 1
+    }
+
+    fn key_for(&self, id: usize, key: UiKey) -> bool {
+        self.enabled && self.focus == Some(id) && self.keys.contains(&key)
+    }
+
+    // clicked with the mouse or activated from the keyboard
+    fn activated(&mut self, id: usize, rect: Rect) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        if self.clicked && rect.contains(self.mouse) {
+            self.focus = Some(id);
+            return true;
+        }
+        self.key_for(id, UiKey::Activate)
+    }
+
+    pub fn panel(&mut self, rect: Rect) {
+        self.commands.push(Command::Panel(rect));
+    }
+
+    pub fn label(&mut self, rect: Rect, text: &str) {
+        let color = if self.enabled {
+            self.style.text
+        } else {
+            self.style.disabled
+        };
+        self.commands.push(Command::Label {
+            rect: rect,
+            text: text.to_owned(),
+            color: color,
+        });
+    }
+
+    pub fn button(&mut self, rect: Rect, text: &str) -> bool {
+        let id = self.id();
+        let hit = self.activated(id, rect);
+        self.commands.push(Command::Button {
+            rect: rect,
+            text: text.to_owned(),
+            hot: self.enabled && rect.contains(self.mouse),
+            focused: self.focus == Some(id),
+            enabled: self.enabled,
+        });
+        hit
+    }
+
+    pub fn toggle(&mut self, rect: Rect, text: &str, value: &mut bool) -> bool {
+        let id = self.id();
+        let changed = self.activated(id, rect)
+            || self.key_for(id, UiKey::Increase) && !*value
+            || self.key_for(id, UiKey::Decrease) && *value;
+        if changed {
+            *value = !*value;
+        }
+        self.commands.push(Command::Toggle {
+            rect: rect,
+            text: text.to_owned(),
+            on: *value,
+            hot: self.enabled && rect.contains(self.mouse),
+            focused: self.focus == Some(id),
+            enabled: self.enabled,
+        });
+        changed
+    }
+
+    // a Bar used as a track; drag it or use left/right when focused
+    pub fn slider(
+        &mut self,
+        rect: Rect,
+        text: &str,
+        value: &mut f32,
+        min: f32,
+        max: f32,
+        step: f32,
+    ) -> bool {
+        let id = self.id();
+        let old = *value;
+        if self.enabled {
+            if self.pressed && rect.contains(self.mouse) {
+                self.active = Some(id);
+                self.focus = Some(id);
+            }
+            if self.active == Some(id) {
+                let k = ((self.mouse.x - rect.x) / rect.w).max(0.0).min(1.0);
+                *value = min + (max - min) * k;
+                if step > 0.0 {
+                    *value = min + ((*value - min) / step).round() * step;
+                }
+            }
+            if self.key_for(id, UiKey::Increase) {
+                *value += step;
+            }
+            if self.key_for(id, UiKey::Decrease) {
+                *value -= step;
+            }
+        }
+        *value = value.max(min).min(max);
+        let mut bar = Bar::new(rect.x, rect.y, rect.w, rect.h);
+        bar.set_rim_width(self.style.line_width);
+        bar.set_rim_color(self.style.track);
+        bar.set_color(self.style.fill);
+        bar.set_value(if max > min {
+            (*value - min) / (max - min)
+        } else {
+            0.0
+        });
+        bar.set_label(BarLabel::Text(text.to_owned()));
+        bar.set_label_color(if self.enabled {
+            self.style.text
+        } else {
+            self.style.disabled
+        });
+        self.commands.push(Command::Slider {
+            rect: rect,
+            bar: bar,
+            focused: self.focus == Some(id),
+        });
+        *value != old
+    }
+
+    fn draw_focus(&self, ctx: &mut Context, rect: Rect) -> GameResult<()> {
+        let w = self.style.line_width * 2.0;
+        graphics::set_color(ctx, self.style.focus)?;
+        graphics::rectangle(
+            ctx,
+            DrawMode::Line(w),
+            Rect::new(rect.x - w, rect.y - w, rect.w + 2.0 * w, rect.h + 2.0 * w),
+        )
+    }
+
+    fn draw_text(
+        &self,
+        ctx: &mut Context,
+        text: &mut TextCache,
+        rect: Rect,
+        s: &str,
+        color: Color,
+    ) -> GameResult<()> {
+        graphics::set_color(ctx, color)?;
+        text.draw_centered(
+            ctx,
+            s,
+            rect.x + rect.w / 2.0,
+            rect.y + (rect.h - text.height()) / 2.0,
+        )
+    }
+
+    pub fn draw(&self, ctx: &mut Context, text: &mut TextCache) -> GameResult<()> {
+        let style = &self.style;
+        for c in self.commands.iter() {
+            match *c {
+                Command::Panel(rect) => {
+                    graphics::set_color(ctx, style.panel)?;
+                    graphics::rectangle(ctx, DrawMode::Fill, rect)?;
+                    graphics::set_color(ctx, style.panel_rim)?;
+                    graphics::rectangle(ctx, DrawMode::Line(style.line_width), rect)?;
+                }
+                Command::Label {
+                    rect,
+                    text: ref s,
+                    color,
+                } => {
+                    self.draw_text(ctx, text, rect, s, color)?;
+                }
+                Command::Button {
+                    rect,
+                    text: ref s,
+                    hot,
+                    focused,
+                    enabled,
+                } => {
+                    graphics::set_color(ctx, if hot { style.button_hot } else { style.button })?;
+                    graphics::rectangle(ctx, DrawMode::Fill, rect)?;
+                    if focused {
+                        self.draw_focus(ctx, rect)?;
+                    }
+                    self.draw_text(
+                        ctx,
+                        text,
+                        rect,
+                        s,
+                        if enabled { style.text } else { style.disabled },
+                    )?;
+                }
+                Command::Toggle {
+                    rect,
+                    text: ref s,
+                    on,
+                    hot,
+                    focused,
+                    enabled,
+                } => {
+                    graphics::set_color(ctx, if hot { style.button_hot } else { style.button })?;
+                    graphics::rectangle(ctx, DrawMode::Fill, rect)?;
+                    let size = rect.h * 0.6;
+                    let check = Rect::new(
+                        rect.x + rect.w - rect.h * 0.8,
+                        rect.y + rect.h * 0.2,
+                        size,
+                        size,
+                    );
+                    graphics::set_color(ctx, style.fill)?;
+                    let mode = if on {
+                        DrawMode::Fill
+                    } else {
+                        DrawMode::Line(style.line_width)
+                    };
+                    graphics::rectangle(ctx, mode, check)?;
+                    if focused {
+                        self.draw_focus(ctx, rect)?;
+                    }
+                    let label = Rect::new(rect.x, rect.y, rect.w - rect.h, rect.h);
+                    self.draw_text(
+                        ctx,
+                        text,
+                        label,
+                        s,
+                        if enabled { style.text } else { style.disabled },
+                    )?;
+                }
+                Command::Slider {
+                    rect,
+                    ref bar,
+                    focused,
+                } => {
+                    bar.draw_with_label(ctx, text)?;
+                    if focused {
+                        self.draw_focus(ctx, rect)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame<F: FnMut(&mut Ui)>(ui: &mut Ui, mut f: F) {
+        ui.begin();
+        f(ui);
+        ui.end();
+    }
+
+    #[test]
+    fn anchors_and_stacks() {
+        let area = Rect::new(0.0, 0.0, 400.0, 600.0);
+        assert_eq!(
+            Anchor::Center.place(area, 100.0, 50.0, 10.0),
+            Rect::new(150.0, 275.0, 100.0, 50.0)
+        );
+        assert_eq!(
+            Anchor::BottomRight.place(area, 100.0, 50.0, 10.0),
+            Rect::new(290.0, 540.0, 100.0, 50.0)
+        );
+        let mut stack = Stack::anchored(area, Anchor::Center, 200.0, 3, 40.0, 10.0);
+        assert_eq!(stack.next(40.0), Rect::new(100.0, 230.0, 200.0, 40.0));
+        assert_eq!(stack.next(40.0).y, 280.0);
+        let cols = stack.columns(40.0, 2);
+        assert_eq!((cols[0].w, cols[1].x), (95.0, 205.0));
+        assert_eq!(stack.bottom(), 370.0);
+    }
+
+    #[test]
+    fn buttons_answer_mouse_and_keyboard() {
+        let mut ui = Ui::new(UiStyle::default());
+        let (a, b) = (
+            Rect::new(0.0, 0.0, 100.0, 20.0),
+            Rect::new(0.0, 30.0, 100.0, 20.0),
+        );
+        let mut hits = vec![];
+        frame(&mut ui, |ui| {
+            ui.button(a, "a");
+            ui.button(b, "b");
+        });
+
+        ui.mouse_down(Point2::new(10.0, 35.0));
+        ui.mouse_up(Point2::new(10.0, 35.0));
+        frame(&mut ui, |ui| {
+            hits.push((ui.button(a, "a"), ui.button(b, "b")))
+        });
+        assert_eq!(hits.pop(), Some((false, true)));
+        assert_eq!(ui.focus(), Some(1));
+
+        ui.key(UiKey::Next);
+        ui.key(UiKey::Activate);
+        frame(&mut ui, |ui| {
+            hits.push((ui.button(a, "a"), ui.button(b, "b")))
+        });
+        assert_eq!(hits.pop(), Some((true, false)));
+
+        ui.key(UiKey::Prev);
+        ui.key(UiKey::Activate);
+        frame(&mut ui, |ui| {
+            ui.button(a, "a");
+            ui.enabled(false);
+            hits.push((false, ui.button(b, "b")));
+        });
+        assert_eq!(hits.pop(), Some((false, false)));
+    }
+
+    #[test]
+    fn toggles_and_sliders() {
+        let mut ui = Ui::new(UiStyle::default());
+        let row = Rect::new(0.0, 0.0, 100.0, 20.0);
+        let (mut on, mut volume) = (false, 0.5);
+        frame(&mut ui, |ui| {
+            ui.toggle(row, "t", &mut on);
+        });
+        ui.key(UiKey::Next);
+        ui.key(UiKey::Activate);
+        frame(&mut ui, |ui| {
+            ui.toggle(row, "t", &mut on);
+        });
+        assert!(on);
+
+        ui.mouse_down(Point2::new(25.0, 10.0));
+        frame(&mut ui, |ui| {
+            ui.slider(row, "v", &mut volume, 0.0, 1.0, 0.05);
+        });
+        assert!((volume - 0.25).abs() < 1e-6);
+        ui.mouse_motion(Point2::new(500.0, 10.0));
+        frame(&mut ui, |ui| {
+            ui.slider(row, "v", &mut volume, 0.0, 1.0, 0.05);
+        });
+        assert_eq!(volume, 1.0);
+        ui.mouse_up(Point2::new(500.0, 10.0));
+        ui.key(UiKey::Decrease);
+        frame(&mut ui, |ui| {
+            ui.slider(row, "v", &mut volume, 0.0, 1.0, 0.25);
+        });
+        assert_eq!(volume, 0.75);
+    }
+}
@@ -0,0 +1,112 @@
+use ggez::graphics::{self, Point2, Rect};
+use ggez::{Context, GameResult};
+
+pub const WORLD_WIDTH: f32 = 400.0;
+pub const WORLD_HEIGHT: f32 = 600.0;
+
+// Maps the fixed logical world onto the window, scaled uniformly and
+// centred, so the game looks the same at any window size.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct View {
+    world: (f32, f32),
+    screen: (f32, f32),
+    // drawable pixels per window point, above 1 on HiDPI displays
+    hidpi: f32,
+}
+
+impl View {
+    pub fn new(world: (f32, f32), screen: (f32, f32)) -> View {
+        View {
+            world: world,
+            screen: screen,
+            hidpi: 1.0,
+        }
+    }
+
+    pub fn set_hidpi(&mut self, factor: f32) {
+        self.hidpi = factor.max(1.0);
+    }
+
+    // reads the window and framebuffer sizes straight from the context
+    pub fn sync(&mut self, ctx: &Context) {
+        let (w, h) = graphics::get_size(ctx);
+        let (dw, _) = graphics::get_drawable_size(ctx);
+        self.resize(w as f32, h as f32);
+        self.set_hidpi(dw as f32 / (w.max(1)) as f32);
+    }
+
+    pub fn resize(&mut self, width: f32, height: f32) {
+        self.screen = (width.max(1.0), height.max(1.0));
+    }
+
+    pub fn world_size(&self) -> (f32, f32) {
+        self.world
+    }
+
+    pub fn screen_size(&self) -> (f32, f32) {
+        self.screen
+    }
+
+    pub fn scale(&self) -> f32 {
+        (self.screen.0 / self.world.0).min(self.screen.1 / self.world.1)
+    }
+
+    // real pixels covered by one world unit
+    pub fn pixels_per_unit(&self) -> f32 {
+        self.scale() * self.hidpi
+    }
+
+    // the part of world space that covers the whole window, margins included
+    pub fn visible(&self) -> Rect {
+        let scale = self.scale();
+        let (w, h) = (self.screen.0 / scale, self.screen.1 / scale);
+        Rect::new((self.world.0 - w) / 2.0, (self.world.1 - h) / 2.0, w, h)
+    }
+
+    pub fn to_world(&self, x: f32, y: f32) -> Point2 {
+        let visible = self.visible();
+        let scale = self.scale();
+        Point2::new(visible.x + x / scale, visible.y + y / scale)
+    }
+
+    pub fn to_screen(&self, p: Point2) -> (f32, f32) {
+        let visible = self.visible();
+        let scale = self.scale();
+        ((p.x - visible.x) * scale, (p.y - visible.y) * scale)
+    }
+
+    pub fn apply(&self, ctx: &mut Context) -> GameResult<()> {
+        graphics::set_screen_coordinates(ctx, self.visible())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn letterboxes_wide_windows() {
+        let view = View::new((400.0, 600.0), (1600.0, 1200.0));
+        assert_eq!(view.scale(), 2.0);
+        assert_eq!(view.visible(), Rect::new(-200.0, 0.0, 800.0, 600.0));
+        assert_eq!(view.to_world(800.0, 1200.0), Point2::new(200.0, 600.0));
+    }
+
+    #[test]
+    fn round_trips_points() {
+        let mut view = View::new((400.0, 600.0), (400.0, 600.0));
+        view.resize(300.0, 900.0);
+        let p = view.to_world(150.0, 450.0);
+        assert_eq!(p, Point2::new(200.0, 300.0));
+        assert_eq!(view.to_screen(p), (150.0, 450.0));
+    }
+
+    #[test]
+    fn hidpi_only_changes_pixel_density() {
+        let mut view = View::new((400.0, 600.0), (800.0, 1200.0));
+        view.set_hidpi(2.0);
+        assert_eq!(view.scale(), 2.0);
+        assert_eq!(view.pixels_per_unit(), 4.0);
+        assert_eq!(view.to_world(400.0, 600.0), Point2::new(200.0, 300.0));
+    }
+}